@@ -19,7 +19,7 @@ lazy_static! {
     /// 2. create filemap
     pub static ref FILES: Files = {
         let map = include_str!("./cache_buster_data.json");
-        Files::new(map)
+        Files::try_new(map).unwrap()
     };
     pub static ref INDEX: String = index::get_index();
 }
@@ -80,7 +80,7 @@ pub async fn static_files(path: web::Path<String>) -> impl Responder {
 
 #[get("/")]
 pub async fn serve_index() -> impl Responder {
-    let index: &str = &*INDEX;
+    let index: &str = &INDEX;
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
         .body(index)
@@ -0,0 +1,16 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+// Kept free of other items of the crate: tests compile it on its own, as
+// the dependency of generated `Asset` enums.
+
+/// Asset of the `Asset` enum generated with
+/// [BusterBuilder::codegen][crate::BusterBuilder::codegen], looked up with [Files::get_asset][crate::Files::get_asset]. Only the
+/// generated enum implements it, so lookups can't be passed arbitrary strings
+pub trait Asset {
+    /// Path of the file before processing, its filemap key
+    fn source(&self) -> &'static str;
+}
@@ -0,0 +1,101 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Digests of source files persisted between builds, see
+//! [BusterBuilder::cache][crate::BusterBuilder::cache]
+use std::collections::BTreeMap;
+use std::fs::{self, Metadata};
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::naming::Digests;
+
+/// Size, modification time and digests of source files, keyed by path in
+/// source
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct BuildCache {
+    /// chunk size digests were computed with
+    chunk_size: Option<u64>,
+    files: BTreeMap<PathBuf, CachedFile>,
+}
+
+/// A source file as of the last build
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedFile {
+    size: u64,
+    modified: SystemTime,
+    /// None for files that weren't read
+    pub(crate) digests: Option<Digests>,
+    /// destination relative to result
+    pub(crate) destination: PathBuf,
+}
+
+impl BuildCache {
+    /// Empty cache of digests computed with chunk_size
+    pub(crate) fn new(chunk_size: Option<u64>) -> Self {
+        Self {
+            chunk_size,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Load cache from path. Missing and unreadable caches, along with those
+    /// computed with a different chunk size, are empty
+    pub(crate) fn load(path: &str, chunk_size: Option<u64>) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|cache| serde_json::from_slice::<Self>(&cache).ok())
+            .filter(|cache| cache.chunk_size == chunk_size)
+            .unwrap_or_else(|| Self::new(chunk_size))
+    }
+
+    /// Write cache to path, creating missing parent directories
+    pub(crate) fn write(&self, path: &str) -> Result<(), Error> {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec(self)?)
+    }
+
+    /// Cached file at path, if it hasn't changed since. Files that weren't
+    /// read are only returned when `digests` aren't needed
+    pub(crate) fn get(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        digests: bool,
+    ) -> Option<&CachedFile> {
+        let modified = metadata.modified().ok()?;
+        self.files.get(path).filter(|cached| {
+            cached.size == metadata.len()
+                && cached.modified == modified
+                && (cached.digests.is_some() || !digests)
+        })
+    }
+
+    /// Record file at path
+    pub(crate) fn insert(
+        &mut self,
+        path: PathBuf,
+        size: u64,
+        modified: SystemTime,
+        digests: Option<Digests>,
+        destination: PathBuf,
+    ) {
+        self.files.insert(
+            path,
+            CachedFile {
+                size,
+                modified,
+                digests,
+                destination,
+            },
+        );
+    }
+}
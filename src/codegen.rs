@@ -0,0 +1,367 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Generates Rust source with a typed `Asset` enum, one variant per processed
+//! file, so that asset references are checked by the compiler, and with the
+//! filemap as a static map, so that it's read without parsing JSON. Also
+//! generates TypeScript and JavaScript modules with the filemap, for code
+//! running in the browser.
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Processed file described by a variant of the `Asset` enum
+#[derive(Debug, Clone)]
+pub(crate) struct Asset {
+    /// path relative to source
+    pub(crate) rel: String,
+    /// original path, the filemap key
+    pub(crate) source: String,
+    /// modified path
+    pub(crate) path: String,
+    /// URL path, like `Files::get_url`
+    pub(crate) url: String,
+}
+
+/// Generate source of the `Asset` enum
+pub(crate) fn asset_enum(assets: &[Asset]) -> String {
+    let mut assets: Vec<&Asset> = assets.iter().collect();
+    assets.sort_by(|a, b| a.rel.cmp(&b.rel));
+
+    let mut seen = HashSet::new();
+    let variants: Vec<String> = assets
+        .iter()
+        .map(|asset| {
+            let name = variant_name(&asset.rel);
+            let mut unique = name.clone();
+            let mut count = 2;
+            while !seen.insert(unique.clone()) {
+                unique = format!("{}{}", name, count);
+                count += 1;
+            }
+            unique
+        })
+        .collect();
+
+    let mut src = String::new();
+    src.push_str("// @generated by cache-buster. Do not edit.\n\n");
+    src.push_str("/// Assets processed by cache-buster\n");
+    src.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]\n");
+    src.push_str("pub enum Asset {\n");
+    for (asset, variant) in assets.iter().zip(variants.iter()) {
+        src.push_str(&format!("    /// `{}`\n    {},\n", asset.source, variant));
+    }
+    src.push_str("}\n\n");
+
+    src.push_str("impl Asset {\n");
+    src.push_str("    /// All processed assets\n");
+    src.push_str("    pub const ALL: &'static [Asset] = &[\n");
+    for variant in variants.iter() {
+        src.push_str(&format!("        Asset::{},\n", variant));
+    }
+    src.push_str("    ];\n\n");
+
+    let accessor = |src: &mut String, doc: &str, name: &str, value: &dyn Fn(usize) -> String| {
+        src.push_str(&format!("    /// {}\n", doc));
+        src.push_str(&format!(
+            "    pub const fn {}(&self) -> &'static str {{\n",
+            name
+        ));
+        // matching on *self compiles without variants, unlike on self
+        src.push_str("        match *self {\n");
+        for (i, variant) in variants.iter().enumerate() {
+            src.push_str(&format!(
+                "            Asset::{} => {:?},\n",
+                variant,
+                value(i)
+            ));
+        }
+        src.push_str("        }\n    }\n");
+    };
+    accessor(
+        &mut src,
+        "Path of the file after processing, same as `Files::get_full_path`",
+        "path",
+        &|i| assets[i].path.clone(),
+    );
+    src.push('\n');
+    accessor(
+        &mut src,
+        "URL path of the file after processing, same as `Files::get_url`",
+        "url",
+        &|i| assets[i].url.clone(),
+    );
+    src.push('\n');
+    accessor(
+        &mut src,
+        "Path of the file before processing",
+        "source",
+        &|i| assets[i].source.clone(),
+    );
+    src.push_str("}\n\n");
+
+    // lets Files::get_asset take assets, and only assets
+    src.push_str("impl ::cache_buster::Asset for Asset {\n");
+    src.push_str("    fn source(&self) -> &'static str {\n");
+    src.push_str("        Asset::source(self)\n");
+    src.push_str("    }\n");
+    src.push_str("}\n\n");
+
+    // lets the other getters take assets
+    src.push_str("impl AsRef<str> for Asset {\n");
+    src.push_str("    fn as_ref(&self) -> &str {\n");
+    src.push_str("        self.source()\n");
+    src.push_str("    }\n");
+    src.push_str("}\n");
+    src
+}
+
+/// Generate source of the static filemap, a `phf` map, and its lookup
+/// functions.
+///
+/// `entries` is a list of `(original path, modified path)`, `base_dir` is
+/// stripped from modified paths by `get`
+pub(crate) fn static_map(base_dir: &str, entries: &[(String, String)]) -> String {
+    // phf hashes with a fixed seed, the same entries in the same order give
+    // the same source
+    let mut entries: Vec<&(String, String)> = entries.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut map = phf_codegen::Map::new();
+    for (source, path) in entries {
+        map.entry(source.as_str(), &format!("{:?}", path));
+    }
+
+    let mut src = String::new();
+    src.push_str("// @generated by cache-buster. Do not edit.\n\n");
+    src.push_str("/// Paths after processing, keyed by original path\n");
+    src.push_str(&format!(
+        "pub static FILEMAP: ::phf::Map<&'static str, &'static str> = {};\n\n",
+        map.build()
+    ));
+
+    src.push_str("/// Path of the file after processing, same as `Files::get_full_path`\n");
+    src.push_str("pub fn get_full_path(path: &str) -> Option<&'static str> {\n");
+    src.push_str("    FILEMAP.get(path).copied()\n");
+    src.push_str("}\n\n");
+
+    src.push_str("/// Path of the file relative to result, same as `Files::get`\n");
+    src.push_str("pub fn get(path: &str) -> Option<&'static str> {\n");
+    src.push_str(&format!(
+        "    get_full_path(path).map(|path| &path[{}..])\n",
+        base_dir.len()
+    ));
+    src.push_str("}\n");
+    src
+}
+
+/// Generate source of a TypeScript module, or an ES module when `typed` is
+/// false, exporting the filemap and a lookup function.
+///
+/// `entries` is a list of `(original path, URL path)`
+pub(crate) fn js_module(entries: &[(String, String)], typed: bool) -> String {
+    let mut entries: Vec<&(String, String)> = entries.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    // JSON strings are valid JavaScript string literals
+    let quoted = |s: &str| serde_json::to_string(s).unwrap();
+
+    let mut src = String::new();
+    src.push_str("// @generated by cache-buster. Do not edit.\n\n");
+    if typed {
+        src.push_str("/** Original paths of processed assets */\n");
+        src.push_str("export type AssetPath =");
+        if entries.is_empty() {
+            src.push_str(" never");
+        }
+        for (source, _) in entries.iter() {
+            src.push_str(&format!("\n  | {}", quoted(source)));
+        }
+        src.push_str(";\n\n");
+    }
+    src.push_str("/** URL paths of assets after processing, keyed by original path */\n");
+    if typed {
+        src.push_str("export const manifest: Readonly<Record<AssetPath, string>> = {\n");
+    } else {
+        src.push_str("export const manifest = Object.freeze({\n");
+    }
+    for (source, url) in entries.iter() {
+        src.push_str(&format!("  {}: {},\n", quoted(source), quoted(url)));
+    }
+    src.push_str(if typed { "};\n\n" } else { "});\n\n" });
+
+    src.push_str("/** URL path of an asset after processing */\n");
+    if typed {
+        src.push_str("export function assetUrl(path: AssetPath): string {\n");
+    } else {
+        src.push_str("export function assetUrl(path) {\n");
+    }
+    src.push_str("  return manifest[path];\n");
+    src.push_str("}\n");
+    src
+}
+
+/// Convert a path into a CamelCase identifier: `img/logo.png` becomes `ImgLogoPng`
+fn variant_name(rel: &str) -> String {
+    let mut name = String::new();
+    for part in Path::new(rel)
+        .to_str()
+        .unwrap()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+    {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            name.push(first.to_ascii_uppercase());
+            name.extend(chars);
+        }
+    }
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert_str(0, "Asset");
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variant_name_works() {
+        assert_eq!(variant_name("img/logo.png"), "ImgLogoPng");
+        assert_eq!(variant_name("css/main-page.min.css"), "CssMainPageMinCss");
+        assert_eq!(variant_name("1.svg"), "Asset1Svg");
+        assert_eq!(variant_name("日本.svg"), "Svg");
+    }
+
+    #[test]
+    fn asset_enum_works() {
+        let asset = |rel: &str, hash: &str| {
+            let hashed = rel.replace(".png", &format!(".{}.png", hash));
+            Asset {
+                rel: rel.into(),
+                source: format!("./dist/{}", rel),
+                path: format!("./prod/{}", hashed),
+                url: format!("/static/{}", hashed),
+            }
+        };
+        let assets = vec![asset("img/a-b.png", "1234"), asset("img/a_b.png", "5678")];
+        let src = asset_enum(&assets);
+        assert!(src.contains("    ImgABPng,\n"));
+        assert!(src.contains("    ImgABPng2,\n"));
+        assert!(src.contains("Asset::ImgABPng => \"./prod/img/a-b.1234.png\","));
+        assert!(src.contains("Asset::ImgABPng => \"/static/img/a-b.1234.png\","));
+        assert!(src.contains("Asset::ImgABPng2 => \"./dist/img/a_b.png\","));
+        assert!(src.contains("impl ::cache_buster::Asset for Asset {"));
+        assert!(src.contains("impl AsRef<str> for Asset {"));
+        compiles("asset-enum", &src);
+        compiles("asset-enum-empty", &asset_enum(&[]));
+    }
+
+    // compile generated source as a library, failing on warnings. The
+    // generated filemap links against phf, a dev-dependency built next to
+    // the test binary, and the Asset enum against the Asset trait, compiled
+    // on its own
+    fn compiles(name: &str, src: &str) {
+        let dir = std::env::temp_dir().join("cache-buster-codegen").join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("generated.rs");
+        std::fs::write(&file, src).unwrap();
+        let runtime = dir.join("cache_buster.rs");
+        std::fs::write(&runtime, include_str!("asset.rs")).unwrap();
+        let deps = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .to_owned();
+        let phf = std::fs::read_dir(&deps)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                let name = path.file_name().unwrap().to_str().unwrap();
+                name.starts_with("libphf-") && name.ends_with(".rlib")
+            })
+            .unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+        let output = std::process::Command::new(&rustc)
+            .args(["--crate-type", "lib", "--edition", "2021"])
+            .arg("--out-dir")
+            .arg(&dir)
+            .arg(&runtime)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let output = std::process::Command::new(rustc)
+            .args(["--crate-type", "lib", "--edition", "2021", "-D", "warnings"])
+            .arg("--extern")
+            .arg(format!(
+                "cache_buster={}",
+                dir.join("libcache_buster.rlib").display()
+            ))
+            .arg("-L")
+            .arg(format!("dependency={}", deps.display()))
+            .arg("--extern")
+            .arg(format!("phf={}", phf.display()))
+            .arg("--out-dir")
+            .arg(&dir)
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn static_map_works() {
+        let entries = vec![
+            (
+                "./dist/img/b.png".to_string(),
+                "./prod/img/b.5678.png".to_string(),
+            ),
+            (
+                "./dist/img/a.png".to_string(),
+                "./prod/img/a.1234.png".to_string(),
+            ),
+        ];
+        let src = static_map("./prod", &entries);
+        assert!(src.contains("pub static FILEMAP: ::phf::Map<&'static str, &'static str> = "));
+        assert!(src.contains("(\"./dist/img/a.png\", \"./prod/img/a.1234.png\")"));
+        assert!(src.contains("(\"./dist/img/b.png\", \"./prod/img/b.5678.png\")"));
+        assert!(src.contains("get_full_path(path).map(|path| &path[6..])"));
+        compiles("static-map", &src);
+        compiles("static-map-empty", &static_map("./prod", &[]));
+    }
+
+    #[test]
+    fn js_module_works() {
+        let entries = vec![
+            (
+                "./dist/\"b\".png".to_string(),
+                "/static/\"b\".5678.png".to_string(),
+            ),
+            ("./dist/a.png".to_string(), "/static/a.1234.png".to_string()),
+        ];
+        let ts = js_module(&entries, true);
+        assert!(ts.contains(
+            "export type AssetPath =\n  | \"./dist/\\\"b\\\".png\"\n  | \"./dist/a.png\";"
+        ));
+        assert!(ts.contains("  \"./dist/a.png\": \"/static/a.1234.png\",\n"));
+        assert!(ts.contains("export function assetUrl(path: AssetPath): string {"));
+
+        let js = js_module(&entries, false);
+        assert!(!js.contains("AssetPath"));
+        assert!(js.contains("export const manifest = Object.freeze({\n"));
+        assert!(js.contains("  \"./dist/a.png\": \"/static/a.1234.png\",\n"));
+
+        assert!(js_module(&[], true).contains("export type AssetPath = never;"));
+    }
+}
@@ -0,0 +1,125 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Assets shipped by dependency crates
+//!
+//! Library crates, like a crate of UI components, can ship their own asset
+//! trees, which the application processes along with its own assets into a
+//! single result directory and filemap. Cargo passes the location of the
+//! assets from the library to the application through build script
+//! metadata, which needs a `links` key in the library's `Cargo.toml`:
+//!
+//! ```toml
+//! [package]
+//! name = "ui-components"
+//! links = "ui"
+//! ```
+//!
+//! The library exports its assets from its `build.rs`:
+//!
+//! ```no_run
+//! cache_buster::deps::export("./static");
+//! ```
+//!
+//! and the application picks up assets of all its dependencies:
+//!
+//! ```no_run
+//! use cache_buster::{deps, BusterBuilder};
+//!
+//! let config = BusterBuilder::default()
+//!     .source("./dist")
+//!     .result("./prod")
+//!     .follow_links(true)
+//!     .dependency_assets(deps::discover())
+//!     .build()
+//!     .unwrap();
+//! config.process().unwrap();
+//! ```
+//!
+//! Assets of a dependency are written to `<result>/<name>/` and recorded in
+//! the filemap as `@<name>/<path relative to the exported directory>`, so
+//! `./static/button.css` of the crate above is looked up with
+//! `files.get("@ui/button.css")`.
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// build script metadata key under which assets are exported
+const METADATA_KEY: &str = "cache_buster_assets";
+
+/// Asset tree of a dependency crate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyAssets {
+    /// name of the dependency: value of its `links` key, in lowercase and with
+    /// `-` replaced by `_` when discovered through build script metadata
+    pub name: String,
+    /// directory containing the assets
+    pub dir: PathBuf,
+}
+
+impl DependencyAssets {
+    /// Register assets in `dir` under `name` manually, without going through
+    /// build script metadata
+    pub fn new(name: impl Into<String>, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            dir: dir.into(),
+        }
+    }
+}
+
+/// Export assets in `dir` to crates depending on this one. Call from
+/// `build.rs` of a library crate with a `links` key.
+pub fn export(dir: impl AsRef<Path>) {
+    let dir = dir.as_ref();
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    println!("cargo:rerun-if-changed={}", dir.display());
+    println!("cargo:{}={}", METADATA_KEY, dir.display());
+}
+
+/// Discover assets exported with [export] by direct dependencies. Call from
+/// `build.rs` of the application. Results are sorted by name.
+pub fn discover() -> Vec<DependencyAssets> {
+    from_vars(env::vars())
+}
+
+// pick exported assets out of environment variables
+fn from_vars(vars: impl Iterator<Item = (String, String)>) -> Vec<DependencyAssets> {
+    let suffix = format!("_{}", METADATA_KEY.to_uppercase());
+    let mut deps: Vec<DependencyAssets> = vars
+        .filter_map(|(key, value)| {
+            let name = key.strip_prefix("DEP_")?.strip_suffix(&suffix)?;
+            Some(DependencyAssets::new(name.to_lowercase(), value))
+        })
+        .collect();
+    deps.sort_by(|a, b| a.name.cmp(&b.name));
+    deps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_vars_works() {
+        let vars = vec![
+            ("DEP_UI_KIT_CACHE_BUSTER_ASSETS", "/ui-kit/static"),
+            ("DEP_OPENSSL_INCLUDE", "/usr/include"),
+            ("CACHE_BUSTER_ASSETS", "/nope"),
+            ("DEP_ICONS_CACHE_BUSTER_ASSETS", "/icons"),
+        ];
+        let deps = from_vars(
+            vars.into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string())),
+        );
+        assert_eq!(
+            deps,
+            vec![
+                DependencyAssets::new("icons", "/icons"),
+                DependencyAssets::new("ui_kit", "/ui-kit/static"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Errors of the build-time APIs
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+/// Why processing, or another build-time operation, failed
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum CacheBusterError {
+    /// reading or writing a file failed
+    #[error("{}: {source}", path.display())]
+    File {
+        /// file that couldn't be read or written
+        path: PathBuf,
+        /// underlying error
+        source: io::Error,
+    },
+    /// walking a source directory failed
+    #[error(transparent)]
+    Walk(walkdir::Error),
+    /// a symlink in source points at a directory containing it, so
+    /// [follow_links][crate::BusterBuilder::follow_links] would follow it
+    /// forever
+    #[error("symlink {} loops back to {}", link.display(), target.display())]
+    SymlinkLoop {
+        /// the offending symlink
+        link: PathBuf,
+        /// directory it points at
+        target: PathBuf,
+    },
+    /// result directory can't be created or written to. Checked before
+    /// processing starts
+    #[error("result directory {} isn't writable: {source}", path.display())]
+    ResultNotWritable {
+        /// result directory
+        path: PathBuf,
+        /// underlying error
+        source: io::Error,
+    },
+    /// a file in source resolves outside of it through a symlink, see
+    /// [external_links][crate::BusterBuilder::external_links]
+    #[error("{} resolves outside of source, to {}", path.display(), target.display())]
+    ExternalLink {
+        /// path of the file in source
+        path: PathBuf,
+        /// file it resolves to
+        target: PathBuf,
+    },
+    /// a source directory is empty or missing, with
+    /// [empty_source][crate::BusterBuilder::empty_source] set to
+    /// [EmptySource::Error][crate::EmptySource::Error]
+    #[error("source {} is empty or missing", .0.display())]
+    EmptySource(PathBuf),
+    /// two walked files map to the same filemap key, which usually means
+    /// overlapping [sources][crate::BusterBuilder::sources]
+    #[error("{0} is picked up more than once, do source directories overlap?")]
+    DuplicateKey(String),
+    /// MIME type of a file couldn't be resolved, and
+    /// [mime_types][crate::BusterBuilder::mime_types] is set without an
+    /// [unknown_mime][crate::BusterBuilder::unknown_mime] policy
+    #[error("couldn't resolve MIME type of {}", .0.display())]
+    UnknownMime(PathBuf),
+    /// path of a file isn't valid UTF-8, so it can't be recorded in the
+    /// filemap
+    #[error("{} isn't valid UTF-8", .0.display())]
+    NonUtf8Path(PathBuf),
+    /// filemap, or another JSON file, couldn't be read or written
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    /// any other failure, like conflicting files or a failing
+    /// [AssetTransform][crate::AssetTransform]
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl CacheBusterError {
+    /// Kind of I/O error this corresponds to, for matching on broad classes
+    /// of errors
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::File { source, .. } | Self::ResultNotWritable { source, .. } => source.kind(),
+            Self::Walk(e) => e.io_error().map_or(ErrorKind::Other, io::Error::kind),
+            Self::SymlinkLoop { .. } => ErrorKind::InvalidInput,
+            Self::ExternalLink { .. } => ErrorKind::PermissionDenied,
+            Self::DuplicateKey(_) => ErrorKind::AlreadyExists,
+            Self::EmptySource(_) => ErrorKind::NotFound,
+            Self::UnknownMime(_) | Self::NonUtf8Path(_) | Self::Json(_) => ErrorKind::InvalidData,
+            Self::Io(e) => e.kind(),
+        }
+    }
+
+    // wrap io::Error with path, for internals returning io::Error
+    pub(crate) fn file(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> io::Error {
+        let path = path.into();
+        move |source| io::Error::new(source.kind(), Self::File { path, source })
+    }
+}
+
+// internals return io::Error, with errors of this crate, walkdir and
+// serde_json as inner errors. Those are unwrapped into their variants
+impl From<io::Error> for CacheBusterError {
+    fn from(e: io::Error) -> Self {
+        if !e.get_ref().is_some_and(|inner| {
+            inner.is::<Self>() || inner.is::<walkdir::Error>() || inner.is::<serde_json::Error>()
+        }) {
+            return Self::Io(e);
+        }
+        let inner = e.into_inner().unwrap();
+        let inner = match inner.downcast::<Self>() {
+            Ok(e) => return *e,
+            Err(inner) => inner,
+        };
+        match inner.downcast::<walkdir::Error>() {
+            Ok(e) => Self::from(*e),
+            Err(inner) => Self::Json(*inner.downcast::<serde_json::Error>().unwrap()),
+        }
+    }
+}
+
+impl From<walkdir::Error> for CacheBusterError {
+    fn from(e: walkdir::Error) -> Self {
+        match (e.path(), e.loop_ancestor()) {
+            (Some(link), Some(target)) => Self::SymlinkLoop {
+                link: link.to_path_buf(),
+                target: target.to_path_buf(),
+            },
+            _ => Self::Walk(e),
+        }
+    }
+}
+
+impl From<CacheBusterError> for io::Error {
+    fn from(e: CacheBusterError) -> Self {
+        match e {
+            CacheBusterError::Io(e) => e,
+            e => io::Error::new(e.kind(), e),
+        }
+    }
+}
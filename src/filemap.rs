@@ -12,27 +12,448 @@
 //! use cache_buster::Files;
 //! use cache_buster::CACHE_BUSTER_DATA_FILE;
 //!
-//! let files = Files::new(CACHE_BUSTER_DATA_FILE);
+//! let map = std::fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap();
+//! let files = Files::try_new(&map).unwrap();
 //! ```
-use std::collections::HashMap;
+//!
+//! Lookups hash the requested path with SipHash, which resists collision
+//! attacks on maps keyed by untrusted input. Filemap keys are known at build
+//! time, so services doing a lookup per request can enable the `fxhash`
+//! feature for the faster FxHash instead.
+//!
+//! Filemaps record the [SCHEMA_VERSION] of their layout. Older layouts are
+//! read transparently, loading a filemap written by a newer version of this
+//! crate fails with a message naming both versions.
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::{error, fmt, io, str};
 
+use serde::de::{Deserializer, Visitor};
 use serde::{Deserialize, Serialize};
 
+// hasher of runtime lookups, see the module documentation
+#[cfg(feature = "fxhash")]
+type Hasher = rustc_hash::FxBuildHasher;
+#[cfg(not(feature = "fxhash"))]
+type Hasher = std::collections::hash_map::RandomState;
+
+/// Version of the filemap layout written by this crate. Filemaps without
+/// a version are version 1, which stores plain paths or records as entries
+pub const SCHEMA_VERSION: u32 = 2;
+
+// version of filemaps written before versions were recorded
+pub(crate) fn legacy_schema_version() -> u32 {
+    1
+}
+
+/// Leading bytes of CBOR filemaps, the CBOR self-described tag. Tells them
+/// apart from JSON and compressed filemaps in [Files::try_from_bytes]
+pub(crate) const CBOR_MAGIC: &[u8] = &[0xd9, 0xd9, 0xf7];
+
+// reads just the version of a filemap
+#[derive(Deserialize)]
+struct SchemaProbe {
+    #[serde(default = "legacy_schema_version")]
+    schema_version: u32,
+}
+
+// fail when map is laid out by a newer version of this crate, instead of
+// failing with a serde error on its first unknown field
+fn check_schema_version(map: &str) -> Result<(), FileMapError> {
+    let version = serde_json::from_str::<SchemaProbe>(map)
+        .map_or_else(|_| legacy_schema_version(), |probe| probe.schema_version);
+    check_version(version)
+}
+
+fn check_version(version: u32) -> Result<(), FileMapError> {
+    if version > SCHEMA_VERSION {
+        return Err(FileMapError::UnsupportedSchema {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    Ok(())
+}
+
+/// Why a filemap couldn't be loaded, see [Files::try_new]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FileMapError {
+    /// filemap isn't valid JSON, or isn't laid out like a filemap
+    Json(serde_json::Error),
+    /// filemap was written by a newer version of this crate
+    UnsupportedSchema {
+        /// schema version of the filemap
+        found: u32,
+        /// newest schema version this crate reads, [SCHEMA_VERSION]
+        supported: u32,
+    },
+    /// filemap isn't valid UTF-8
+    NotUtf8(str::Utf8Error),
+    /// compressed filemap couldn't be decompressed
+    Decompress(io::Error),
+    /// filemap is compressed or in a binary format, but the feature to read
+    /// it is disabled
+    FeatureDisabled(&'static str),
+    /// filemap isn't valid CBOR, or isn't laid out like a filemap
+    #[cfg(feature = "cbor")]
+    Cbor(ciborium::de::Error<io::Error>),
+}
+
+impl fmt::Display for FileMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "invalid filemap: {}", e),
+            Self::UnsupportedSchema { found, supported } => write!(
+                f,
+                "filemap has schema version {}, but this version of cache-buster reads up to {}. \
+                 Process and load filemaps with the same version of cache-buster",
+                found, supported
+            ),
+            Self::NotUtf8(e) => write!(f, "filemap isn't valid UTF-8: {}", e),
+            Self::Decompress(e) => write!(f, "couldn't decompress filemap: {}", e),
+            Self::FeatureDisabled(feature) => write!(
+                f,
+                "reading this filemap needs the `{}` feature of cache-buster",
+                feature
+            ),
+            #[cfg(feature = "cbor")]
+            Self::Cbor(e) => write!(f, "invalid CBOR filemap: {}", e),
+        }
+    }
+}
+
+impl error::Error for FileMapError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Json(e) => Some(e),
+            Self::NotUtf8(e) => Some(e),
+            Self::Decompress(e) => Some(e),
+            Self::UnsupportedSchema { .. } | Self::FeatureDisabled(_) => None,
+            #[cfg(feature = "cbor")]
+            Self::Cbor(e) => Some(e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for FileMapError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<str::Utf8Error> for FileMapError {
+    fn from(e: str::Utf8Error) -> Self {
+        Self::NotUtf8(e)
+    }
+}
+
 /// Filemap struct
 ///
 /// maps original names to generated names
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Files {
-    /// filemap<original-path, modified-path>
-    map: HashMap<String, String>,
-    base_dir: String,
+    /// layout version, see [SCHEMA_VERSION]
+    #[serde(default = "legacy_schema_version")]
+    pub(crate) schema_version: u32,
+    /// filemap<original-path, entry>
+    pub(crate) map: HashMap<String, Entry, Hasher>,
+    pub(crate) base_dir: String,
+    /// files skipped by the MIME filter, globs or size filters, relative to source
+    #[serde(default)]
+    pub(crate) skipped: Vec<String>,
+    /// variants<logical-path, <variant, original-path>>
+    #[serde(default)]
+    pub(crate) variants: HashMap<String, HashMap<String, String, Hasher>, Hasher>,
+    /// route base_dir is served under
+    #[serde(default)]
+    pub(crate) prefix: Option<String>,
+    /// files of previous runs kept in base_dir, newest run first
+    #[serde(default)]
+    pub(crate) generations: Vec<Vec<String>>,
+    /// original paths of critical assets, see [Files::get_preload_links]
+    #[serde(default)]
+    pub(crate) preload: Vec<String>,
+}
+
+/// Why a requested path isn't in the filemap, see [Files::explain_miss]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MissReason {
+    /// request matches a processed file, the miss is on the serving side
+    Known {
+        /// original path of the file
+        original: String,
+    },
+    /// request is for a processed file, but with an outdated or missing hash
+    StaleHash {
+        /// original path of the file
+        original: String,
+        /// URL path the file is served under now, see [Files::get_url]
+        current: String,
+    },
+    /// file name matches a processed file, but the directory or prefix doesn't
+    WrongPrefix {
+        /// original path of the file
+        original: String,
+        /// URL path the file is served under now, see [Files::get_url]
+        current: String,
+    },
+    /// file exists in source, but wasn't processed due to the MIME filter or
+    /// globs
+    NotProcessed {
+        /// path of the file, relative to source
+        source: String,
+    },
+    /// request doesn't match any file known to the filemap
+    UnknownOriginal,
+}
+
+/// Filemap entry: modified path of a file and what's known about it
+///
+/// Filemaps written by older versions of this crate store just the modified
+/// path, they deserialize into entries with every other field unset.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(from = "EntryRepr")]
+pub struct Entry {
+    /// modified path
+    pub path: String,
+    /// size in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// resolved MIME type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// SHA-256 hash of file contents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Subresource Integrity digest of file contents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    /// strong ETag, quotes included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Content-Security-Policy hash source of file contents, quotes
+    /// included, for files inlined into pages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csp_hash: Option<String>,
+    /// short key derived from file contents, for `?v=<key>` query parameters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_key: Option<String>,
+    /// hashes of fixed-size blocks, for files served with range requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<Chunks>,
+    /// modified paths of precompressed variants, keyed by `Content-Encoding`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub precompressed: BTreeMap<String, String>,
+    /// resized and re-encoded variants of raster images
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageVariant>,
+}
+
+/// Variant of a raster image, see
+/// [BusterBuilder::responsive_images][crate::BusterBuilder::responsive_images]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ImageVariant {
+    /// modified path
+    pub path: String,
+    /// width in pixels, None for variants in the size of the original
+    pub width: Option<u32>,
+    /// MIME type
+    pub mime: String,
+}
+
+/// SHA-256 hashes of consecutive fixed-size blocks of a file, see
+/// [BusterBuilder::chunk_size][crate::BusterBuilder::chunk_size]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Chunks {
+    /// block size in bytes, the last block may be shorter
+    pub size: u64,
+    /// hashes of blocks, in order
+    pub hashes: Vec<String>,
+}
+
+impl Entry {
+    /// Create entry with just the modified path
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            size: None,
+            mime: None,
+            hash: None,
+            integrity: None,
+            etag: None,
+            csp_hash: None,
+            cache_key: None,
+            chunks: None,
+            precompressed: BTreeMap::new(),
+            images: Vec::new(),
+        }
+    }
+}
+
+// accepts both plain paths and records. Only lives during deserialization,
+// so the size of records doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EntryRepr {
+    Path(String),
+    Record {
+        path: String,
+        #[serde(default)]
+        size: Option<u64>,
+        #[serde(default)]
+        mime: Option<String>,
+        #[serde(default)]
+        hash: Option<String>,
+        #[serde(default)]
+        integrity: Option<String>,
+        #[serde(default)]
+        etag: Option<String>,
+        #[serde(default)]
+        csp_hash: Option<String>,
+        #[serde(default)]
+        cache_key: Option<String>,
+        #[serde(default)]
+        chunks: Option<Chunks>,
+        #[serde(default)]
+        precompressed: BTreeMap<String, String>,
+        #[serde(default)]
+        images: Vec<ImageVariant>,
+    },
+}
+
+impl From<EntryRepr> for Entry {
+    fn from(repr: EntryRepr) -> Self {
+        match repr {
+            EntryRepr::Path(path) => Self::new(path),
+            EntryRepr::Record {
+                path,
+                size,
+                mime,
+                hash,
+                integrity,
+                etag,
+                csp_hash,
+                cache_key,
+                chunks,
+                precompressed,
+                images,
+            } => Self {
+                path,
+                size,
+                mime,
+                hash,
+                integrity,
+                etag,
+                csp_hash,
+                cache_key,
+                chunks,
+                precompressed,
+                images,
+            },
+        }
+    }
 }
 
 impl Files {
     /// Load filemap in main program. Should be called from main program
+    ///
+    /// Panics on malformed filemaps
+    #[deprecated(since = "0.3.0", note = "use Files::try_new")]
     pub fn new(map: &str) -> Self {
-        let res: Files = serde_json::from_str(map).unwrap();
-        res
+        Self::try_new(map).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Load filemap in main program, failing with a [FileMapError] on
+    /// malformed filemaps and ones written by a newer version of this crate
+    pub fn try_new(map: &str) -> Result<Self, FileMapError> {
+        check_schema_version(map)?;
+        Ok(serde_json::from_str(map)?)
+    }
+
+    /// Layout version the filemap was written with, see [SCHEMA_VERSION]
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    /// Load filemap from bytes, like ones from `include_bytes!`.
+    ///
+    /// Filemaps compressed with
+    /// [ManifestCompression][crate::processor::ManifestCompression] are
+    /// decompressed transparently, provided the matching `gzip` or `zstd`
+    /// feature is enabled. So are CBOR filemaps written with
+    /// [ManifestFormat::Cbor][crate::processor::ManifestFormat], which are
+    /// smaller and faster to parse, with the `cbor` feature.
+    ///
+    /// ```ignore
+    /// use cache_buster::Files;
+    ///
+    /// let files = Files::try_from_bytes(include_bytes!("./cache_buster_data.json.gz")).unwrap();
+    /// ```
+    ///
+    /// Panics on malformed filemaps
+    #[deprecated(since = "0.3.0", note = "use Files::try_from_bytes")]
+    pub fn from_bytes(map: &[u8]) -> Self {
+        Self::try_from_bytes(map).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Load filemap from bytes, see [from_bytes][Self::from_bytes], failing
+    /// with a [FileMapError] like [try_new][Self::try_new]
+    pub fn try_from_bytes(map: &[u8]) -> Result<Self, FileMapError> {
+        const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+        const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+        if map.starts_with(GZIP_MAGIC) {
+            Self::from_gzip(map)
+        } else if map.starts_with(ZSTD_MAGIC) {
+            Self::from_zstd(map)
+        } else if let Some(map) = map.strip_prefix(CBOR_MAGIC) {
+            Self::from_cbor(map)
+        } else {
+            Self::try_new(str::from_utf8(map)?)
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    fn from_gzip(map: &[u8]) -> Result<Self, FileMapError> {
+        use std::io::Read;
+
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(map)
+            .read_to_string(&mut json)
+            .map_err(FileMapError::Decompress)?;
+        Self::try_new(&json)
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    fn from_gzip(_map: &[u8]) -> Result<Self, FileMapError> {
+        Err(FileMapError::FeatureDisabled("gzip"))
+    }
+
+    #[cfg(feature = "zstd")]
+    fn from_zstd(map: &[u8]) -> Result<Self, FileMapError> {
+        let json = zstd::decode_all(map).map_err(FileMapError::Decompress)?;
+        Self::try_new(str::from_utf8(&json)?)
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn from_zstd(_map: &[u8]) -> Result<Self, FileMapError> {
+        Err(FileMapError::FeatureDisabled("zstd"))
+    }
+
+    #[cfg(feature = "cbor")]
+    fn from_cbor(map: &[u8]) -> Result<Self, FileMapError> {
+        // an unreadable version is left for the full parse to report
+        if let Ok(probe) = ciborium::from_reader::<SchemaProbe, _>(map) {
+            check_version(probe.schema_version)?;
+        }
+        ciborium::from_reader(map).map_err(FileMapError::Cbor)
+    }
+
+    #[cfg(not(feature = "cbor"))]
+    fn from_cbor(_map: &[u8]) -> Result<Self, FileMapError> {
+        Err(FileMapError::FeatureDisabled("cbor"))
     }
 
     /// Get relative file path
@@ -40,24 +461,459 @@ impl Files {
     /// If the modified filename path is `./prod/test.randomhash.svg`, it will
     /// output `/test.randomhash.svg`. For full path, see [get_full_path][Self::get_full_path].
     pub fn get(&self, path: impl AsRef<str>) -> Option<&str> {
-        if let Some(path) = self.map.get(path.as_ref()) {
-            Some(&path[self.base_dir.len()..])
+        if let Some(entry) = self.map.get(path.as_ref()) {
+            Some(&entry.path[self.base_dir.len()..])
             // Some(&path)
         } else {
             None
         }
     }
 
+    /// Get relative file path of an asset of the `Asset` enum generated with
+    /// [BusterBuilder::codegen][crate::BusterBuilder::codegen], see
+    /// [get][Self::get]. Every variant names a processed file, so matches over
+    /// assets are exhaustive and renamed files break the build:
+    ///
+    /// ```ignore
+    /// include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+    ///
+    /// let logo: Option<&str> = files.get_asset(Asset::ImgLogoPng);
+    /// ```
+    ///
+    /// `Asset` also works with the other getters, like
+    /// [get_integrity][Self::get_integrity]. None when the asset isn't in
+    /// the filemap, which happens when the enum and the filemap come from
+    /// different builds
+    pub fn get_asset(&self, asset: impl crate::Asset) -> Option<&str> {
+        self.get(asset.source())
+    }
+
     /// Get file path
     ///
     /// If the modified filename path is `./prod/test.randomhash.svg`, it will
     /// output `/prod/test.randomhash.svg`. For relative path, see [get][Self::get].
     pub fn get_full_path(&self, path: impl AsRef<str>) -> Option<&String> {
+        self.map.get(path.as_ref()).map(|entry| &entry.path)
+    }
+
+    /// Get URL path of a file: the route prefix set with
+    /// [BusterBuilder::prefix][crate::BusterBuilder::prefix] followed by the
+    /// relative path from [get][Self::get]
+    ///
+    /// With prefix `/static`, `./prod/test.randomhash.svg` is served at
+    /// `/static/test.randomhash.svg`. Without a prefix, this is the same as [get][Self::get].
+    pub fn get_url(&self, path: impl AsRef<str>) -> Option<String> {
+        let entry = self.map.get(path.as_ref())?;
+        Some(self.url(&entry.path))
+    }
+
+    // URL path of a modified path in base_dir
+    fn url(&self, path: &str) -> String {
+        let rel = &path[self.base_dir.len()..];
+        prefixed(self.prefix.as_deref(), rel)
+    }
+
+    /// Get a `srcset` attribute value listing the resized variants of an
+    /// image of MIME type `mime`, like `/img/a-480w.1f2e.webp 480w,
+    /// /img/a-960w.3d4c.webp 960w`. See
+    /// [BusterBuilder::responsive_images][crate::BusterBuilder::responsive_images]
+    pub fn get_srcset(&self, path: impl AsRef<str>, mime: &str) -> Option<String> {
+        let entry = self.map.get(path.as_ref())?;
+        let srcset: Vec<String> = entry
+            .images
+            .iter()
+            .filter(|image| image.mime == mime)
+            .filter_map(|image| Some(format!("{} {}w", self.url(&image.path), image.width?)))
+            .collect();
+        if srcset.is_empty() {
+            None
+        } else {
+            Some(srcset.join(", "))
+        }
+    }
+
+    /// Get relative file path of a variant of a file, registered with
+    /// [BusterBuilder::variants][crate::BusterBuilder::variants]
+    ///
+    /// `path` is the logical path: with variants `light` and `dark`,
+    /// `./dist/app.light.css` and `./dist/app.dark.css` are variants of
+    /// `./dist/app.css`, which doesn't have to exist.
+    pub fn get_variant(&self, path: impl AsRef<str>, variant: &str) -> Option<&str> {
+        let original = self.variants.get(path.as_ref())?.get(variant)?;
+        self.get(original)
+    }
+
+    /// Get filemap entry of a file, with its modified path and metadata
+    pub fn get_entry(&self, path: impl AsRef<str>) -> Option<&Entry> {
         self.map.get(path.as_ref())
     }
+
+    /// Iterate over original paths and their entries
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &Entry)> {
+        self.map.iter()
+    }
+
+    // files of previous runs kept in base_dir, newest run first
+    #[cfg(feature = "processor")]
+    pub(crate) fn generations(&self) -> &[Vec<String>] {
+        &self.generations
+    }
+
+    /// Explain why `request_path` couldn't be served, for logging
+    /// actionable diagnostics on 404s
+    ///
+    /// `request_path` is the path part of the request URL, query string and
+    /// fragment are ignored. It's matched against the URL paths from
+    /// [get_url][Self::get_url], and the paths from [get][Self::get] for
+    /// servers that strip the route prefix before looking files up.
+    pub fn explain_miss(&self, request_path: &str) -> MissReason {
+        let request = normalize(request_path);
+        let request_name = request.rsplit('/').next().unwrap_or_default();
+        let mut entries: Vec<(&String, String, &str)> = self
+            .map
+            .iter()
+            .map(|(original, entry)| {
+                let rel = &entry.path[self.base_dir.len()..];
+                (original, self.url(&entry.path), rel)
+            })
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (original, url, rel) in entries.iter() {
+            if normalize(url) == request || normalize(rel) == request {
+                return MissReason::Known {
+                    original: original.to_string(),
+                };
+            }
+        }
+        let stale = strip_hashes(request);
+        for (original, url, rel) in entries.iter() {
+            if strip_hashes(normalize(url)) == stale || strip_hashes(normalize(rel)) == stale {
+                return MissReason::StaleHash {
+                    original: original.to_string(),
+                    current: url.clone(),
+                };
+            }
+        }
+        for (original, url, _) in entries.iter() {
+            if url.rsplit('/').next() == Some(request_name) {
+                return MissReason::WrongPrefix {
+                    original: original.to_string(),
+                    current: url.clone(),
+                };
+            }
+        }
+        for source in self.skipped.iter() {
+            if request == source || request.ends_with(&format!("/{}", source)) {
+                return MissReason::NotProcessed {
+                    source: source.clone(),
+                };
+            }
+        }
+        MissReason::UnknownOriginal
+    }
+
+    /// Get size of a file in bytes, for `Content-Length` headers
+    pub fn get_size(&self, path: impl AsRef<str>) -> Option<u64> {
+        self.map.get(path.as_ref()).and_then(|entry| entry.size)
+    }
+
+    /// Get resolved MIME type of a file, for `Content-Type` headers
+    pub fn get_mime(&self, path: impl AsRef<str>) -> Option<&str> {
+        self.map
+            .get(path.as_ref())
+            .and_then(|entry| entry.mime.as_deref())
+    }
+
+    /// Get SHA-256 hash of file contents
+    ///
+    /// Recorded for every processed file, including ones with an explicit output
+    /// name. Files excluded from hashing with [NoHashCategory][crate::NoHashCategory]
+    /// are only covered with [track_no_hash][crate::BusterBuilder::track_no_hash].
+    pub fn get_hash(&self, path: impl AsRef<str>) -> Option<&str> {
+        self.map
+            .get(path.as_ref())
+            .and_then(|entry| entry.hash.as_deref())
+    }
+
+    /// Get [Subresource Integrity](https://developer.mozilla.org/en-US/docs/Web/Security/Subresource_Integrity)
+    /// digest of a file, like `sha384-<base64 digest>`. Use it in the `integrity`
+    /// attribute of `<script>` and `<link>` tags.
+    ///
+    /// Recorded for files excluded from hashing with
+    /// [NoHashCategory][crate::NoHashCategory] only with
+    /// [track_no_hash][crate::BusterBuilder::track_no_hash].
+    pub fn get_integrity(&self, path: impl AsRef<str>) -> Option<&str> {
+        self.map
+            .get(path.as_ref())
+            .and_then(|entry| entry.integrity.as_deref())
+    }
+
+    /// Get strong ETag of a file, quotes included, for `ETag` headers
+    ///
+    /// Recorded with [etag][crate::BusterBuilder::etag], for files excluded from
+    /// hashing with [NoHashCategory][crate::NoHashCategory] only with
+    /// [track_no_hash][crate::BusterBuilder::track_no_hash] as well.
+    pub fn get_etag(&self, path: impl AsRef<str>) -> Option<&str> {
+        self.map
+            .get(path.as_ref())
+            .and_then(|entry| entry.etag.as_deref())
+    }
+
+    /// Get hashes of fixed-size blocks of a file, recorded for files larger
+    /// than [BusterBuilder::chunk_size][crate::BusterBuilder::chunk_size]
+    pub fn get_chunks(&self, path: impl AsRef<str>) -> Option<&Chunks> {
+        self.map
+            .get(path.as_ref())
+            .and_then(|entry| entry.chunks.as_ref())
+    }
+
+    /// Get modified path of the variant of a file precompressed with
+    /// `encoding`, a `Content-Encoding` like `gzip`, `br` or `zstd`. See
+    /// [BusterBuilder::precompress][crate::BusterBuilder::precompress]
+    pub fn get_precompressed(&self, path: impl AsRef<str>, encoding: &str) -> Option<&str> {
+        self.map
+            .get(path.as_ref())
+            .and_then(|entry| entry.precompressed.get(encoding))
+            .map(|path| path.as_str())
+    }
+
+    /// Get short cache key of a file, for URLs that can't be renamed but can
+    /// carry a `?v=<key>` query parameter
+    ///
+    /// Recorded for every processed file that is read. Files excluded from
+    /// hashing with [NoHashCategory][crate::NoHashCategory] are only read with
+    /// [track_no_hash][crate::BusterBuilder::track_no_hash].
+    pub fn get_cache_key(&self, path: impl AsRef<str>) -> Option<&str> {
+        self.map
+            .get(path.as_ref())
+            .and_then(|entry| entry.cache_key.as_deref())
+    }
+
+    /// Get Content-Security-Policy hash source of a file inlined into pages,
+    /// like `'sha256-<base64 digest>'`, quotes included. Recorded for files set
+    /// with [BusterBuilder::inline][crate::BusterBuilder::inline]
+    pub fn get_csp_hash(&self, path: impl AsRef<str>) -> Option<&str> {
+        self.map
+            .get(path.as_ref())
+            .and_then(|entry| entry.csp_hash.as_deref())
+    }
+
+    /// Get Content-Security-Policy hash sources of all inlined files of
+    /// `mime`, like `text/css` or `text/javascript`, sorted and deduplicated.
+    /// Join them for `style-src` and `script-src` directives:
+    ///
+    /// ```no_run
+    /// # let files: cache_buster::Files = unimplemented!();
+    /// let script_src = format!("script-src 'self' {}", files.get_csp_hashes("text/javascript").join(" "));
+    /// ```
+    pub fn get_csp_hashes(&self, mime: &str) -> Vec<&str> {
+        let mut hashes: Vec<&str> = self
+            .map
+            .values()
+            .filter(|entry| entry.mime.as_deref() == Some(mime))
+            .filter_map(|entry| entry.csp_hash.as_deref())
+            .collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+        hashes
+    }
+
+    /// Get `Link` header values preloading the critical assets set with
+    /// [BusterBuilder::preload][crate::BusterBuilder::preload], like
+    /// `</static/app.<hash>.css>; rel=preload; as=style`, for responses and
+    /// `103 Early Hints`
+    pub fn get_preload_links(&self) -> Vec<String> {
+        self.preload
+            .iter()
+            .filter_map(|key| {
+                let entry = self.map.get(key)?;
+                Some(preload_link(&self.url(&entry.path), entry.mime.as_deref()))
+            })
+            .collect()
+    }
 }
 
-#[cfg(test)]
+/// `Link` header value preloading the file at `url`, with `as` picked from
+/// its MIME type or, when that isn't known, its extension
+pub(crate) fn preload_link(url: &str, mime: Option<&str>) -> String {
+    let extension = url.rsplit_once('.').map_or("", |(_, extension)| extension);
+    let destination = match (mime.unwrap_or_default(), extension) {
+        ("text/css", _) | (_, "css") => "style",
+        ("text/javascript" | "application/javascript", _) | (_, "js" | "mjs") => "script",
+        (mime, _) if mime.starts_with("font/") => "font",
+        (_, "woff" | "woff2" | "ttf" | "otf") => "font",
+        (mime, _) if mime.starts_with("image/") => "image",
+        (_, "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" | "avif") => "image",
+        _ => "fetch",
+    };
+    // fonts and fetches are requested in CORS mode, which preloads have to match
+    let cors = if matches!(destination, "font" | "fetch") {
+        "; crossorigin"
+    } else {
+        ""
+    };
+    format!("<{}>; rel=preload; as={}{}", url, destination, cors)
+}
+
+/// Filemap borrowing paths from the JSON it is loaded from, so that loading
+/// one embedded with `include_str!` doesn't allocate a string per file. Only
+/// modified paths and the route prefix are loaded, use [Files] for metadata:
+///
+/// ```ignore
+/// use cache_buster::BorrowedFiles;
+///
+/// let files = BorrowedFiles::try_new(include_str!("./cache_buster_data.json")).unwrap();
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct BorrowedFiles<'a> {
+    /// map<original-path, modified-path>
+    map: HashMap<Cow<'a, str>, Cow<'a, str>, Hasher>,
+    base_dir: Cow<'a, str>,
+    /// route base_dir is served under
+    prefix: Option<Cow<'a, str>>,
+}
+
+// layout of BorrowedFiles in filemaps, fields of Files not listed are ignored
+#[derive(Deserialize)]
+struct BorrowedRepr<'a> {
+    #[serde(borrow)]
+    map: HashMap<Text<'a>, BorrowedEntryRepr<'a>>,
+    #[serde(borrow)]
+    base_dir: Text<'a>,
+    #[serde(borrow, default)]
+    prefix: Option<Text<'a>>,
+}
+
+// accepts both plain paths and records, like EntryRepr
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BorrowedEntryRepr<'a> {
+    #[serde(borrow)]
+    Path(Text<'a>),
+    Record {
+        #[serde(borrow)]
+        path: Text<'a>,
+    },
+}
+
+// string borrowed from the filemap, unless it had to be unescaped
+#[derive(PartialEq, Eq, Hash)]
+struct Text<'a>(Cow<'a, str>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for Text<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TextVisitor<'a>(std::marker::PhantomData<&'a str>);
+
+        impl<'de: 'a, 'a> Visitor<'de> for TextVisitor<'a> {
+            type Value = Text<'a>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E>(self, text: &'de str) -> Result<Self::Value, E> {
+                Ok(Text(Cow::Borrowed(text)))
+            }
+
+            fn visit_str<E>(self, text: &str) -> Result<Self::Value, E> {
+                Ok(Text(Cow::Owned(text.into())))
+            }
+
+            fn visit_string<E>(self, text: String) -> Result<Self::Value, E> {
+                Ok(Text(Cow::Owned(text)))
+            }
+        }
+
+        deserializer.deserialize_str(TextVisitor(std::marker::PhantomData))
+    }
+}
+
+impl<'a> BorrowedFiles<'a> {
+    /// Load filemap in main program, borrowing from `map`. Compressed
+    /// filemaps have to be decompressed first, see [Files::try_new]
+    pub fn try_new(map: &'a str) -> Result<Self, FileMapError> {
+        check_schema_version(map)?;
+        let repr: BorrowedRepr<'a> = serde_json::from_str(map)?;
+        let map = repr
+            .map
+            .into_iter()
+            .map(|(original, entry)| {
+                let path = match entry {
+                    BorrowedEntryRepr::Path(path) => path,
+                    BorrowedEntryRepr::Record { path } => path,
+                };
+                (original.0, path.0)
+            })
+            .collect();
+        Ok(Self {
+            map,
+            base_dir: repr.base_dir.0,
+            prefix: repr.prefix.map(|prefix| prefix.0),
+        })
+    }
+
+    /// Get relative file path, see [Files::get]
+    pub fn get(&self, path: impl AsRef<str>) -> Option<&str> {
+        let modified = self.map.get(path.as_ref())?;
+        Some(&modified[self.base_dir.len()..])
+    }
+
+    /// Get file path, see [Files::get_full_path]
+    pub fn get_full_path(&self, path: impl AsRef<str>) -> Option<&str> {
+        self.map
+            .get(path.as_ref())
+            .map(|modified| modified.as_ref())
+    }
+
+    /// Get URL path of a file, see [Files::get_url]
+    pub fn get_url(&self, path: impl AsRef<str>) -> Option<String> {
+        let rel = self.get(path)?;
+        Some(prefixed(self.prefix.as_deref(), rel))
+    }
+}
+
+// rel behind the route prefix, which may lack its leading slash or carry a
+// trailing one in hand-written filemaps
+pub(crate) fn prefixed(prefix: Option<&str>, rel: &str) -> String {
+    match prefix.map(|prefix| prefix.trim_matches('/')) {
+        Some(prefix) if !prefix.is_empty() => format!("/{}{}", prefix, rel),
+        _ => rel.to_string(),
+    }
+}
+
+// strip query string, fragment, scheme and host, and leading `./` and `/`
+fn normalize(path: &str) -> &str {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let path = match path.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |host_end| &rest[host_end..]),
+        None => path,
+    };
+    path.trim_start_matches(['.', '/'])
+}
+
+// drop hashes from path components: `<hash>/a.<hash>.svg` becomes `a.svg`
+fn strip_hashes(path: &str) -> String {
+    let is_hash = |part: &&str| {
+        part.len() == 64
+            && part
+                .chars()
+                .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_lowercase())
+    };
+    path.split('/')
+        .map(|component| {
+            component
+                .split('.')
+                .filter(|part| !is_hash(part))
+                .collect::<Vec<&str>>()
+                .join(".")
+        })
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+#[cfg(all(test, feature = "processor"))]
 mod tests {
     use std::fs;
 
@@ -88,7 +944,7 @@ mod tests {
         config.process().unwrap();
 
         let map = fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap();
-        let files = Files::new(&map);
+        let files = Files::try_new(&map).unwrap();
 
         assert!(get_full_path_runner("./dist/log-out.svg", &files));
         assert!(get_full_path_runner(
@@ -132,13 +988,36 @@ mod tests {
         config.process().unwrap();
 
         let map = fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap();
-        let files = Files::new(&map);
+        let files = Files::try_new(&map).unwrap();
 
         assert!(get_runner("./dist/log-out.svg", &files));
         assert!(get_runner("./dist/a/b/c/d/s/d/svg/credit-card.svg", &files));
+        struct LogOut;
+        impl crate::Asset for LogOut {
+            fn source(&self) -> &'static str {
+                "./dist/log-out.svg"
+            }
+        }
+        assert_eq!(files.get_asset(LogOut), files.get("./dist/log-out.svg"));
 
         assert!(!get_runner("dist/log-out.svg", &files));
         assert!(!get_runner("dist/a/b/c/d/s/d/svg/credit-card.svg", &files));
+
+        let hash = files.get_hash("./dist/log-out.svg").unwrap();
+        assert!(files.get("./dist/log-out.svg").unwrap().contains(hash));
+        assert!(files.get_hash("dist/log-out.svg").is_none());
+
+        // openssl dgst -sha384 -binary dist/log-out.svg | openssl base64 -A
+        assert_eq!(
+            files.get_integrity("./dist/log-out.svg").unwrap(),
+            "sha384-B4MJalMLoHBO9n7Xmr2KXgpuMPp78hb33QNVUHFPG2UPq9WsRvHBAwg8SV1DFPej"
+        );
+        assert!(files.get_integrity("dist/log-out.svg").is_none());
+
+        let cache_key = files.get_cache_key("./dist/log-out.svg").unwrap();
+        assert_eq!(cache_key.len(), 10);
+        assert!(hash.starts_with(cache_key));
+        assert!(files.get_cache_key("dist/log-out.svg").is_none());
         cleanup(&config);
     }
 
@@ -153,10 +1032,181 @@ mod tests {
         }
     }
 
+    fn explain_miss_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodexplainmiss")
+            .mime_types(vec![mime::IMAGE_PNG, mime::IMAGE_SVG])
+            .follow_links(true)
+            .prefix("/static")
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::try_new(&fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap()).unwrap();
+
+        let current = files.get_url("./dist/icon.png").unwrap();
+        let original = "./dist/icon.png".to_string();
+        let known = MissReason::Known {
+            original: original.clone(),
+        };
+        assert_eq!(files.explain_miss(&format!("{}?v=1", current)), known);
+        assert_eq!(
+            files.explain_miss(&format!("https://example.com{}", current)),
+            known
+        );
+        // servers mounting result under the prefix look up paths without it
+        assert_eq!(
+            files.explain_miss(files.get("./dist/icon.png").unwrap()),
+            known
+        );
+
+        let expected = MissReason::StaleHash {
+            original: original.clone(),
+            current: current.clone(),
+        };
+        let stale = format!("/static/icon.{}.png", "A".repeat(64));
+        assert_eq!(files.explain_miss(&stale), expected);
+        assert_eq!(files.explain_miss("/static/icon.png"), expected);
+        assert_eq!(files.explain_miss("/icon.png"), expected);
+
+        let name = current.rsplit('/').next().unwrap();
+        assert_eq!(
+            files.explain_miss(&format!("/assets/{}", name)),
+            MissReason::WrongPrefix { original, current }
+        );
+
+        assert_eq!(
+            files.explain_miss("/static/main.1ed8da86f47dbdff4959.bundle.js"),
+            MissReason::NotProcessed {
+                source: "main.1ed8da86f47dbdff4959.bundle.js".into()
+            }
+        );
+        assert_eq!(
+            files.explain_miss("/static/nonexistent.svg"),
+            MissReason::UnknownOriginal
+        );
+        cleanup(&config);
+    }
+
+    #[test]
+    fn plain_path_entries_work() {
+        let map = r#"{
+            "map": {
+                "./dist/github.svg": "./prod/github.1234.svg",
+                "./dist/bell.svg": {"path": "./prod/bell.svg", "size": 42, "mime": "image/svg+xml"}
+            },
+            "base_dir": "./prod"
+        }"#;
+        let files = Files::try_new(map).unwrap();
+        assert_eq!(files.get("./dist/github.svg"), Some("/github.1234.svg"));
+        assert_eq!(files.get_size("./dist/github.svg"), None);
+        assert_eq!(files.get_mime("./dist/github.svg"), None);
+        assert_eq!(files.get("./dist/bell.svg"), Some("/bell.svg"));
+        assert_eq!(files.get_size("./dist/bell.svg"), Some(42));
+        assert_eq!(files.get_mime("./dist/bell.svg"), Some("image/svg+xml"));
+        assert_eq!(files.get_url("./dist/bell.svg").unwrap(), "/bell.svg");
+    }
+
+    #[test]
+    fn schema_version_works() {
+        let legacy = r#"{"map": {"./dist/a.svg": "./prod/a.1.svg"}, "base_dir": "./prod"}"#;
+        assert_eq!(Files::try_new(legacy).unwrap().schema_version(), 1);
+        let current = format!(
+            r#"{{"schema_version": {}, "map": {{}}, "base_dir": "./prod"}}"#,
+            SCHEMA_VERSION
+        );
+        assert_eq!(
+            Files::try_new(&current).unwrap().schema_version(),
+            SCHEMA_VERSION
+        );
+
+        let newer = r#"{"schema_version": 99, "map": {}, "base_dir": "./prod", "shiny": []}"#;
+        let err = Files::try_new(newer).unwrap_err();
+        assert!(matches!(
+            err,
+            FileMapError::UnsupportedSchema {
+                found: 99,
+                supported: SCHEMA_VERSION
+            }
+        ));
+        assert!(err.to_string().contains("schema version 99"));
+        assert!(matches!(
+            BorrowedFiles::try_new(newer),
+            Err(FileMapError::UnsupportedSchema { found: 99, .. })
+        ));
+    }
+
+    #[test]
+    fn try_new_works() {
+        assert!(matches!(
+            Files::try_new("{\"map\": "),
+            Err(FileMapError::Json(_))
+        ));
+        assert!(matches!(Files::try_new("[]"), Err(FileMapError::Json(_))));
+        assert!(matches!(
+            Files::try_from_bytes(b"\xff\xfe"),
+            Err(FileMapError::NotUtf8(_))
+        ));
+        assert!(BorrowedFiles::try_new("{}").is_err());
+        let files = Files::try_from_bytes(br#"{"map": {}, "base_dir": "./prod"}"#).unwrap();
+        assert_eq!(files.get("./dist/a.svg"), None);
+    }
+
+    #[test]
+    fn get_asset_works() {
+        struct Missing;
+        impl crate::Asset for Missing {
+            fn source(&self) -> &'static str {
+                "./dist/a.svg"
+            }
+        }
+        let files = Files::try_new(r#"{"map": {}, "base_dir": "./prod"}"#).unwrap();
+        assert_eq!(files.get_asset(Missing), None);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    #[should_panic(expected = "invalid filemap")]
+    fn new_panics() {
+        Files::new("{");
+    }
+
+    #[test]
+    fn borrowed_files_work() {
+        let map = r#"{
+            "map": {
+                "./dist/github.svg": "./prod/github.1234.svg",
+                "./dist/bell.svg": {"path": "./prod/bell.svg", "size": 42, "chunks": null},
+                "./dist/\"quoted\".svg": "./prod/\"quoted\".svg"
+            },
+            "base_dir": "./prod",
+            "skipped": ["index.html"],
+            "prefix": "/static/"
+        }"#;
+        let files = BorrowedFiles::try_new(map).unwrap();
+        assert_eq!(files.get("./dist/github.svg"), Some("/github.1234.svg"));
+        assert!(matches!(
+            files.map.get("./dist/github.svg"),
+            Some(Cow::Borrowed(_))
+        ));
+        assert_eq!(
+            files.get_full_path("./dist/bell.svg"),
+            Some("./prod/bell.svg")
+        );
+        assert_eq!(files.get("./dist/\"quoted\".svg"), Some("/\"quoted\".svg"));
+        assert_eq!(
+            files.get_url("./dist/bell.svg").unwrap(),
+            "/static/bell.svg"
+        );
+        assert_eq!(files.get("./dist/eye.svg"), None);
+    }
+
     #[test]
     pub fn runner() {
         get_works();
         get_full_path_works();
+        explain_miss_works();
         processor_runner();
     }
 }
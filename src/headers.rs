@@ -0,0 +1,122 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Generates web server configuration setting cache headers of processed
+//! files: hashed files never change and are cached forever, files excluded
+//! from hashing keep their names and are revalidated on every use, where
+//! the format allows.
+use std::collections::BTreeSet;
+
+/// `Cache-Control` of hashed files
+pub(crate) const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// `Cache-Control` of files excluded from hashing
+pub(crate) const NO_CACHE: &str = "no-cache";
+
+/// Number of `_headers` rules Cloudflare Pages reads
+pub(crate) const MAX_RULES: usize = 100;
+
+/// Generate an Apache `.htaccess` with rules for files named `immutable`
+/// and `no_cache`, file names without directories. Each set goes into a
+/// single `<FilesMatch>`
+pub(crate) fn htaccess(immutable: &BTreeSet<String>, no_cache: &BTreeSet<String>) -> String {
+    let files = |src: &mut String, names: &BTreeSet<String>, directive: &str| {
+        if names.is_empty() {
+            return;
+        }
+        let names: Vec<String> = names.iter().map(|name| regex_escape(name)).collect();
+        let pattern = format!("^({})$", names.join("|"));
+        src.push_str(&format!("    <FilesMatch \"{}\">\n", escape(&pattern)));
+        src.push_str(&format!("        {}\n", directive));
+        src.push_str("    </FilesMatch>\n");
+    };
+
+    let mut src = String::new();
+    src.push_str("# @generated by cache-buster. Do not edit.\n\n");
+    src.push_str("<IfModule mod_headers.c>\n");
+    files(
+        &mut src,
+        immutable,
+        &format!("Header set Cache-Control \"{}\"", IMMUTABLE),
+    );
+    files(
+        &mut src,
+        no_cache,
+        &format!("Header set Cache-Control \"{}\"", NO_CACHE),
+    );
+    src.push_str("</IfModule>\n\n");
+    src.push_str("<IfModule mod_expires.c>\n");
+    src.push_str("    ExpiresActive On\n");
+    files(&mut src, immutable, "ExpiresDefault \"access plus 1 year\"");
+    files(&mut src, no_cache, "ExpiresDefault \"access\"");
+    src.push_str("</IfModule>\n");
+    src
+}
+
+/// Generate a Netlify and Cloudflare Pages `_headers` file with rules for
+/// the `immutable` URL paths, which may end in a `*` splat
+pub(crate) fn headers_file(immutable: &BTreeSet<String>) -> String {
+    let mut src = String::new();
+    src.push_str("# @generated by cache-buster. Do not edit.\n");
+    for path in immutable.iter() {
+        src.push_str(&format!("{}\n  Cache-Control: {}\n", path, IMMUTABLE));
+    }
+    src
+}
+
+// escape file names for use as literals in Apache's regular expressions
+fn regex_escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// escape quotes for double quoted Apache arguments, backslashes are taken
+// as they are
+fn escape(name: &str) -> String {
+    name.replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn htaccess_works() {
+        let immutable = BTreeSet::from(["app.1234.css".into(), "app.1234.css.gz".into()]);
+        let no_cache = BTreeSet::from(["robots \"1\".txt".into()]);
+        let src = htaccess(&immutable, &no_cache);
+        assert!(src.contains(concat!(
+            "    <FilesMatch \"^(app\\.1234\\.css|app\\.1234\\.css\\.gz)$\">\n",
+            "        Header set Cache-Control \"public, max-age=31536000, immutable\"\n",
+            "    </FilesMatch>\n",
+        )));
+        assert!(src.contains(concat!(
+            "    <FilesMatch \"^(robots \\\"1\\\"\\.txt)$\">\n",
+            "        ExpiresDefault \"access\"\n",
+        )));
+        assert_eq!(src.matches("<FilesMatch ").count(), 4);
+        assert_eq!(regex_escape("a(1).min.js"), "a\\(1\\)\\.min\\.js");
+
+        let src = htaccess(&immutable, &BTreeSet::new());
+        assert_eq!(src.matches("<FilesMatch ").count(), 2);
+
+        let src = headers_file(&BTreeSet::from(["/static/app.1234.css".into()]));
+        assert_eq!(
+            src,
+            concat!(
+                "# @generated by cache-buster. Do not edit.\n",
+                "/static/app.1234.css\n",
+                "  Cache-Control: public, max-age=31536000, immutable\n",
+            )
+        );
+    }
+}
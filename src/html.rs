@@ -0,0 +1,244 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! HTML pages with tags for hashed entry assets
+//!
+//! Like `html-webpack-plugin`, processing can write HTML pages to result with
+//! `<link>` tags for stylesheets injected before `</head>` and `<script>`
+//! tags for scripts before `</body>`, pointing at the hashed names:
+//!
+//! ```no_run
+//! use cache_buster::html::HtmlPage;
+//! use cache_buster::BusterBuilder;
+//!
+//! let config = BusterBuilder::default()
+//!     .source("./dist")
+//!     .result("./prod")
+//!     .follow_links(true)
+//!     .html_pages(vec![HtmlPage::new(
+//!         "./templates/index.html",
+//!         ["./dist/css/app.css", "./dist/js/app.js"],
+//!     )
+//!     .integrity(true)])
+//!     .build()
+//!     .unwrap();
+//! config.process().unwrap();
+//! ```
+//!
+//! Pages without `</head>` get the `<link>` tags after the opening `<head>`
+//! tag, or after the opening `<html>` tag or the doctype when those are
+//! missing too. Pages without `</body>` get the `<script>` tags at the end.
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// HTML page written to result with tags for entry assets, see [html][self]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlPage {
+    /// template the tags are injected into
+    pub template: PathBuf,
+    /// filemap keys of stylesheets (`.css`) and scripts (`.js`, `.mjs`) to
+    /// inject, in order
+    pub entries: Vec<String>,
+    /// path of the page relative to result. File name of the template when
+    /// not set
+    pub output: Option<PathBuf>,
+    /// add `integrity` and `crossorigin` attributes with the Subresource
+    /// Integrity digests of entries
+    pub integrity: bool,
+}
+
+impl HtmlPage {
+    /// Inject tags for `entries`, filemap keys of stylesheets and scripts,
+    /// into `template`
+    pub fn new(
+        template: impl Into<PathBuf>,
+        entries: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            template: template.into(),
+            entries: entries.into_iter().map(Into::into).collect(),
+            output: None,
+            integrity: false,
+        }
+    }
+
+    /// Write the page to `output`, relative to result
+    pub fn output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+
+    /// Add `integrity` and `crossorigin` attributes
+    pub fn integrity(mut self, integrity: bool) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    // path of the page relative to result
+    pub(crate) fn output_path(&self) -> Result<&Path, Error> {
+        match &self.output {
+            Some(output) => Ok(output),
+            None => self.template.file_name().map(Path::new).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("HTML template {} has no file name", self.template.display()),
+                )
+            }),
+        }
+    }
+}
+
+/// Tag loading the entry `key` from `url`, None when `key` is neither a
+/// stylesheet nor a script
+pub(crate) fn tag(key: &str, url: &str, integrity: Option<&str>) -> Option<Tag> {
+    let extension = Path::new(key).extension()?.to_str()?;
+    let sri = integrity.map_or_else(String::new, |integrity| {
+        format!(
+            " integrity=\"{}\" crossorigin=\"anonymous\"",
+            escape(integrity)
+        )
+    });
+    let url = escape(url);
+    match extension {
+        "css" => Some(Tag::Link(format!(
+            "<link rel=\"stylesheet\" href=\"{}\"{}>",
+            url, sri
+        ))),
+        "js" => Some(Tag::Script(format!(
+            "<script defer src=\"{}\"{}></script>",
+            url, sri
+        ))),
+        "mjs" => Some(Tag::Script(format!(
+            "<script type=\"module\" src=\"{}\"{}></script>",
+            url, sri
+        ))),
+        _ => None,
+    }
+}
+
+/// Tag to inject, see [tag]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Tag {
+    /// goes into `<head>`
+    Link(String),
+    /// goes at the end of `<body>`
+    Script(String),
+}
+
+/// `template` with `tags` injected
+pub(crate) fn inject(template: &str, tags: &[Tag]) -> String {
+    let block = |link: bool| -> String {
+        tags.iter()
+            .filter_map(|tag| match tag {
+                Tag::Link(tag) if link => Some(format!("{}\n", tag)),
+                Tag::Script(tag) if !link => Some(format!("{}\n", tag)),
+                _ => None,
+            })
+            .collect()
+    };
+    let mut page = template.to_string();
+    let links = block(true);
+    let head = find_ignore_case(&page, "</head>")
+        .or_else(|| after_open_tag(&page, "<head"))
+        .or_else(|| after_open_tag(&page, "<html"))
+        .or_else(|| after_open_tag(&page, "<!doctype"))
+        .unwrap_or(0);
+    page.insert_str(head, &links);
+    let scripts = block(false);
+    let body = find_ignore_case(&page, "</body>").unwrap_or(page.len());
+    page.insert_str(body, &scripts);
+    page
+}
+
+// byte offset of the last occurrence of the ASCII `needle` in `haystack`,
+// ignoring case
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.to_ascii_lowercase().rfind(needle)
+}
+
+// byte offset right after the first tag opened with the ASCII `open`, like
+// `<head`, ignoring case. Tags merely starting with it, like `<header`,
+// don't count
+fn after_open_tag(page: &str, open: &str) -> Option<usize> {
+    let lower = page.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(start) = lower[from..].find(open).map(|i| from + i) {
+        let rest = &lower[start + open.len()..];
+        if rest.starts_with(|c: char| c.is_ascii_whitespace() || c == '>') {
+            return rest.find('>').map(|end| start + open.len() + end + 1);
+        }
+        from = start + open.len();
+    }
+    None
+}
+
+// escape text for use in double quoted attribute values
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inject_works() {
+        let tags = vec![
+            tag("./dist/app.css", "/static/app.1.css", Some("sha384-abc")).unwrap(),
+            tag("./dist/app.js", "/static/app.2.js", None).unwrap(),
+            tag("./dist/main.mjs", "/static/\"main\".3.mjs", None).unwrap(),
+        ];
+        assert!(tag("./dist/logo.svg", "/static/logo.4.svg", None).is_none());
+
+        let page = inject(
+            "<html><HEAD><title>t</title></HEAD><body><p>hi</p></body></html>",
+            &tags,
+        );
+        assert_eq!(
+            page,
+            concat!(
+                "<html><HEAD><title>t</title>",
+                "<link rel=\"stylesheet\" href=\"/static/app.1.css\" integrity=\"sha384-abc\" crossorigin=\"anonymous\">\n",
+                "</HEAD><body><p>hi</p>",
+                "<script defer src=\"/static/app.2.js\"></script>\n",
+                "<script type=\"module\" src=\"/static/&quot;main&quot;.3.mjs\"></script>\n",
+                "</body></html>"
+            )
+        );
+
+        assert_eq!(
+            inject("<p>hi</p>", &tags[..2]),
+            concat!(
+                "<link rel=\"stylesheet\" href=\"/static/app.1.css\" integrity=\"sha384-abc\" crossorigin=\"anonymous\">\n",
+                "<p>hi</p>",
+                "<script defer src=\"/static/app.2.js\"></script>\n",
+            )
+        );
+
+        let link = "<link rel=\"stylesheet\" href=\"/static/app.1.css\" integrity=\"sha384-abc\" crossorigin=\"anonymous\">\n";
+        assert_eq!(
+            inject(
+                "<!DOCTYPE html>\n<html lang=\"en\"><Head><header>h</header>",
+                &tags[..1]
+            ),
+            format!(
+                "<!DOCTYPE html>\n<html lang=\"en\"><Head>{}<header>h</header>",
+                link
+            )
+        );
+        assert_eq!(
+            inject("<!DOCTYPE html>\n<html><header>h</header>", &tags[..1]),
+            format!("<!DOCTYPE html>\n<html>{}<header>h</header>", link)
+        );
+        assert_eq!(
+            inject("<!doctype html><p>hi</p>", &tags[..1]),
+            format!("<!doctype html>{}<p>hi</p>", link)
+        );
+    }
+}
@@ -0,0 +1,219 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Responsive variants of raster images
+//!
+//! With [BusterBuilder::responsive_images][crate::BusterBuilder::responsive_images],
+//! every raster image is handed to an [ImageEncoder] for each configured
+//! width and [ImageFormat]. Variants are hashed and written next to the
+//! image, and recorded in its filemap entry, so that templates can build
+//! `srcset` attributes with [Files::get_srcset][crate::Files::get_srcset].
+//!
+//! [RasterEncoder] decodes PNG, JPEG, GIF and WebP images and encodes
+//! variants with the `image` crate:
+//!
+//! ```rust
+//! use cache_buster::images::{ImageFormat, RasterEncoder};
+//! use cache_buster::BusterBuilder;
+//!
+//! let config = BusterBuilder::default()
+//!     .source("./dist")
+//!     .result("./prod")
+//!     .follow_links(true)
+//!     .responsive_images(
+//!         RasterEncoder,
+//!         [480, 960],
+//!         [ImageFormat::Original, ImageFormat::WebP, ImageFormat::Avif],
+//!     )
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! Other encoders, like ones tuned for a specific format, implement
+//! [ImageEncoder]:
+//!
+//! ```rust
+//! use std::io::Error;
+//! use std::path::Path;
+//!
+//! use cache_buster::images::{ImageEncoder, ImageFormat};
+//! use cache_buster::BusterBuilder;
+//!
+//! struct Encoder;
+//!
+//! impl ImageEncoder for Encoder {
+//!     fn encode(
+//!         &self,
+//!         _path: &Path,
+//!         input: &[u8],
+//!         width: Option<u32>,
+//!         format: ImageFormat,
+//!     ) -> Result<Option<Vec<u8>>, Error> {
+//!         // decode input, resize it to width and encode it as format with
+//!         // an image library, or return None to skip the variant
+//!         # let _ = (input, width, format);
+//!         Ok(None)
+//!     }
+//! }
+//!
+//! let config = BusterBuilder::default()
+//!     .source("./dist")
+//!     .result("./prod")
+//!     .follow_links(true)
+//!     .responsive_images(Encoder, [480, 960], [ImageFormat::WebP, ImageFormat::Avif])
+//!     .build()
+//!     .unwrap();
+//! ```
+use std::io::{Cursor, Error, ErrorKind};
+use std::path::Path;
+
+use image::codecs::avif::AvifEncoder;
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+/// Encodes variants of raster images, see [images][self]
+pub trait ImageEncoder: Send + Sync {
+    /// Encode the image at `path` in source, with contents `input`, as
+    /// `format`, resized to `width` pixels when set. Return `None` to skip
+    /// the variant, like when `width` is larger than the image
+    fn encode(
+        &self,
+        path: &Path,
+        input: &[u8],
+        width: Option<u32>,
+        format: ImageFormat,
+    ) -> Result<Option<Vec<u8>>, Error>;
+}
+
+/// Encoding of image variants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// format of the original image, for resized variants
+    Original,
+    /// WebP
+    WebP,
+    /// AVIF
+    Avif,
+}
+
+impl ImageFormat {
+    /// MIME type of variants, None for [ImageFormat::Original]
+    pub fn mime(&self) -> Option<&'static str> {
+        match self {
+            Self::Original => None,
+            Self::WebP => Some("image/webp"),
+            Self::Avif => Some("image/avif"),
+        }
+    }
+
+    /// Extension of variants, without the leading dot. None for
+    /// [ImageFormat::Original]
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Self::Original => None,
+            Self::WebP => Some("webp"),
+            Self::Avif => Some("avif"),
+        }
+    }
+}
+
+/// Encodes variants with the `image` crate. Resizing keeps the aspect ratio
+/// and never upscales: widths at or above the width of the image are
+/// skipped. WebP variants are lossless, AVIF variants are encoded at quality
+/// 80
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RasterEncoder;
+
+// AVIF encoder settings, speed ranges from 1 (slowest) to 10
+const AVIF_SPEED: u8 = 8;
+const AVIF_QUALITY: u8 = 80;
+
+impl ImageEncoder for RasterEncoder {
+    fn encode(
+        &self,
+        path: &Path,
+        input: &[u8],
+        width: Option<u32>,
+        format: ImageFormat,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let invalid = |err: image::ImageError| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("can't encode {}: {}", path.display(), err),
+            )
+        };
+        let original = image::guess_format(input).map_err(invalid)?;
+        let mut image = image::load_from_memory_with_format(input, original).map_err(invalid)?;
+        if let Some(width) = width {
+            if width >= image.width() {
+                return Ok(None);
+            }
+            let height = (u64::from(image.height()) * u64::from(width) / u64::from(image.width()))
+                .max(1) as u32;
+            image = image.resize_exact(width, height, FilterType::Lanczos3);
+        }
+
+        let mut output = Vec::new();
+        match format {
+            ImageFormat::Original => image
+                .write_to(&mut Cursor::new(&mut output), original)
+                .map_err(invalid)?,
+            ImageFormat::WebP => DynamicImage::ImageRgba8(image.to_rgba8())
+                .write_to(&mut Cursor::new(&mut output), image::ImageFormat::WebP)
+                .map_err(invalid)?,
+            ImageFormat::Avif => DynamicImage::ImageRgba8(image.to_rgba8())
+                .write_with_encoder(AvifEncoder::new_with_speed_quality(
+                    &mut output,
+                    AVIF_SPEED,
+                    AVIF_QUALITY,
+                ))
+                .map_err(invalid)?,
+        }
+        Ok(Some(output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raster_encoder_works() {
+        let mut png = Vec::new();
+        DynamicImage::new_rgb8(40, 20)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let path = Path::new("./dist/img/a.png");
+        let encode = |width, format| RasterEncoder.encode(path, &png, width, format).unwrap();
+
+        let resized = encode(Some(10), ImageFormat::Original).unwrap();
+        assert_eq!(
+            image::guess_format(&resized).unwrap(),
+            image::ImageFormat::Png
+        );
+        let resized = image::load_from_memory(&resized).unwrap();
+        assert_eq!((resized.width(), resized.height()), (10, 5));
+
+        let webp = encode(None, ImageFormat::WebP).unwrap();
+        assert_eq!(
+            image::guess_format(&webp).unwrap(),
+            image::ImageFormat::WebP
+        );
+        assert_eq!(image::load_from_memory(&webp).unwrap().width(), 40);
+
+        let avif = encode(Some(20), ImageFormat::Avif).unwrap();
+        assert_eq!(&avif[4..12], b"ftypavif");
+
+        assert!(encode(Some(40), ImageFormat::WebP).is_none());
+        assert_eq!(
+            RasterEncoder
+                .encode(path, b"Rust", None, ImageFormat::WebP)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
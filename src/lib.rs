@@ -56,17 +56,151 @@
 //! use cache_buster::Files;
 //! use cache_buster::CACHE_BUSTER_DATA_FILE;
 //!
-//! let files = Files::new(CACHE_BUSTER_DATA_FILE);
+//! let map = std::fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap();
+//! let files = Files::try_new(&map).unwrap();
 //! // the path to the file before setting up for cache busting
 //! files.get("./dist/github.svg");
 //! ```
+//!
+//! ## Features
+//!
+//! - `processor` (default): build-time processing with [BusterBuilder]. Pulls in
+//!   `derive_builder`, `walkdir`, `ignore`, `globset`, `mime_guess`, `sha2`,
+//!   `data-encoding` and `phf_codegen`.
+//!   Crates that only read the filemap at runtime can depend on `cache-buster`
+//!   with `default-features = false`, which leaves just `serde` and `serde_json`.
+//!   With [codegen_map][BusterBuilder::codegen_map], the program doesn't need to
+//!   depend on `cache-buster` at all, only on `phf`.
+//! - `gzip`, `zstd`: write compressed filemaps with
+//!   [ManifestCompression][processor::ManifestCompression] and load them with
+//!   [Files::try_from_bytes].
+//! - `gzip`, `brotli`, `zstd`: write precompressed variants of assets with
+//!   [Precompression][processor::Precompression].
+//! - `images`: responsive variants of raster images, with a ready-made encoder
+//!   on the `image` crate, see `images`.
+//! - `svg`: SVG minifier to run before hashing, see `svg`.
+//! - `rayon`: hash and copy files on multiple threads, see
+//!   [threads][BusterBuilder::threads].
+//! - `jwalk`: walk source on multiple threads, see
+//!   [parallel_walk][BusterBuilder::parallel_walk].
+//! - `toml`: write the filemap as TOML with
+//!   [ManifestFormat][processor::ManifestFormat], for tooling outside the program.
+//! - `yaml`: write the filemap as YAML with
+//!   [ManifestFormat][processor::ManifestFormat], like for Kubernetes ConfigMaps.
+//! - `cbor`: write the filemap as compact CBOR with
+//!   [ManifestFormat][processor::ManifestFormat] and load it with
+//!   [Files::try_from_bytes].
 
+#[cfg(feature = "processor")]
 pub mod processor;
+#[cfg(feature = "processor")]
+pub use processor::Allowlist;
+#[cfg(feature = "processor")]
 pub use processor::BusterBuilder;
+#[cfg(feature = "processor")]
+pub use processor::EmptySource;
+#[cfg(feature = "processor")]
+pub use processor::ExternalLinks;
+#[cfg(feature = "processor")]
+pub use processor::FileFilter;
+#[cfg(feature = "processor")]
+pub use processor::HashPlacement;
+#[cfg(feature = "processor")]
+pub use processor::HiddenFiles;
+#[cfg(feature = "processor")]
+pub use processor::LinkStrategy;
+#[cfg(feature = "processor")]
 pub use processor::NoHashCategory;
+#[cfg(feature = "processor")]
+pub use processor::NonUtf8Policy;
+#[cfg(feature = "processor")]
+pub use processor::Observer;
+#[cfg(feature = "processor")]
+pub use processor::Plan;
+#[cfg(feature = "processor")]
+pub use processor::PlannedCopy;
+#[cfg(feature = "processor")]
+pub use processor::ProcessEvent;
+#[cfg(feature = "processor")]
+pub use processor::ProcessReport;
+#[cfg(feature = "processor")]
+pub use processor::Sanitize;
+#[cfg(feature = "processor")]
+pub use processor::SourceProtection;
+#[cfg(feature = "processor")]
+pub use processor::UnknownMimePolicy;
+#[cfg(feature = "processor")]
+pub use processor::UnreadablePolicy;
+#[cfg(feature = "processor")]
+pub use processor::FILEMAP_ENV;
+#[cfg(feature = "processor")]
+pub use processor::RESULT_MARKER;
+#[cfg(feature = "processor")]
+mod cache;
+#[cfg(feature = "processor")]
+mod codegen;
+#[cfg(feature = "processor")]
+pub mod deps;
+#[cfg(feature = "processor")]
+pub mod error;
+#[cfg(feature = "processor")]
+pub use error::CacheBusterError;
+#[cfg(feature = "processor")]
+mod headers;
+#[cfg(feature = "processor")]
+pub mod html;
+#[cfg(feature = "images")]
+pub mod images;
+#[cfg(feature = "processor")]
+pub mod naming;
+#[cfg(feature = "processor")]
+pub mod scaffold;
+#[cfg(feature = "processor")]
+pub use scaffold::scaffold;
+#[cfg(feature = "processor")]
+pub mod snapshot;
+#[cfg(feature = "processor")]
+pub mod store;
+#[cfg(feature = "svg")]
+pub mod svg;
+#[cfg(feature = "processor")]
+pub mod transform;
+#[cfg(feature = "processor")]
+pub use transform::{AssetTransform, TransformTarget};
+#[cfg(feature = "processor")]
+pub mod watch;
+#[cfg(feature = "processor")]
+pub use naming::{hash_file, HashOptions, HashOptionsBuilder, HashedName, NameMangler};
+mod asset;
+pub use asset::Asset;
 pub mod filemap;
-pub use filemap::Files;
+#[cfg(feature = "processor")]
+mod protect;
+#[cfg(feature = "processor")]
+mod rewrite;
+#[cfg(feature = "processor")]
+mod sanitize;
+pub use filemap::{
+    BorrowedFiles, Chunks, FileMapError, Files, ImageVariant, MissReason, SCHEMA_VERSION,
+};
+
+/// Load the filemap a build script wrote to `OUT_DIR` with
+/// [BusterBuilder::out_dir], embedding it in the binary. Evaluates to a
+/// `Result<Files, FileMapError>`
+///
+/// ```ignore
+/// use cache_buster::{include_filemap, Files};
+///
+/// let files: Files = include_filemap!().unwrap();
+/// ```
+#[macro_export]
+macro_rules! include_filemap {
+    () => {
+        $crate::Files::try_from_bytes(include_bytes!(env!("CACHE_BUSTER_FILEMAP")))
+    };
+}
 
 /// file to which filemap is written during compilation
-/// include this to `.gitignore`
+/// include this, along with the `.lock` file processing creates next to it,
+/// to `.gitignore`
 pub const CACHE_BUSTER_DATA_FILE: &str = "./src/cache_buster_data.json";
@@ -0,0 +1,43 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Command line helpers for setting up cache-buster
+//!
+//! `cache-buster init <source> [result]` inspects a static directory and
+//! writes a starter `build.rs` to the current directory.
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage: cache-buster init <source> [result]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (source, result) = match args.as_slice() {
+        [command, source] if command == "init" => (source.as_str(), "./prod"),
+        [command, source, result] if command == "init" => (source.as_str(), result.as_str()),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let scaffold = cache_buster::scaffold(source).unwrap_or_else(|e| {
+        eprintln!("can't inspect {}: {}", source, e);
+        process::exit(1);
+    });
+    println!("MIME types: {}", scaffold.mime_types.join(", "));
+    for file in scaffold.no_hash.iter() {
+        println!("not hashing: {}", file);
+    }
+    for file in scaffold.unknown.iter() {
+        println!("unknown MIME type, copied without hash: {}", file);
+    }
+    if let Err(e) = scaffold.write("./build.rs", result) {
+        eprintln!("can't write build.rs: {}", e);
+        process::exit(1);
+    }
+    println!("wrote build.rs, add cache-buster and mime to [build-dependencies]");
+}
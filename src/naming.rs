@@ -0,0 +1,365 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Naming scheme shared by the build-time processor and [hash_file]
+//!
+//! Use [hash_file] to bust one-off files generated at runtime, like a
+//! compiled user theme, with the same names [Buster][crate::processor::Buster]
+//! would have given them:
+//!
+//! ```rust
+//! use cache_buster::{hash_file, HashOptionsBuilder};
+//!
+//! let options = HashOptionsBuilder::default()
+//!     .compound_extensions(vec!["min.js"])
+//!     .build()
+//!     .unwrap();
+//! let hashed = hash_file("./dist/github.svg", &options).unwrap();
+//! assert_eq!(hashed.name, format!("github.{}.svg", hashed.hash));
+//! ```
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use data_encoding::{BASE64, HEXUPPER};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384};
+
+use crate::CacheBusterError;
+
+use crate::filemap::Chunks;
+
+use crate::processor::Sanitize;
+use crate::sanitize;
+
+/// Options that influence generated names. See [hash_file]
+#[derive(Debug, Clone, Default, Builder)]
+pub struct HashOptions {
+    /// see [BusterBuilder::compound_extensions][crate::BusterBuilder::compound_extensions]
+    #[builder(setter(custom), default)]
+    pub compound_extensions: Vec<String>,
+    /// see [BusterBuilder::sanitize][crate::BusterBuilder::sanitize]
+    #[builder(setter(into, strip_option), default)]
+    pub sanitize: Option<Sanitize>,
+}
+
+impl HashOptionsBuilder {
+    /// see [BusterBuilder::compound_extensions][crate::BusterBuilder::compound_extensions]
+    pub fn compound_extensions<I, S>(&mut self, extensions: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.compound_extensions = Some(extensions.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+/// Busted name of a file, see [hash_file]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashedName {
+    /// SHA-256 hash of file contents
+    pub hash: String,
+    /// Subresource Integrity digest of file contents
+    pub integrity: String,
+    /// file name to write the file to: `<stem>.<hash>.<extension>`
+    pub name: String,
+    /// file name as it appears in URLs. Differs from [name][Self::name] only
+    /// with [Sanitize::PercentEncode]
+    pub url_name: String,
+}
+
+/// Full control over output naming, see
+/// [BusterBuilder::name_mangler][crate::BusterBuilder::name_mangler]
+///
+/// ```rust
+/// use std::path::Path;
+///
+/// use cache_buster::NameMangler;
+///
+/// /// `css/main.css` becomes `<first 8 characters of hash>/css/main.css`
+/// struct HashDir;
+///
+/// impl NameMangler for HashDir {
+///     fn name(&self, path: &Path, hash: &str) -> String {
+///         format!("{}/{}", &hash[..8], path.display())
+///     }
+/// }
+/// ```
+pub trait NameMangler: Send + Sync {
+    /// Output path of the file at `path` with contents hashing to `hash`.
+    /// Both are relative to the result directory, `path` being where the
+    /// file would go without a hash
+    fn name(&self, path: &Path, hash: &str) -> String;
+}
+
+/// Compute the busted name of a single file, using the naming scheme of the
+/// build-time processor
+pub fn hash_file(
+    path: impl AsRef<Path>,
+    options: &HashOptions,
+) -> Result<HashedName, CacheBusterError> {
+    let path = path.as_ref();
+    let mut digester = Digester::new(None);
+    let mut file = fs::File::open(path).map_err(CacheBusterError::file(path))?;
+    io::copy(&mut file, &mut digester).map_err(CacheBusterError::file(path))?;
+    let Digests {
+        hash, integrity, ..
+    } = digester.finish();
+    let name = hashed_name(path, &hash, &options.compound_extensions);
+    let (name, url_name) = match options.sanitize {
+        Some(Sanitize::Transliterate) => {
+            let name = sanitize::transliterate(&name);
+            (name.clone(), name)
+        }
+        Some(Sanitize::PercentEncode) => {
+            let url_name = sanitize::percent_encode(&name);
+            (name, url_name)
+        }
+        None => (name.clone(), name),
+    };
+    Ok(HashedName {
+        hash,
+        integrity,
+        name,
+        url_name,
+    })
+}
+
+/// SHA-256 hash of payload
+pub(crate) fn hash(payload: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(payload);
+    HEXUPPER.encode(&hasher.finalize())
+}
+
+/// Content-Security-Policy hash source of payload, quotes included
+pub(crate) fn csp_hash(payload: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(payload);
+    format!("'sha256-{}'", BASE64.encode(&hasher.finalize()))
+}
+
+/// Subresource Integrity digest of payload
+#[cfg(test)]
+pub(crate) fn integrity(payload: &[u8]) -> String {
+    let mut hasher = Sha384::new();
+    hasher.update(payload);
+    format!("sha384-{}", BASE64.encode(&hasher.finalize()))
+}
+
+/// Digests of file contents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Digests {
+    /// SHA-256 hash, see [hash]
+    pub(crate) hash: String,
+    /// Subresource Integrity digest, see [integrity]
+    pub(crate) integrity: String,
+    /// hashes of blocks, for contents larger than a single block
+    pub(crate) chunks: Option<Chunks>,
+}
+
+/// Computes [Digests] of contents written to it piece by piece, so that
+/// large files don't have to be held in memory
+pub(crate) struct Digester {
+    hash: Sha256,
+    integrity: Sha384,
+    // size of blocks hashed separately
+    chunk_size: Option<u64>,
+    // hasher of the current block and bytes written to it
+    chunk: Sha256,
+    chunk_len: u64,
+    chunks: Vec<String>,
+    len: u64,
+}
+
+impl Digester {
+    /// Digester hashing blocks of `chunk_size` bytes along with the whole
+    pub(crate) fn new(chunk_size: Option<u64>) -> Self {
+        Self {
+            hash: Sha256::new(),
+            integrity: Sha384::new(),
+            chunk_size,
+            chunk: Sha256::new(),
+            chunk_len: 0,
+            chunks: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Digests of everything written so far
+    pub(crate) fn finish(mut self) -> Digests {
+        if self.chunk_len > 0 {
+            self.chunks
+                .push(HEXUPPER.encode(&self.chunk.finalize_reset()));
+        }
+        let chunks = self
+            .chunk_size
+            .filter(|size| self.len > *size)
+            .map(|size| Chunks {
+                size,
+                hashes: self.chunks,
+            });
+        Digests {
+            hash: HEXUPPER.encode(&self.hash.finalize()),
+            integrity: format!("sha384-{}", BASE64.encode(&self.integrity.finalize())),
+            chunks,
+        }
+    }
+}
+
+impl Write for Digester {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.hash.update(buf);
+        self.integrity.update(buf);
+        self.len += buf.len() as u64;
+        if let Some(size) = self.chunk_size {
+            let mut rest = buf;
+            while !rest.is_empty() {
+                let take = rest.len().min((size - self.chunk_len) as usize);
+                self.chunk.update(&rest[..take]);
+                self.chunk_len += take as u64;
+                rest = &rest[take..];
+                if self.chunk_len == size {
+                    self.chunks
+                        .push(HEXUPPER.encode(&self.chunk.finalize_reset()));
+                    self.chunk_len = 0;
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `<stem>.<hash>.<extension>`, or `<stem>.<hash>` for files without an
+/// extension
+pub(crate) fn hashed_name(path: &Path, hash: &str, compound_extensions: &[String]) -> String {
+    let (stem, extension) = split_name(path, compound_extensions);
+    join_name(&format!("{}.{}", stem, hash), extension)
+}
+
+/// `<stem>.<extension>`, or just `stem` when extension is empty
+pub(crate) fn join_name(stem: &str, extension: &str) -> String {
+    if extension.is_empty() {
+        stem.to_string()
+    } else {
+        format!("{}.{}", stem, extension)
+    }
+}
+
+/// Split file name into stem and extension, keeping compound extensions
+/// together. The extension follows the last dot, so `a.b.c` splits into
+/// `a.b` and `c`. It's empty for names without a dot, like `Makefile`, names
+/// whose only dot leads, like `.gitignore`, and names ending with a dot,
+/// which is dropped. `.env.example` splits into `.env` and `example`
+pub(crate) fn split_name<'b>(path: &'b Path, compound_extensions: &[String]) -> (&'b str, &'b str) {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let compound = compound_extensions
+        .iter()
+        .filter(|ext| {
+            name.len() > ext.len() + 1
+                && name.ends_with(*ext)
+                && name[..name.len() - ext.len()].ends_with('.')
+        })
+        .max_by_key(|ext| ext.len());
+    match compound {
+        Some(ext) => {
+            let split = name.len() - ext.len();
+            (&name[..split - 1], &name[split..])
+        }
+        None => match name.rfind('.') {
+            Some(0) | None => (name, ""),
+            Some(dot) => (&name[..dot], &name[dot + 1..]),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digester_works() {
+        let contents = b"0123456789";
+        let mut digester = Digester::new(Some(3));
+        for piece in [&contents[..2], &contents[2..7], &contents[7..]] {
+            digester.write_all(piece).unwrap();
+        }
+        let digests = digester.finish();
+        assert_eq!(digests.hash, hash(contents));
+        assert_eq!(digests.integrity, integrity(contents));
+        let chunks = digests.chunks.unwrap();
+        assert_eq!(chunks.size, 3);
+        assert_eq!(
+            chunks.hashes,
+            contents.chunks(3).map(hash).collect::<Vec<String>>()
+        );
+
+        let mut digester = Digester::new(Some(10));
+        digester.write_all(contents).unwrap();
+        assert!(digester.finish().chunks.is_none());
+    }
+
+    #[test]
+    fn hash_file_works() {
+        let contents = fs::read("./dist/github.svg").unwrap();
+        let options = HashOptions::default();
+        let hashed = hash_file("./dist/github.svg", &options).unwrap();
+        assert_eq!(hashed.hash, hash(&contents));
+        assert_eq!(hashed.integrity, integrity(&contents));
+        assert_eq!(hashed.name, format!("github.{}.svg", hashed.hash));
+        assert_eq!(hashed.name, hashed.url_name);
+
+        assert!(hash_file("./dist/nonexistent.svg", &options).is_err());
+    }
+
+    #[test]
+    fn split_name_works() {
+        let compound = ["min.js".to_string(), "tar.gz".to_string()];
+        assert_eq!(
+            split_name(Path::new("app.min.js"), &compound),
+            ("app", "min.js")
+        );
+        assert_eq!(split_name(Path::new("app.min.js"), &[]), ("app.min", "js"));
+        assert_eq!(split_name(Path::new("min.js"), &compound), ("min", "js"));
+        assert_eq!(
+            split_name(Path::new("a.tar.gz"), &compound),
+            ("a", "tar.gz")
+        );
+        assert_eq!(
+            split_name(Path::new("Makefile"), &compound),
+            ("Makefile", "")
+        );
+        assert_eq!(
+            split_name(Path::new(".gitignore"), &compound),
+            (".gitignore", "")
+        );
+        assert_eq!(
+            split_name(Path::new(".env.example"), &compound),
+            (".env", "example")
+        );
+        assert_eq!(split_name(Path::new("notes."), &compound), ("notes", ""));
+        assert_eq!(split_name(Path::new("a.b.c"), &compound), ("a.b", "c"));
+    }
+
+    #[test]
+    fn hashed_name_works() {
+        let hashed = |name| hashed_name(Path::new(name), "ABC", &[]);
+        assert_eq!(hashed("dir/app.js"), "app.ABC.js");
+        assert_eq!(hashed("Makefile"), "Makefile.ABC");
+        assert_eq!(hashed(".gitignore"), ".gitignore.ABC");
+        assert_eq!(hashed(".env.example"), ".env.ABC.example");
+        assert_eq!(hashed("a.b.c"), "a.b.ABC.c");
+    }
+}
@@ -35,15 +35,39 @@
 //! There's a runtime component to this library which will let you read modified
 //! filenames from within your program. See [Files]
 
-use std::collections::HashMap;
-use std::io::Error;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Error, ErrorKind, Write};
+use std::ops::Range;
 use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use std::{fs, path::PathBuf};
 
 use derive_builder::Builder;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
+use crate::cache::BuildCache;
+use crate::codegen;
+use crate::deps::DependencyAssets;
+use crate::filemap::Entry;
+#[cfg(feature = "images")]
+use crate::filemap::ImageVariant;
+use crate::headers;
+use crate::html::{self, HtmlPage};
+#[cfg(feature = "images")]
+use crate::images::{ImageEncoder, ImageFormat};
+use crate::naming::{self, Digester, Digests, NameMangler};
+use crate::protect;
+use crate::rewrite;
+use crate::sanitize;
+use crate::snapshot::{Snapshot, SnapshotEntry};
+#[cfg(unix)]
+use crate::store::Store;
+use crate::transform::{AssetTransform, TransformTarget};
 use crate::*;
 
 #[derive(Debug, Clone)]
@@ -56,472 +80,7202 @@ use crate::*;
 /// ```rust
 /// use cache_buster::NoHashCategory;
 ///
-/// let extensions = NoHashCategory::FileExtentions(vec!["wasm"]);
-/// let files = NoHashCategory::FileExtentions(vec!["swagger-ui-bundle.js", "favicon-16x16.png"]);
+/// let extensions = NoHashCategory::file_extensions(["wasm"]);
+/// let files = NoHashCategory::file_paths(["swagger-ui-bundle.js", "favicon-16x16.png"]);
+/// let globs = NoHashCategory::Globs(vec!["vendor/**/*.js".to_string()]);
+/// let types = NoHashCategory::MimeTypes(vec!["application/wasm".parse().unwrap()]);
 /// ```
-pub enum NoHashCategory<'a> {
+pub enum NoHashCategory {
     /// vector of file extensions that should be avoided for hash processing
-    FileExtentions(Vec<&'a str>),
+    FileExtentions(Vec<String>),
     /// list of file paths that should be avoided for file processing
-    FilePaths(Vec<&'a str>),
+    FilePaths(Vec<String>),
+    /// globs matching file paths relative to source that should be avoided
+    /// for hash processing
+    Globs(Vec<String>),
+    /// MIME types that should be avoided for hash processing. Wildcards like
+    /// `font/*` match every subtype of their top-level type
+    MimeTypes(Vec<mime::Mime>),
 }
 
+impl NoHashCategory {
+    /// [FileExtentions][Self::FileExtentions] from borrowed or owned strings
+    pub fn file_extensions<I, S>(extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::FileExtentions(strings(extensions))
+    }
+
+    /// [FilePaths][Self::FilePaths] from borrowed or owned strings
+    pub fn file_paths<I, S>(paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::FilePaths(strings(paths))
+    }
+
+    /// [Globs][Self::Globs] from borrowed or owned strings
+    pub fn globs<I, S>(globs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::Globs(strings(globs))
+    }
+}
+
+// collects borrowed or owned strings, for setters and constructors
+fn strings<I, S>(items: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    items.into_iter().map(Into::into).collect()
+}
+
+// renders a path, valid UTF-8, with forward slashes, so that filemap keys,
+// paths and URLs are the same on every platform. Backslashes are valid in
+// file names elsewhere, so they are only replaced on Windows
+fn slashed(path: &Path) -> String {
+    let path = path.to_str().unwrap();
+    #[cfg(windows)]
+    return path.replace('\\', "/");
+    #[cfg(not(windows))]
+    path.into()
+}
+
+/// Sanitization applied to generated destination names.
+///
+/// Original paths are always kept as-is in the filemap keys, only the
+/// destination names are affected.
+///
+/// ```rust
+/// use cache_buster::{BusterBuilder, Sanitize};
+///
+/// let config = BusterBuilder::default()
+///     .source("./dist")
+///     .result("./prod")
+///     .follow_links(true)
+///     .sanitize(Sanitize::PercentEncode)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanitize {
+    /// Percent-encode spaces, `#`, `?`, non-ASCII characters, etc. in the
+    /// filemap. Files on disk keep their names, so the encoded URL resolves
+    /// to them once a server decodes it.
+    PercentEncode,
+    /// Replace whitespace with `-`, strip common diacritics and replace
+    /// everything else that isn't URL safe with `_`, both on disk and in the
+    /// filemap. Processing fails when two files end up at the same path,
+    /// like `a b.svg` and `a-b.svg`.
+    Transliterate,
+}
+
+/// Where the hash goes in the generated path
+///
+/// ```rust
+/// use cache_buster::{BusterBuilder, HashPlacement};
+///
+/// let config = BusterBuilder::default()
+///     .source("./dist")
+///     .result("./prod")
+///     .follow_links(true)
+///     .hash_placement(HashPlacement::Directory)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashPlacement {
+    /// Include the hash of each file in its name: `result/css/main.<hash>.css`
+    #[default]
+    FileName,
+    /// Keep file names and put every hashed file under one leading directory
+    /// named after a hash of all of them: `result/<hash>/css/main.css`. The
+    /// whole versioned prefix changes when any file changes. Files excluded
+    /// with [NoHashCategory] stay at `result/css/main.css`
+    Directory,
+}
+
+/// Progress reported by [Buster::process] through
+/// [BusterBuilder::events]
+///
+/// ```rust
+/// use std::sync::mpsc::channel;
+/// use std::thread;
+///
+/// use cache_buster::{BusterBuilder, ProcessEvent};
+///
+/// let (tx, rx) = channel();
+/// let config = BusterBuilder::default()
+///     .source("./dist")
+///     .result("./prod")
+///     .mime_types(vec![mime::IMAGE_SVG])
+///     .follow_links(true)
+///     .events(tx)
+///     .build()
+///     .unwrap();
+///
+/// let progress = thread::spawn(move || {
+///     for event in rx.iter() {
+///         if let ProcessEvent::Copied { destination, .. } = event {
+///             println!("wrote {:?}", destination);
+///         }
+///     }
+/// });
+/// config.process().unwrap();
+/// drop(config);
+/// progress.join().unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessEvent {
+    /// file was read and hashed
+    Hashed {
+        /// path of the file in source
+        source: PathBuf,
+        /// hash of file contents
+        hash: String,
+    },
+    /// file was found in source but isn't picked up by the configured filters
+    Skipped {
+        /// path of the file in source
+        source: PathBuf,
+    },
+    /// source was walked, `total` files will be written to result
+    Planned {
+        /// number of files that will be written
+        total: usize,
+    },
+    /// file was written to result
+    Copied {
+        /// path of the file in source
+        source: PathBuf,
+        /// path of the file in result
+        destination: PathBuf,
+    },
+    /// processing finished and the filemap was written
+    Finished {
+        /// number of files written
+        total: usize,
+    },
+}
+
+/// Summary of a processing run, see [Buster::process]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessReport {
+    /// number of files written with a hash in their path
+    pub hashed: usize,
+    /// number of files written without a hash in their path
+    pub copied: usize,
+    /// number of files not picked up by the configured filters
+    pub skipped: usize,
+    /// number of written files left in place from an earlier build: hashed
+    /// files whose destination already exists, and files unchanged since the
+    /// last build with a [cache][BusterBuilder::cache]
+    pub unchanged: usize,
+    /// total size of written files in bytes
+    pub bytes: u64,
+    /// files and directories in source that couldn't be read and were left
+    /// out, see [BusterBuilder::unreadable]
+    pub unreadable: Vec<PathBuf>,
+    /// path the filemap was written to, see [BusterBuilder::manifest_path]
+    pub manifest: PathBuf,
+    /// time processing took
+    pub duration: Duration,
+    /// outcome of every file, written files first
+    pub files: Vec<FileReport>,
+}
+
+/// Outcome of processing a single file, see [ProcessReport]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReport {
+    /// path of the file in source
+    pub source: PathBuf,
+    /// what happened to the file
+    pub outcome: Outcome,
+}
+
+/// What happened to a file during processing, see [FileReport]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// written with a hash in its path
+    Hashed {
+        /// path of the file in result
+        destination: PathBuf,
+    },
+    /// written without a hash in its path
+    Copied {
+        /// path of the file in result
+        destination: PathBuf,
+    },
+    /// not picked up by the configured filters
+    Skipped,
+}
+
+/// Precompressed variant written next to processed files, see
+/// [BusterBuilder::precompress]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precompression {
+    /// write variants with a `.gz` suffix. Needs the `gzip` feature
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// write variants with a `.br` suffix. Needs the `brotli` feature
+    #[cfg(feature = "brotli")]
+    Brotli,
+    /// write variants with a `.zst` suffix. Needs the `zstd` feature
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Precompression {
+    /// `Content-Encoding` of variants, under which they are recorded in the
+    /// filemap
+    pub fn encoding(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => "gzip",
+            #[cfg(feature = "brotli")]
+            Self::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// Suffix appended to paths of variants
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => ".gz",
+            #[cfg(feature = "brotli")]
+            Self::Brotli => ".br",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => ".zst",
+        }
+    }
+
+    #[cfg_attr(
+        not(any(feature = "gzip", feature = "brotli", feature = "zstd")),
+        allow(unused_variables)
+    )]
+    fn compress(&self, contents: &[u8]) -> Result<Vec<u8>, Error> {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => ManifestCompression::Gzip.compress(contents),
+            #[cfg(feature = "brotli")]
+            Self::Brotli => {
+                let mut compressed = Vec::new();
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: 11,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut &contents[..], &mut compressed, &params)?;
+                Ok(compressed)
+            }
+            #[cfg(feature = "zstd")]
+            // highest level short of the memory hungry ultra levels
+            Self::Zstd => zstd::encode_all(contents, 19),
+        }
+    }
+}
+
+/// Compression applied to the filemap written during processing. Load
+/// compressed filemaps with [Files::try_from_bytes][crate::Files::try_from_bytes]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestCompression {
+    /// write the filemap with a `.gz` suffix
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// write the filemap with a `.zst` suffix
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl ManifestCompression {
+    /// Path the compressed filemap is written to, by default
+    pub fn path(&self) -> String {
+        self.path_for(CACHE_BUSTER_DATA_FILE)
+    }
+
+    /// Path the compressed filemap is written to, with
+    /// [manifest_path][BusterBuilder::manifest_path] set to `manifest`
+    #[cfg_attr(not(any(feature = "gzip", feature = "zstd")), allow(unused_variables))]
+    pub fn path_for(&self, manifest: &str) -> String {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => format!("{}.gz", manifest),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => format!("{}.zst", manifest),
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "gzip", feature = "zstd")), allow(unused_variables))]
+    fn compress(&self, json: &[u8]) -> Result<Vec<u8>, Error> {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => {
+                use std::io::Write;
+
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(json)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::encode_all(json, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+}
+
+/// Format of the filemap written during processing, see
+/// [BusterBuilder::manifest_format]. [Files][crate::Files] loads JSON and
+/// CBOR filemaps, other formats are meant for tooling outside the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ManifestFormat {
+    /// JSON
+    #[default]
+    Json,
+    /// TOML. Needs the `toml` feature
+    #[cfg(feature = "toml")]
+    Toml,
+    /// YAML. Needs the `yaml` feature
+    #[cfg(feature = "yaml")]
+    Yaml,
+    /// CBOR, a compact binary format that is faster to parse than JSON, for
+    /// filemaps embedded in binaries. Needs the `cbor` feature, also to load
+    /// them with [Files::try_from_bytes][crate::Files::try_from_bytes]
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl ManifestFormat {
+    /// Extension of filemaps in this format, without the leading dot
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Self::Json => "json",
+            #[cfg(feature = "toml")]
+            Self::Toml => "toml",
+            #[cfg(feature = "yaml")]
+            Self::Yaml => "yaml",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "cbor",
+        }
+    }
+
+    // can crate::Files load filemaps in this format?
+    fn loadable(&self) -> bool {
+        match *self {
+            Self::Json => true,
+            #[cfg(feature = "cbor")]
+            Self::Cbor => true,
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    fn serialize(&self, file_map: &Files) -> Result<Vec<u8>, Error> {
+        match *self {
+            Self::Json => Ok(serde_json::to_vec(file_map)?),
+            #[cfg(feature = "toml")]
+            Self::Toml => Ok(toml::to_string_pretty(file_map)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+                .into_bytes()),
+            #[cfg(feature = "yaml")]
+            Self::Yaml => Ok(serde_yaml::to_string(file_map)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+                .into_bytes()),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => {
+                let mut cbor = crate::filemap::CBOR_MAGIC.to_vec();
+                ciborium::into_writer(file_map, &mut cbor)
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                Ok(cbor)
+            }
+        }
+    }
+
+    fn deserialize(&self, map: &[u8]) -> Result<crate::Files, Error> {
+        match *self {
+            Self::Json => {
+                crate::Files::try_from_bytes(map).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+            }
+            #[cfg(feature = "cbor")]
+            Self::Cbor => {
+                crate::Files::try_from_bytes(map).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+            }
+            #[cfg(feature = "toml")]
+            Self::Toml => std::str::from_utf8(map)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+                .and_then(|map| {
+                    toml::from_str(map).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+                }),
+            #[cfg(feature = "yaml")]
+            Self::Yaml => {
+                serde_yaml::from_slice(map).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+            }
+        }
+    }
+}
+
+/// Commonly used compound extensions, for use with
+/// [BusterBuilder::compound_extensions]
+///
+/// ```rust
+/// use cache_buster::BusterBuilder;
+/// use cache_buster::processor::COMPOUND_EXTENSIONS;
+///
+/// let config = BusterBuilder::default()
+///     .source("./dist")
+///     .result("./prod")
+///     .follow_links(true)
+///     .compound_extensions(COMPOUND_EXTENSIONS.to_vec())
+///     .build()
+///     .unwrap();
+/// ```
+pub const COMPOUND_EXTENSIONS: &[&str] = &[
+    "min.js", "min.css", "min.mjs", "js.map", "css.map", "d.ts", "tar.gz", "tar.bz2", "tar.xz",
+    "tar.zst",
+];
+
+// length of cache keys recorded in the filemap
+const CACHE_KEY_LEN: usize = 10;
+
+/// file marking directories as result directories of this crate. Files
+/// recorded in the filemap are only removed from marked directories, unless
+/// [force][BusterBuilder::force] is set
+pub const RESULT_MARKER: &str = ".cache-buster";
+
+// name of the filemap in OUT_DIR, see BusterBuilder::out_dir
+const OUT_DIR_MANIFEST: &str = "cache_buster_data.json";
+
+/// environment variable pointing at the filemap written to `OUT_DIR`, see
+/// [BusterBuilder::out_dir]
+pub const FILEMAP_ENV: &str = "CACHE_BUSTER_FILEMAP";
+
+// file locked in result directories while processing
+const RESULT_LOCK: &str = ".cache-buster.lock";
+
 /// Configuration for setting up cache-busting
 #[derive(Debug, Clone, Builder)]
 #[builder(build_fn(validate = "Self::validate"))]
-pub struct Buster<'a> {
-    /// source directory
-    #[builder(setter(into))]
+pub struct Buster {
+    /// source directory. Required unless [sources][Self::sources] is set
+    #[builder(setter(into), default)]
     source: String,
-    /// mime_types for hashing
+    /// more source directories, processed after [source][Self::source] into
+    /// a single result directory and filemap. Files have to be at distinct
+    /// paths relative to their source directories, paths relative to source
+    /// in other options, like [no_hash][Self::no_hash], match in any of them
+    #[builder(setter(custom), default)]
+    sources: Vec<String>,
+    /// mime_types for hashing. Wildcards like [mime::IMAGE_STAR] match every
+    /// subtype of their top-level type
     #[builder(setter(into, strip_option), default)]
     mime_types: Option<Vec<mime::Mime>>,
-    /// directory for writing results
+    /// copy files not matching [mime_types][Self::mime_types] from source to
+    /// result without a hash in their names, like files excluded with
+    /// [NoHashCategory], instead of leaving them out. Off by default
+    #[builder(default)]
+    copy_unmatched: bool,
+    /// MIME types of extensions, like `("webmanifest", "application/manifest+json")`,
+    /// for files that `mime_guess` can't resolve or resolves differently than
+    /// wanted. Take precedence over guessed types for MIME filtering and in
+    /// the filemap
+    #[builder(setter(custom), default)]
+    mime_overrides: Vec<(String, mime::Mime)>,
+    /// what to do with files whose MIME type can't be resolved. Without a
+    /// policy, processing fails on them when [mime_types][Self::mime_types]
+    /// is set and hashes them otherwise
+    #[builder(setter(into, strip_option), default)]
+    unknown_mime: Option<UnknownMimePolicy>,
+    /// directory for writing results. Only decides where files end up on
+    /// disk, filemap entries record paths inside it
     #[builder(setter(into))]
     result: String,
+    /// route the result directory is served under, like `/static`. Only
+    /// applies to URLs, see [Files::get_url][crate::Files::get_url], and
+    /// never changes where files are written. Leading and trailing slashes
+    /// are optional: `/static`, `static` and `static/` are the same route,
+    /// and `""` or `/` means no prefix
     #[builder(setter(into, strip_option), default)]
-    /// route prefixes
     prefix: Option<String>,
     /// follow symlinks?
     follow_links: bool,
+    /// what to do with files that resolve outside of their source
+    /// directory through a symlink, which would publish arbitrary files
+    /// in result. Fails processing by default
+    #[builder(default)]
+    external_links: ExternalLinks,
+    /// what to do with source directories holding no files, like when the
+    /// frontend build was skipped. Warns by default. Sources missing when
+    /// building the config are rejected unless set to [EmptySource::Allow]
+    #[builder(default)]
+    empty_source: EmptySource,
+    /// what to do with files and directories in source that can't be read,
+    /// like ones lacking permissions or dangling symlinks. Fails processing
+    /// by default
+    #[builder(default)]
+    unreadable: UnreadablePolicy,
+    /// what to do with files whose paths aren't valid UTF-8, which can't be
+    /// recorded in the filemap. Fails processing by default
+    #[builder(default)]
+    non_utf8: NonUtf8Policy,
+    /// remove files written by the previous run, as recorded in its filemap,
+    /// before processing. Other files in the result directory, like a
+    /// hand-placed `robots.txt` or output of `wasm-pack`, are never removed.
+    /// Disable to keep files of earlier runs too. Defaults to true
+    #[builder(default = "true")]
+    clean: bool,
+    /// [clean][Self::clean] the result directory even when it lacks the
+    /// [RESULT_MARKER] written by processing, like one written by an older
+    /// version of this crate. Off by default
+    #[builder(default)]
+    force: bool,
+    /// keep files of this many previous runs in the result directory when
+    /// cleaning, so that clients holding a page from before a rolling deploy
+    /// can still fetch the assets it references. Older files are removed
+    #[builder(default)]
+    keep_generations: usize,
+    /// keep files written to result as hard links to blobs of a
+    /// content-addressed [Store] in this directory, outside of result.
+    /// Files identical across the generations kept by
+    /// [keep_generations][Self::keep_generations], like unhashed files or
+    /// those of [HashPlacement::Directory] builds, then take space once.
+    /// Blobs that no kept generation links to anymore are removed after
+    /// every run. Files are written as usual when the store is on another
+    /// filesystem than result. Linked files share their permissions and
+    /// times, so this can't be combined with
+    /// [preserve_metadata][Self::preserve_metadata] or
+    /// [LinkStrategy::Hardlink]
+    #[cfg(unix)]
+    #[builder(setter(into, strip_option), default)]
+    store: Option<String>,
+    /// skip files matched by `.gitignore`, `.ignore` and global git ignore
+    /// files, as well as hidden files like `.DS_Store`, so local junk in
+    /// source never makes it into the result. Ignore files apply even when
+    /// source isn't in a git repository
+    #[builder(default)]
+    respect_ignore_files: bool,
+    /// walk source directories on [threads][Self::threads] threads with the
+    /// `jwalk` feature, for huge directory trees. Files are picked up the
+    /// same way as with the default single-threaded walk. Ignore files are
+    /// only read by the latter, so this can't be combined with
+    /// [respect_ignore_files][Self::respect_ignore_files]
+    #[cfg(feature = "jwalk")]
+    #[builder(default)]
+    parallel_walk: bool,
+    /// what to do with dotfiles and files in dot-directories, like
+    /// `.well-known/`. Takes a [HiddenFiles] or a bool, `false` ignoring
+    /// them. Without it, they're processed like any other file, unless
+    /// [respect_ignore_files][Self::respect_ignore_files] is set
+    #[builder(setter(into, strip_option), default)]
+    include_hidden: Option<HiddenFiles>,
+    /// only process listed files, and report every other file in source with
+    /// a `cargo:warning`. Processing fails when a listed file is missing
+    #[builder(setter(into, strip_option), default)]
+    allowlist: Option<Allowlist>,
+    /// only process files matching one of these globs, like `**/*.css`.
+    /// Globs are matched against paths relative to [self.source], or
+    /// `<name>/<path>` for [dependency assets][crate::deps]. All files are
+    /// processed when empty
+    #[builder(setter(custom), default)]
+    include_globs: Vec<String>,
+    /// skip files matching one of these globs, like `**/vendor/**`. Takes
+    /// precedence over [self.include_globs]
+    #[builder(setter(custom), default)]
+    exclude_globs: Vec<String>,
+    /// directories pruned from the walk, like `node_modules`. Entries
+    /// without `/` match directories of that name anywhere in source, others
+    /// match paths relative to source. Pruned files aren't reported as skipped
+    #[builder(setter(custom), default)]
+    exclude_dirs: Vec<String>,
+    /// descend at most this many directories into source: `1` only processes
+    /// files directly in source. Unlimited by default
+    #[builder(setter(strip_option), default)]
+    max_depth: Option<usize>,
+    /// only process files for which this predicate returns true. Applied
+    /// after globs and before the MIME filter
+    #[builder(setter(custom), default)]
+    filter: Option<FileFilter>,
+    /// skip files smaller than this many bytes, like tiny icons that are
+    /// better inlined
+    #[builder(setter(strip_option), default)]
+    min_size: Option<u64>,
+    /// skip files larger than this many bytes, like videos that shouldn't be
+    /// duplicated into the result directory
+    #[builder(setter(strip_option), default)]
+    max_size: Option<u64>,
     /// exclude these files for hashing.
     /// They will be copied over without including a hash in the filename
     /// Path should be relative to [self.source]
     #[builder(default)]
-    no_hash: Vec<NoHashCategory<'a>>,
+    no_hash: Vec<NoHashCategory>,
+    /// explicit output names for particular files, as `(source path, output name)`.
+    /// Source paths are relative to [self.source]. The file keeps its
+    /// directory and gets exactly the given name, while its hash is still
+    /// recorded in the filemap
+    #[builder(setter(custom), default)]
+    output_names: Vec<(String, String)>,
+    /// extensions made up of several parts, like `min.js` or `tar.gz`, that
+    /// should be kept together when inserting the hash: `app.min.js` becomes
+    /// `app.<hash>.min.js` instead of `app.min.<hash>.js`. See
+    /// [COMPOUND_EXTENSIONS] for a list of common ones
+    #[builder(setter(custom), default)]
+    compound_extensions: Vec<String>,
+    /// where the hash goes in the generated path. Defaults to
+    /// [HashPlacement::FileName]
+    #[builder(default)]
+    hash_placement: HashPlacement,
+    /// point `//# sourceMappingURL=` comments and relative specifiers of
+    /// static `import` and `export ... from` statements in JavaScript files
+    /// at hashed names, so that source maps and module graphs keep working.
+    /// Can't be combined with [HashPlacement::Directory]. Off by default
+    #[builder(default)]
+    rewrite_js: bool,
+    /// names of asset variants, like `light` and `dark`. Files named
+    /// `<stem>.<variant>.<extension>` are processed as usual and also
+    /// registered as that variant of `<stem>.<extension>`, see
+    /// [Files::get_variant][crate::Files::get_variant]
+    #[builder(setter(custom), default)]
+    variants: Vec<String>,
+    /// write Rust source with a typed `Asset` enum, one variant per processed
+    /// file, to this path. Typos in asset references become build errors
+    /// instead of runtime 404s:
+    ///
+    /// ```ignore
+    /// // build.rs: .codegen(format!("{}/assets.rs", env::var("OUT_DIR").unwrap()))
+    /// include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+    ///
+    /// // URL to link to, like Files::get_url
+    /// let logo: &'static str = Asset::ImgLogoPng.url();
+    /// ```
+    ///
+    /// Assets also look up the filemap, see
+    /// [Files::get_asset][crate::Files::get_asset]. The enum implements
+    /// [Asset][crate::Asset], so the program depends on `cache-buster`, with
+    /// `default-features = false` when processing runs elsewhere
+    #[builder(setter(into, strip_option), default)]
+    codegen: Option<String>,
+    /// write Rust source with the filemap as a `static` [phf] map to this
+    /// path, along with `get` and `get_full_path` functions that look paths
+    /// up like [Files][crate::Files] does. The program then includes it as a
+    /// module and reads the filemap without `serde_json` or any parsing at
+    /// startup. It needs `phf` as a dependency:
+    ///
+    /// [phf]: https://docs.rs/phf
+    ///
+    /// ```ignore
+    /// // build.rs: .codegen_map(format!("{}/filemap.rs", env::var("OUT_DIR").unwrap()))
+    /// mod filemap {
+    ///     include!(concat!(env!("OUT_DIR"), "/filemap.rs"));
+    /// }
+    ///
+    /// let logo: Option<&'static str> = filemap::get("./dist/img/logo.png");
+    /// ```
+    #[builder(setter(into, strip_option), default)]
+    codegen_map: Option<String>,
+    /// write the filemap as a TypeScript module to this path, or as an ES
+    /// module when the path doesn't end in `.ts`, so that code running in the
+    /// browser resolves hashed URLs from the same filemap as the server. It
+    /// maps original paths to the URL paths
+    /// [Files::get_url][crate::Files::get_url] returns:
+    ///
+    /// ```js
+    /// import { assetUrl } from "./manifest";
+    ///
+    /// img.src = assetUrl("./dist/img/logo.png");
+    /// ```
+    ///
+    /// The TypeScript module also exports an `AssetPath` type of all original
+    /// paths, which turns typos into type errors
+    #[builder(setter(into, strip_option), default)]
+    js_manifest: Option<String>,
+    /// generate output paths of hashed files with this instead of the built-in
+    /// scheme. [hash_placement][Self::hash_placement] and
+    /// [Sanitize::Transliterate] don't apply to mangled paths
+    #[builder(setter(custom), default)]
+    name_mangler: Option<Mangler>,
+    /// rewrite contents of matching files before hashing, in the order they
+    /// were added. See [transform][crate::transform]
+    #[builder(setter(custom), default)]
+    transforms: Vec<(TransformTarget, Transform)>,
+    /// record a strong ETag for every hashed file in the filemap, so web
+    /// handlers can answer conditional requests without hashing files at
+    /// runtime
+    #[builder(default)]
+    etag: bool,
+    /// record hashes, integrity digests and ETags of files excluded with
+    /// [no_hash][Self::no_hash] too. They still keep their names. Without
+    /// this, those files are copied without being read
+    #[builder(default)]
+    track_no_hash: bool,
+    /// record SHA-256 hashes of fixed-size blocks of this many bytes for
+    /// files larger than a single block, so that integrity checks and partial
+    /// re-uploads of assets served with range requests, like videos, can work
+    /// on chunks. See [Files::get_chunks][crate::Files::get_chunks]
+    #[builder(setter(strip_option), default)]
+    chunk_size: Option<u64>,
+    /// make output reproducible across builds: copied files get a fixed
+    /// modification time, taken from `SOURCE_DATE_EPOCH` when set and the Unix
+    /// epoch otherwise. Filemap entries are always written in sorted order
+    #[builder(default)]
+    reproducible: bool,
+    /// how files are placed in the result directory. Defaults to
+    /// [LinkStrategy::Copy]. Hardlinks share permissions and times with
+    /// their source files, so [LinkStrategy::Hardlink] can't be combined
+    /// with [reproducible][Self::reproducible]
+    #[builder(default)]
+    link_strategy: LinkStrategy,
+    /// hash and copy files on this many threads, with the `rayon` feature.
+    /// The result is the same as with a single thread, but [ProcessEvent]s
+    /// of different files arrive in no particular order. This caps every
+    /// thread processing starts, [parallel_walk][Self::parallel_walk]
+    /// included, at one per CPU when unset. Set it to the CPU quota in
+    /// constrained CI environments
+    #[builder(setter(strip_option), default)]
+    #[cfg_attr(not(any(feature = "rayon", feature = "jwalk")), allow(dead_code))]
+    threads: Option<usize>,
+    // pool hashing and copying run on, built on first use and shared by
+    // every run of this configuration, watch mode rebuilds included
+    #[cfg(feature = "rayon")]
+    #[builder(setter(skip))]
+    pool: Arc<std::sync::OnceLock<Result<rayon::ThreadPool, String>>>,
+    /// copy files through a buffer of this many bytes. By default copying is
+    /// left to the OS, which avoids moving file contents through user space
+    /// with `copy_file_range` on Linux and `fclonefileat` on macOS. Larger
+    /// buffers help on filesystems where that isn't available
+    #[builder(setter(strip_option), default)]
+    copy_buffer_size: Option<usize>,
+    /// give files written to result the permissions and access and
+    /// modification times of their source files. Can't be combined with
+    /// [reproducible][Self::reproducible]. Off by default
+    #[builder(default)]
+    preserve_metadata: bool,
+    /// also give files written to result the owner and group of their source
+    /// files, which usually needs root. Implies
+    /// [preserve_metadata][Self::preserve_metadata]. Off by default
+    #[cfg(unix)]
+    #[builder(default)]
+    preserve_ownership: bool,
+    /// send [ProcessEvent]s to this channel while processing, to display
+    /// live progress in GUIs, TUIs, dev servers, etc.
+    #[builder(setter(strip_option), default)]
+    events: Option<Sender<ProcessEvent>>,
+    /// call this with every [ProcessEvent] while processing, on the thread
+    /// doing the processing. Works along with [events][Self::events]
+    #[builder(setter(custom), default)]
+    observer: Option<Observer>,
+    /// compress the filemap. Needs the `gzip` or `zstd` feature.
+    /// Uncompressed by default
+    #[builder(setter(into, strip_option), default)]
+    manifest_compression: Option<ManifestCompression>,
+    /// where to write the filemap, like a file in `OUT_DIR` to keep the
+    /// source tree clean. Missing directories are created. Defaults to
+    /// [CACHE_BUSTER_DATA_FILE]
+    ///
+    /// ```rust,no_run
+    /// use cache_buster::BusterBuilder;
+    ///
+    /// let out_dir = std::env::var("OUT_DIR").unwrap();
+    /// let config = BusterBuilder::default()
+    ///     .source("./dist")
+    ///     .result("./prod")
+    ///     .follow_links(true)
+    ///     .manifest_path(format!("{}/cache_buster_data.json", out_dir))
+    ///     .build()
+    ///     .unwrap();
+    /// let report = config.process().unwrap();
+    /// // load it with include_str!(concat!(env!("OUT_DIR"), "/cache_buster_data.json"))
+    /// assert!(report.manifest.ends_with("cache_buster_data.json"));
+    /// ```
+    #[builder(setter(into, strip_option), default)]
+    manifest_path: Option<String>,
+    /// format of the filemap. Filemaps in other formats than JSON are
+    /// written next to [CACHE_BUSTER_DATA_FILE], with its extension
+    /// replaced, unless [manifest_path][Self::manifest_path] is set. They
+    /// can't be compressed or written to `OUT_DIR`. Defaults to
+    /// [ManifestFormat::Json]
+    #[builder(default)]
+    manifest_format: ManifestFormat,
+    /// write the filemap to `$OUT_DIR/cache_buster_data.json`, keeping the
+    /// source tree clean, and point the `CACHE_BUSTER_FILEMAP` environment
+    /// variable of the crate at it, for [include_filemap][crate::include_filemap].
+    /// Only works in build scripts, can't be combined with
+    /// [manifest_path][Self::manifest_path]. Off by default
+    ///
+    /// ```rust,no_run
+    /// // build.rs
+    /// use cache_buster::BusterBuilder;
+    ///
+    /// let config = BusterBuilder::default()
+    ///     .source("./dist")
+    ///     .result("./prod")
+    ///     .follow_links(true)
+    ///     .out_dir(true)
+    ///     .build()
+    ///     .unwrap();
+    /// config.process().unwrap();
+    /// ```
+    #[builder(default)]
+    out_dir: bool,
+    /// also write compressed variants of processed files next to them, like
+    /// `main.<hash>.css.gz`, for servers to pick by `Accept-Encoding`.
+    /// Variants are recorded in the filemap, see
+    /// [Files::get_precompressed][crate::Files::get_precompressed]
+    #[builder(default)]
+    precompress: Vec<Precompression>,
+    /// only precompress files of these MIME types, like `text/*`. Every
+    /// processed file is precompressed when empty
+    #[builder(default)]
+    precompress_mime_types: Vec<mime::Mime>,
+    /// write resized and re-encoded variants of raster images, see
+    /// [images][crate::images]
+    #[cfg(feature = "images")]
+    #[builder(setter(custom), default)]
+    responsive_images: Option<ResponsiveImages>,
+    /// sanitize generated destination names. Off by default
+    #[builder(setter(into, strip_option), default)]
+    sanitize: Option<Sanitize>,
+    /// process assets shipped by dependency crates along with source, see
+    /// [deps][crate::deps]
+    #[builder(default)]
+    dependency_assets: Vec<DependencyAssets>,
+    /// write HTML pages to result with tags for hashed entry assets, see
+    /// [html][crate::html]
+    #[builder(default)]
+    html_pages: Vec<HtmlPage>,
+    /// protect source from being written to, guarding against outputs like
+    /// [codegen][Self::codegen] accidentally pointing into it. A result
+    /// directory inside source is always rejected. Off by default
+    #[builder(setter(into, strip_option), default)]
+    protect_source: Option<SourceProtection>,
+    /// write a diffable [Snapshot][crate::snapshot::Snapshot] of the
+    /// processing result to this path, for regression checks in CI
+    #[builder(setter(into, strip_option), default)]
+    snapshot: Option<String>,
+    /// cache size, modification time and digests of source files in this
+    /// file between builds. Unchanged files aren't hashed again, and aren't
+    /// copied again while their output of the last build is in place. Missing
+    /// parent directories are created, so build scripts can keep the cache out
+    /// of the source tree:
+    ///
+    /// ```ignore
+    /// // build.rs
+    /// .cache(format!("{}/cache-buster/cache.json", env::var("OUT_DIR").unwrap()))
+    /// ```
+    #[builder(setter(into, strip_option), default)]
+    cache: Option<String>,
+    /// absolute URL of the site, like `https://example.com`. Used for
+    /// [warm_list][Self::warm_list]: URLs are the base URL followed by
+    /// [prefix][Self::prefix] and paths inside the result directory when a
+    /// prefix is set, and by paths relative to the working directory otherwise
+    #[builder(setter(into, strip_option), default)]
+    base_url: Option<String>,
+    /// write absolute URLs of hashed files, one per line, to this path. Feed
+    /// it to `curl`, `wget` or proxy priming scripts to warm caches after a
+    /// deploy. Needs [base_url][Self::base_url]
+    #[builder(setter(into, strip_option), default)]
+    warm_list: Option<String>,
+    /// filemap keys of critical assets to preload, in order. Their `Link`
+    /// header values are recorded in the filemap, see
+    /// [Files::get_preload_links][crate::Files::get_preload_links], and
+    /// written to [preload_list][Self::preload_list]
+    #[builder(default)]
+    preload: Vec<String>,
+    /// write `Link` header values preloading [preload][Self::preload] assets
+    /// to this path, for servers and CDNs configured outside the program. A
+    /// JSON array when the path ends in `.json`, `Link: <value>` lines for
+    /// header files like Netlify's `_headers` otherwise
+    #[builder(setter(into, strip_option), default)]
+    preload_list: Option<String>,
+    /// filemap keys of small scripts and stylesheets inlined into pages.
+    /// Their Content-Security-Policy hashes are recorded in the filemap, see
+    /// [Files::get_csp_hash][crate::Files::get_csp_hash], so that policies
+    /// allowing them stay in sync with their contents
+    #[builder(default)]
+    inline: Vec<String>,
+    /// write an Apache `.htaccess` to result, caching hashed files for a
+    /// year as immutable and revalidating files excluded from hashing on
+    /// every use. Needs `mod_headers` and `mod_expires`. Rules match file
+    /// names, so this can't be combined with [HashPlacement::Directory],
+    /// where hashed files keep their names
+    #[builder(default)]
+    htaccess: bool,
+    /// write a `_headers` file to result, as read by Netlify and Cloudflare
+    /// Pages, caching hashed files for a year as immutable. Paths are URL
+    /// paths, [prefix][Self::prefix] included. Directories holding nothing
+    /// but hashed files get a single `/dir/*` rule, other hashed files one
+    /// rule each. Cloudflare Pages reads up to 100 rules, processing warns
+    /// when there are more
+    #[builder(default)]
+    headers_file: bool,
+    /// also write a manifest shaped like the one of webpack's
+    /// `webpack-manifest-plugin` to this path, for backend integrations that
+    /// already read it. It maps paths relative to result before hashing to
+    /// the URL paths [Files::get_url][crate::Files::get_url] returns:
+    ///
+    /// ```json
+    /// {
+    ///   "css/app.css": "/static/css/app.<hash>.css"
+    /// }
+    /// ```
+    #[builder(setter(into, strip_option), default)]
+    webpack_manifest: Option<String>,
 }
 
-impl<'a> BusterBuilder<'a> {
-    fn validate(&self) -> Result<(), String> {
-        for no_hash_configs in self.no_hash.iter() {
-            for no_hash in no_hash_configs.iter() {
-                if let NoHashCategory::FilePaths(files) = no_hash {
-                    for file in files.iter() {
-                        if !Path::new(&self.source.as_ref().unwrap())
-                            .join(file)
-                            .exists()
-                        {
-                            return Err(format!("File {} doesn't exist", file));
-                        }
-                    }
-                }
-            }
+/// Operations processing would perform, see [Buster::plan]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    /// filemap that would be written, keyed by original path
+    pub map: BTreeMap<String, Entry>,
+    /// copies that would be made, in order
+    pub copies: Vec<PlannedCopy>,
+    /// files that would be skipped, relative to source
+    pub skipped: Vec<String>,
+}
+
+/// Copy of a file from source to result, see [Plan]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedCopy {
+    /// path of the file in source
+    pub source: PathBuf,
+    /// path the file would be written to
+    pub destination: PathBuf,
+}
+
+/// Handling of files with unresolvable MIME types, see
+/// [BusterBuilder::unknown_mime]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownMimePolicy {
+    /// don't process them
+    Skip,
+    /// copy them over without a hash in their names, like files excluded with
+    /// [NoHashCategory]
+    CopyWithoutHash,
+    /// process them like any other file, regardless of
+    /// [mime_types][BusterBuilder::mime_types]
+    Hash,
+    /// fail processing
+    Error,
+}
+
+/// Handling of files that resolve outside of source through a symlink, see
+/// [BusterBuilder::external_links]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExternalLinks {
+    /// fail processing
+    #[default]
+    Error,
+    /// leave them out of the walk, with a warning
+    Skip,
+    /// process them like any other file
+    Allow,
+}
+
+/// Handling of unreadable entries in source, see
+/// [BusterBuilder::unreadable]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnreadablePolicy {
+    /// fail processing
+    #[default]
+    Fail,
+    /// leave them out, listing them in [ProcessReport::unreadable]
+    Skip,
+    /// [Skip][Self::Skip], with a warning
+    SkipWithWarning,
+}
+
+/// Handling of files with paths that aren't valid UTF-8, see
+/// [BusterBuilder::non_utf8]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonUtf8Policy {
+    /// fail processing
+    #[default]
+    Fail,
+    /// leave them out, reporting them as skipped
+    Skip,
+    /// [Skip][Self::Skip], with a warning
+    SkipWithWarning,
+}
+
+/// Handling of empty or missing source directories, see
+/// [BusterBuilder::empty_source]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptySource {
+    /// fail processing
+    Error,
+    /// process anyway, with a warning
+    #[default]
+    Warn,
+    /// process anyway
+    Allow,
+}
+
+/// Handling of dotfiles and files in dot-directories, see
+/// [BusterBuilder::include_hidden]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HiddenFiles {
+    /// process them like any other file
+    Process,
+    /// copy them over verbatim, keeping their paths and bypassing the MIME
+    /// filter, for paths like `.well-known/` that have to stay canonical
+    Copy,
+    /// leave them out of the walk
+    Ignore,
+}
+
+impl From<bool> for HiddenFiles {
+    fn from(include: bool) -> Self {
+        if include {
+            Self::Process
+        } else {
+            Self::Ignore
         }
-        Ok(())
     }
 }
 
-impl<'a> Buster<'a> {
-    // creates base_dir to output files to
-    fn init(&self) -> Result<(), Error> {
-        let res = Path::new(&self.result);
-        println!("cargo:rerun-if-changed={}", self.source);
-        if res.exists() {
-            fs::remove_dir_all(&self.result).unwrap();
-        }
+/// Files to process, see [BusterBuilder::allowlist]
+///
+/// Paths are relative to source, or `<name>/<path>` for
+/// [dependency assets][crate::deps].
+///
+/// ```rust
+/// use cache_buster::Allowlist;
+///
+/// let paths = Allowlist::Paths(vec!["css/main.css".into(), "img/logo.svg".into()]);
+/// // one path per line, blank lines and lines starting with `#` are ignored
+/// let file = Allowlist::File("./assets.allowlist".into());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Allowlist {
+    /// list of paths
+    Paths(Vec<String>),
+    /// file listing paths, one per line
+    File(String),
+}
 
-        fs::create_dir(&self.result).unwrap();
-        self.create_dir_structure(Path::new(&self.source))?;
-        Ok(())
+/// How files are placed in the result directory, see
+/// [BusterBuilder::link_strategy]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkStrategy {
+    /// copy files
+    #[default]
+    Copy,
+    /// hardlink files under their new names instead of duplicating their
+    /// contents. Falls back to copying when source and result are on
+    /// different file systems. Transformed files are always written
+    Hardlink,
+}
+
+/// Protection of the source directory against writes, see
+/// [BusterBuilder::protect_source]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceProtection {
+    /// fail before writing anything when the result directory, filemap or
+    /// any other output resolves inside source, or source inside them
+    Verify,
+    /// [Verify][Self::Verify], and also mark files in source read-only
+    /// while processing. Original permissions are restored when processing
+    /// returns or unwinds. A build that is killed, like with Ctrl-C or by
+    /// cargo cancelling the build script, or that aborts on panic with
+    /// `panic = "abort"`, leaves the files read-only: make them writable
+    /// again with `chmod -R u+w` on source. Prefer [Verify][Self::Verify]
+    /// where builds get interrupted
+    Enforce,
+}
+
+/// Predicate deciding which files are processed, see
+/// [BusterBuilder::filter]
+#[derive(Clone)]
+pub struct FileFilter(Arc<dyn Fn(&Path) -> bool + Send + Sync>);
+
+impl std::fmt::Debug for FileFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FileFilter")
     }
+}
 
-    fn hasher(payload: &[u8]) -> String {
-        use data_encoding::HEXUPPER;
-        use sha2::{Digest, Sha256};
-        let mut hasher = Sha256::new();
-        hasher.update(payload);
-        HEXUPPER.encode(&hasher.finalize())
+/// Callback receiving [ProcessEvent]s, see [BusterBuilder::observer]
+#[derive(Clone)]
+pub struct Observer(Arc<Mutex<dyn FnMut(ProcessEvent) + Send>>);
+
+impl std::fmt::Debug for Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Observer")
     }
+}
 
-    /// Processes files.
-    ///
-    /// Panics when a weird MIME is encountered.
-    pub fn process(&self) -> Result<(), Error> {
-        // panics when mimetypes are detected. This way you'll know which files are ignored
-        // from processing
+/// [AssetTransform] added with [BusterBuilder::transform]
+#[derive(Clone)]
+pub struct Transform(Arc<dyn AssetTransform>);
 
-        self.init()?;
-        let mut file_map: Files = Files::new(&self.result);
+impl std::fmt::Debug for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Transform")
+    }
+}
 
-        let mut process_worker = |path: &Path| {
-            let contents = Self::read_to_string(path).unwrap();
-            let hash = Self::hasher(&contents);
+/// [ImageEncoder] along with the widths and formats of variants, see
+/// [BusterBuilder::responsive_images]
+#[cfg(feature = "images")]
+#[derive(Clone)]
+pub struct ResponsiveImages {
+    encoder: Arc<dyn ImageEncoder>,
+    widths: Vec<u32>,
+    formats: Vec<ImageFormat>,
+}
 
-            let get_name = |no_hash: bool| -> String {
-                if no_hash {
-                    format!(
-                        "{}.{}",
-                        path.file_stem().unwrap().to_str().unwrap(),
-                        path.extension().unwrap().to_str().unwrap()
-                    )
-                } else {
-                    format!(
-                        "{}.{}.{}",
-                        path.file_stem().unwrap().to_str().unwrap(),
-                        hash,
-                        path.extension().unwrap().to_str().unwrap()
-                    )
-                }
-            };
+#[cfg(feature = "images")]
+impl std::fmt::Debug for ResponsiveImages {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponsiveImages")
+            .field("widths", &self.widths)
+            .field("formats", &self.formats)
+            .finish_non_exhaustive()
+    }
+}
 
-            let no_hash_status = self.no_hash.iter().any(|no_hash| {
-                match no_hash {
-                    NoHashCategory::FilePaths(paths) => {
-                        let no_hash_status = paths
-                            .iter()
-                            .any(|file_path| Path::new(&self.source).join(&file_path) == path);
-                        no_hash_status
-                    }
-                    NoHashCategory::FileExtentions(extensions) => {
-                        let mut no_hash_status = false;
-                        if let Some(cur_extention) = path.extension() {
-                            // .unwrap().to_str().unwrap();
-                            if let Some(cur_extention) = cur_extention.to_str() {
-                                no_hash_status = extensions.iter().any(|ext| &cur_extention == ext);
-                            }
-                        }
-                        no_hash_status
-                    }
-                }
-            });
+/// [NameMangler] set with [BusterBuilder::name_mangler]
+#[derive(Clone)]
+pub struct Mangler(Arc<dyn NameMangler>);
 
-            let new_name = get_name(no_hash_status);
+impl std::fmt::Debug for Mangler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Mangler")
+    }
+}
 
-            self.copy(path, &new_name);
-            let (source, destination) = self.gen_map(path, &new_name);
-            let _ = file_map.add(
-                source.to_str().unwrap().into(),
-                destination.to_str().unwrap().into(),
-            );
-        };
+// a file that is to be processed
+struct Candidate {
+    path: PathBuf,
+    // path relative to result, before naming
+    rel: PathBuf,
+    // filemap key
+    key: String,
+    size: u64,
+    // modification time, for the build cache
+    modified: Option<SystemTime>,
+    mime: Option<String>,
+    // destination relative to result in the last build, set when digests
+    // were taken from the build cache
+    cached: Option<PathBuf>,
+    // None for files that weren't read, see Candidate::read
+    digests: Option<Digests>,
+    // contents to write in place of the source file, set when an
+    // AssetTransform applies
+    contents: Option<Vec<u8>>,
+    no_hash: bool,
+    output_name: Option<String>,
+}
 
-        for entry in WalkDir::new(&self.source)
-            .follow_links(self.follow_links)
+// files picked for processing along with their destinations relative to
+// result, and the filemap describing them
+struct Schedule {
+    file_map: Files,
+    candidates: Vec<Candidate>,
+    destinations: Vec<PathBuf>,
+    // files derived from candidates, like image variants: path of the
+    // candidate in source, destination relative to result and contents
+    derived: Vec<(PathBuf, PathBuf, Vec<u8>)>,
+    // paths of skipped files in source
+    skipped: Vec<PathBuf>,
+    // paths of unreadable entries in source
+    unreadable: Vec<PathBuf>,
+}
+
+impl Candidate {
+    // hash of file contents, every hashed file is read
+    fn hash(&self) -> &str {
+        &self.digests.as_ref().unwrap().hash
+    }
+
+    // is the hash part of the generated path?
+    fn hashed(&self) -> bool {
+        !self.no_hash && self.output_name.is_none()
+    }
+}
+
+impl BusterBuilder {
+    /// source directories to merge into a single result directory and
+    /// filemap, like `vec!["./dist", "./static/icons"]`
+    pub fn sources<I, S>(&mut self, sources: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.sources = Some(strings(sources));
+        self
+    }
+
+    /// MIME types of extensions, like `("webmanifest", "application/manifest+json")`,
+    /// overriding guessed ones
+    pub fn mime_overrides<I, S>(&mut self, overrides: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (S, mime::Mime)>,
+        S: Into<String>,
+    {
+        let overrides = overrides
             .into_iter()
-        {
-            let entry = entry?;
+            .map(|(ext, mime)| (ext.into(), mime))
+            .collect();
+        self.mime_overrides = Some(overrides);
+        self
+    }
 
-            let path = entry.path();
-            if !path.is_dir() {
-                let path = Path::new(&path);
-
-                match self.mime_types.as_ref() {
-                    Some(mime_types) => {
-                        for mime_type in mime_types.iter() {
-                            let file_mime =
-                                mime_guess::from_path(path).first().unwrap_or_else(|| {
-                                    panic!("couldn't resolve MIME for file: {:?}", &path)
-                                });
-                            if &file_mime == mime_type {
-                                process_worker(path);
-                            }
-                        }
-                    }
-                    None => process_worker(path),
-                }
-            }
-        }
+    /// only process files matching one of these globs, like `**/*.css`
+    pub fn include_globs<I, S>(&mut self, globs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.include_globs = Some(strings(globs));
+        self
+    }
 
-        file_map.to_env();
-        Ok(())
+    /// skip files matching one of these globs, like `**/vendor/**`
+    pub fn exclude_globs<I, S>(&mut self, globs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude_globs = Some(strings(globs));
+        self
     }
 
-    // helper fn to read file to string
-    fn read_to_string(path: &Path) -> Result<Vec<u8>, Error> {
-        use std::fs::File;
-        use std::io::Read;
+    /// prune these directories from the walk, like `node_modules` or `docs/drafts`
+    pub fn exclude_dirs<I, S>(&mut self, dirs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude_dirs = Some(strings(dirs));
+        self
+    }
 
-        let mut file_content = Vec::new();
-        let mut file = File::open(path)?;
-        file.read_to_end(&mut file_content).expect("Unable to read");
-        Ok(file_content)
+    /// explicit output names for particular files, as `(source path, output name)`
+    pub fn output_names<I, S, T>(&mut self, output_names: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (S, T)>,
+        S: Into<String>,
+        T: Into<String>,
+    {
+        let output_names = output_names
+            .into_iter()
+            .map(|(file, name)| (file.into(), name.into()))
+            .collect();
+        self.output_names = Some(output_names);
+        self
     }
 
-    // helper fn to generate filemap
-    fn gen_map<'b>(&self, source: &'b Path, name: &str) -> (&'b Path, PathBuf) {
-        let rel_location = source.strip_prefix(&self.source).unwrap().parent().unwrap();
-        if let Some(prefix) = &self.prefix {
-            //panic!("{}", &prefix);
-            let mut result = self.result.as_str();
-            if result.starts_with('/') {
-                result = &self.result[1..];
-            }
-            let destination = Path::new(prefix)
-                .join(&result)
-                .join(rel_location)
-                .join(name);
-
-            (source, destination)
-        } else {
-            let destination = Path::new(&self.result).join(rel_location).join(name);
-            (source, destination)
-        }
+    /// extensions made up of several parts, like `min.js` or `tar.gz`, that
+    /// should be kept together when inserting the hash
+    pub fn compound_extensions<I, S>(&mut self, extensions: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.compound_extensions = Some(strings(extensions));
+        self
+    }
+
+    /// names of asset variants, like `light` and `dark`
+    pub fn variants<I, S>(&mut self, variants: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.variants = Some(strings(variants));
+        self
     }
 
-    // helper fn to copy files
-    fn copy(&self, source: &Path, name: &str) {
-        let rel_location = source.strip_prefix(&self.source).unwrap().parent().unwrap();
-        let destination = Path::new(&self.result).join(rel_location).join(name);
-        fs::copy(source, &destination).unwrap();
+    /// only process files for which `filter` returns true, for selection
+    /// logic the other filters can't express:
+    ///
+    /// ```rust
+    /// use cache_buster::BusterBuilder;
+    ///
+    /// let config = BusterBuilder::default()
+    ///     .source("./dist")
+    ///     .result("./prod")
+    ///     .follow_links(true)
+    ///     .filter(|path| path.metadata().is_ok_and(|m| m.len() < 1024 * 1024))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn filter<F>(&mut self, filter: F) -> &mut Self
+    where
+        F: Fn(&Path) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Some(FileFilter(Arc::new(filter))));
+        self
+    }
+
+    /// generate output paths of hashed files with `mangler` instead of the
+    /// built-in scheme. [hash_placement][Self::hash_placement] and
+    /// [Sanitize::Transliterate] don't apply to mangled paths
+    pub fn name_mangler<M>(&mut self, mangler: M) -> &mut Self
+    where
+        M: NameMangler + 'static,
+    {
+        self.name_mangler = Some(Some(Mangler(Arc::new(mangler))));
+        self
+    }
+
+    /// write variants of every raster image encoded with `encoder` in each of
+    /// `formats`, in the size of the original and resized to each of
+    /// `widths`. See [images][crate::images]
+    #[cfg(feature = "images")]
+    pub fn responsive_images<E, W, F>(&mut self, encoder: E, widths: W, formats: F) -> &mut Self
+    where
+        E: ImageEncoder + 'static,
+        W: IntoIterator<Item = u32>,
+        F: IntoIterator<Item = ImageFormat>,
+    {
+        self.responsive_images = Some(Some(ResponsiveImages {
+            encoder: Arc::new(encoder),
+            widths: widths.into_iter().collect(),
+            formats: formats.into_iter().collect(),
+        }));
+        self
+    }
+
+    /// rewrite contents of files matching `target` with `transform` before
+    /// they are hashed. Can be called multiple times, transforms run in the
+    /// order they were added. See [transform][crate::transform]
+    pub fn transform<T>(&mut self, target: impl Into<TransformTarget>, transform: T) -> &mut Self
+    where
+        T: AssetTransform + 'static,
+    {
+        self.transforms
+            .get_or_insert_with(Vec::new)
+            .push((target.into(), Transform(Arc::new(transform))));
+        self
+    }
+
+    /// call `observer` with every [ProcessEvent] while processing, to
+    /// surface progress in build tooling without setting up a channel
+    ///
+    /// ```rust
+    /// use cache_buster::{BusterBuilder, ProcessEvent};
+    ///
+    /// let config = BusterBuilder::default()
+    ///     .source("./dist")
+    ///     .result("./prod")
+    ///     .follow_links(true)
+    ///     .observer(|event| {
+    ///         if let ProcessEvent::Hashed { source, .. } = event {
+    ///             eprintln!("hashed {:?}", source);
+    ///         }
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn observer<F>(&mut self, observer: F) -> &mut Self
+    where
+        F: FnMut(ProcessEvent) + Send + 'static,
+    {
+        self.observer = Some(Some(Observer(Arc::new(Mutex::new(observer)))));
+        self
+    }
+
+    // source directories set so far
+    fn source_dirs(&self) -> Vec<&str> {
+        let source = self.source.iter().filter(|source| !source.is_empty());
+        source
+            .chain(self.sources.iter().flatten())
+            .map(|source| source.as_str())
+            .collect()
+    }
+
+    // is file, relative to source, present in any source directory?
+    fn exists_in_source(&self, file: &str) -> bool {
+        self.source_dirs()
+            .iter()
+            .any(|source| Path::new(source).join(file).exists())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.source_dirs().is_empty() {
+            return Err("source or sources must be set".into());
+        }
+        for source in self.source_dirs() {
+            if !Path::new(source).is_dir() && self.empty_source != Some(EmptySource::Allow) {
+                return Err(format!("Source {} doesn't exist", source));
+            }
+        }
+        if let Some(result) = self.result.as_ref() {
+            let resolved = protect::resolve(Path::new(result)).map_err(|e| e.to_string())?;
+            let home = std::env::var_os("HOME").map(PathBuf::from);
+            if resolved.parent().is_none() || Some(&resolved) == home.as_ref() {
+                return Err(format!("Result {} can't be {}", result, resolved.display()));
+            }
+            for source in self.source_dirs() {
+                let source_dir = protect::resolve(Path::new(source)).map_err(|e| e.to_string())?;
+                if resolved == source_dir {
+                    return Err(format!("Result {} is source {}", result, source));
+                }
+                if resolved.starts_with(&source_dir) {
+                    return Err(format!(
+                        "Result {} is inside source {}, output would be processed again",
+                        result, source
+                    ));
+                }
+            }
+        }
+        for no_hash_configs in self.no_hash.iter() {
+            for no_hash in no_hash_configs.iter() {
+                if let NoHashCategory::FilePaths(files) = no_hash {
+                    for file in files.iter() {
+                        if !self.exists_in_source(file) {
+                            return Err(format!("File {} doesn't exist", file));
+                        }
+                    }
+                }
+            }
+        }
+        let no_hash_globs = self
+            .no_hash
+            .iter()
+            .flatten()
+            .filter_map(|no_hash| match no_hash {
+                NoHashCategory::Globs(globs) => Some(globs),
+                _ => None,
+            });
+        for globs in [&self.include_globs, &self.exclude_globs]
+            .into_iter()
+            .flatten()
+            .chain(no_hash_globs)
+        {
+            for glob in globs.iter() {
+                if let Err(e) = Glob::new(glob) {
+                    return Err(format!("Invalid glob {}: {}", glob, e));
+                }
+            }
+        }
+        let format = self.manifest_format.unwrap_or_default();
+        if format != ManifestFormat::Json && matches!(self.manifest_compression, Some(Some(_))) {
+            return Err("Filemaps in other formats than JSON can't be compressed".into());
+        }
+        if !format.loadable() && self.out_dir == Some(true) {
+            return Err(
+                "Only filemaps in formats Files loads, JSON and CBOR, can be written to OUT_DIR"
+                    .into(),
+            );
+        }
+        if self.out_dir == Some(true) {
+            if std::env::var_os("OUT_DIR").is_none() {
+                return Err("OUT_DIR isn't set, out_dir only works in build scripts".into());
+            }
+            if matches!(self.manifest_path, Some(Some(_))) {
+                return Err("out_dir and manifest_path can't be combined".into());
+            }
+        }
+        if let Some(Some(prefix)) = self.prefix.as_ref() {
+            if prefix.contains(['?', '#', '\\'])
+                || prefix
+                    .split('/')
+                    .any(|segment| segment == "." || segment == "..")
+            {
+                return Err(format!("Prefix {} must be a plain route", prefix));
+            }
+        }
+        if let (Some(Some(min_size)), Some(Some(max_size))) = (self.min_size, self.max_size) {
+            if min_size > max_size {
+                return Err(format!(
+                    "min_size {} is larger than max_size {}",
+                    min_size, max_size
+                ));
+            }
+        }
+        if self.rewrite_js == Some(true) && self.hash_placement == Some(HashPlacement::Directory) {
+            return Err("rewrite_js can't be combined with HashPlacement::Directory".into());
+        }
+        if self.htaccess == Some(true) && self.hash_placement == Some(HashPlacement::Directory) {
+            return Err("htaccess can't be combined with HashPlacement::Directory".into());
+        }
+        if let Some(Some(0)) = self.threads {
+            return Err("threads must be positive".into());
+        }
+        #[cfg(feature = "jwalk")]
+        if self.parallel_walk == Some(true) && self.respect_ignore_files == Some(true) {
+            return Err("parallel_walk can't be combined with respect_ignore_files".into());
+        }
+        if let Some(Some(0)) = self.copy_buffer_size {
+            return Err("copy_buffer_size must be positive".into());
+        }
+        if let Some(Some(0)) = self.chunk_size {
+            return Err("chunk_size must be positive".into());
+        }
+        #[cfg(unix)]
+        let preserve =
+            self.preserve_metadata == Some(true) || self.preserve_ownership == Some(true);
+        #[cfg(not(unix))]
+        let preserve = self.preserve_metadata == Some(true);
+        if preserve && self.reproducible == Some(true) {
+            return Err("preserve_metadata can't be combined with reproducible".into());
+        }
+        if self.link_strategy == Some(LinkStrategy::Hardlink) && self.reproducible == Some(true) {
+            return Err("LinkStrategy::Hardlink can't be combined with reproducible".into());
+        }
+        #[cfg(unix)]
+        if let Some(Some(store)) = &self.store {
+            if preserve || self.link_strategy == Some(LinkStrategy::Hardlink) {
+                return Err(
+                    "store can't be combined with preserve_metadata or LinkStrategy::Hardlink"
+                        .into(),
+                );
+            }
+            if let Some(result) = &self.result {
+                if Path::new(store).starts_with(result) {
+                    return Err("store can't be inside result".into());
+                }
+            }
+        }
+        if matches!(self.warm_list, Some(Some(_))) && !matches!(self.base_url, Some(Some(_))) {
+            return Err("warm_list needs base_url".into());
+        }
+        if let Some(output_names) = self.output_names.as_ref() {
+            for (file, _) in output_names.iter() {
+                if !self.exists_in_source(file) {
+                    return Err(format!("File {} doesn't exist", file));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Buster {
+    // creates base_dir to output files to, returns files of previous runs
+    // that are kept, newest run first. Files in reused are left in place
+    fn init(&self, reused: &BTreeSet<PathBuf>) -> Result<Vec<Vec<String>>, Error> {
+        let res = Path::new(&self.result);
+        for (root, _) in self.roots() {
+            println!("cargo:rerun-if-changed={}", root.display());
+        }
+        let mut generations = Vec::new();
+        if self.clean && res.exists() {
+            generations = self.remove_previous(reused)?;
+        }
+
+        fs::create_dir_all(&self.result).map_err(CacheBusterError::file(&self.result))?;
+        let marker = res.join(RESULT_MARKER);
+        if !marker.exists() {
+            fs::write(&marker, "").map_err(CacheBusterError::file(marker))?;
+        }
+        // missing sources are allowed with EmptySource::Allow
+        for source in self.sources() {
+            if Path::new(source).is_dir() {
+                self.create_dir_structure(Path::new(source), Path::new(source))?;
+            }
+        }
+        Ok(generations)
+    }
+
+    // helper fn to remove files of previous runs recorded in the filemap,
+    // along with directories that are left empty. Files of the last
+    // keep_generations runs are kept and returned, newest run first. Files
+    // in reused are left in place
+    fn remove_previous(&self, reused: &BTreeSet<PathBuf>) -> Result<Vec<Vec<String>>, Error> {
+        let manifest = self.manifest();
+        let mut manifests = vec![manifest.to_string()];
+        if let Some(compression) = self.manifest_compression {
+            manifests.insert(0, compression.path_for(&manifest));
+        }
+        let previous = match manifests.iter().find(|path| Path::new(path).exists()) {
+            Some(path) => self.manifest_format.deserialize(&fs::read(path)?)?,
+            None => return Ok(Vec::new()),
+        };
+        let result = Path::new(&self.result);
+        let last: Vec<String> = previous
+            .entries()
+            .flat_map(|(_, entry)| {
+                std::iter::once(&entry.path)
+                    .chain(entry.precompressed.values())
+                    .chain(entry.images.iter().map(|image| &image.path))
+            })
+            .filter(|path| Path::new(path).starts_with(result))
+            .cloned()
+            .collect();
+        let mut generations = vec![last];
+        generations.extend(previous.generations().iter().cloned());
+        let expired = generations.split_off(self.keep_generations.min(generations.len()));
+        let kept: BTreeSet<&String> = generations.iter().flatten().collect();
+
+        let removed: Vec<PathBuf> = expired
+            .iter()
+            .flatten()
+            .filter(|path| !kept.contains(path))
+            .map(|path| PathBuf::from(self.disk_path(path)))
+            .filter(|path| path.is_file() && !reused.contains(path))
+            .collect();
+        if !removed.is_empty() && !self.force && !result.join(RESULT_MARKER).exists() {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "{} lacks {}, refusing to remove files from it. Set force to clean it anyway",
+                    result.display(),
+                    RESULT_MARKER
+                ),
+            ));
+        }
+        for path in removed {
+            fs::remove_file(&path)?;
+            for dir in path.ancestors().skip(1) {
+                if dir == result || fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(generations)
+    }
+
+    /// Naming options of this configuration, for use with
+    /// [hash_file][crate::hash_file]
+    pub fn hash_options(&self) -> naming::HashOptions {
+        naming::HashOptions {
+            compound_extensions: self.compound_extensions.clone(),
+            sanitize: self.sanitize,
+        }
+    }
+
+    /// Processes files, returning a [ProcessReport] of what was done.
+    ///
+    /// Fails with [CacheBusterError::UnknownMime] when a weird MIME is
+    /// encountered while filtering by [mime_types][BusterBuilder::mime_types],
+    /// unless a [policy][BusterBuilder::unknown_mime] for those is set.
+    pub fn process(&self) -> Result<ProcessReport, CacheBusterError> {
+        Ok(self.run()?)
+    }
+
+    /// Processes files like [process][Self::process], for build
+    /// orchestrators running on tokio. This is a wrapper running
+    /// [process][Self::process] on a clone of the configuration on tokio's
+    /// blocking thread pool, not async I/O: files are read and written with
+    /// blocking calls that occupy one blocking thread for the whole run,
+    /// while tasks on the async workers carry on in the meantime:
+    ///
+    /// ```no_run
+    /// # async fn build() -> Result<(), cache_buster::CacheBusterError> {
+    /// use cache_buster::BusterBuilder;
+    ///
+    /// let config = BusterBuilder::default()
+    ///     .source("./dist")
+    ///     .result("./prod")
+    ///     .follow_links(true)
+    ///     .build()
+    ///     .unwrap();
+    /// let report = config.process_async().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn process_async(&self) -> Result<ProcessReport, CacheBusterError> {
+        let config = self.clone();
+        match tokio::task::spawn_blocking(move || config.run()).await {
+            Ok(report) => Ok(report?),
+            Err(e) => match e.try_into_panic() {
+                Ok(panic) => std::panic::resume_unwind(panic),
+                Err(e) => Err(CacheBusterError::Io(Error::other(e))),
+            },
+        }
+    }
+
+    /// Processes files like [process][Self::process], merging the resulting
+    /// filemap into `files` and writing the combined filemap. Chain several
+    /// configurations, starting with an empty filemap, for one filemap
+    /// covering assets of all of them:
+    ///
+    /// ```no_run
+    /// use cache_buster::{BusterBuilder, Files};
+    ///
+    /// let mut files = Files::default();
+    /// for (source, result) in [("./dist", "./prod/app"), ("./icons", "./prod/icons")] {
+    ///     let config = BusterBuilder::default()
+    ///         .source(source)
+    ///         .result(result)
+    ///         .follow_links(true)
+    ///         .build()
+    ///         .unwrap();
+    ///     config.process_into(&mut files).unwrap();
+    /// }
+    /// // relative to ./prod, which contains both result directories
+    /// files.get("./icons/github.svg");
+    /// ```
+    ///
+    /// Configurations sharing a result directory need
+    /// [clean(false)][BusterBuilder::clean] to keep files of earlier ones.
+    /// Fails when a file is in both filemaps, when their route prefixes
+    /// differ, or when their base directories have nothing in common, like a
+    /// relative and an absolute one.
+    pub fn process_into(
+        &self,
+        files: &mut crate::Files,
+    ) -> Result<ProcessReport, CacheBusterError> {
+        Ok(self.run_into(Some(files))?)
+    }
+
+    /// Work out what [process][Self::process] would do, without writing
+    /// anything. Source is read to hash files, result isn't touched.
+    pub fn plan(&self) -> Result<Plan, CacheBusterError> {
+        let Schedule {
+            file_map,
+            candidates,
+            destinations,
+            ..
+        } = self.schedule(&self.build_cache())?;
+        let copies = candidates
+            .into_iter()
+            .zip(destinations)
+            .map(|(candidate, rel_destination)| PlannedCopy {
+                source: candidate.path,
+                destination: Path::new(&self.result).join(rel_destination),
+            })
+            .collect();
+        Ok(Plan {
+            map: file_map.map,
+            copies,
+            skipped: file_map.skipped.into_iter().collect(),
+        })
+    }
+
+    // processes files
+    pub(crate) fn run(&self) -> Result<ProcessReport, Error> {
+        self.run_into(None)
+    }
+
+    // processes files, merging the filemap into `into` if given
+    fn run_into(&self, into: Option<&mut crate::Files>) -> Result<ProcessReport, Error> {
+        let start = Instant::now();
+        self.check_result()?;
+        let manifest = self.manifest();
+        let guard = match self.protect_source {
+            Some(protection) => {
+                let mut outputs = vec![self.result.as_str(), &manifest];
+                outputs.extend(self.codegen.as_deref());
+                outputs.extend(self.codegen_map.as_deref());
+                outputs.extend(self.js_manifest.as_deref());
+                outputs.extend(self.webpack_manifest.as_deref());
+                outputs.extend(self.snapshot.as_deref());
+                outputs.extend(self.cache.as_deref());
+                outputs.extend(self.warm_list.as_deref());
+                outputs.extend(self.preload_list.as_deref());
+                #[cfg(unix)]
+                outputs.extend(self.store.as_deref());
+                for source in self.sources() {
+                    protect::check(source, &outputs)?;
+                }
+                match protection {
+                    SourceProtection::Enforce => Some(protect::ReadOnlyGuard::new(
+                        &self.sources(),
+                        self.follow_links,
+                    )?),
+                    SourceProtection::Verify => None,
+                }
+            }
+            None => None,
+        };
+        let _locks = self.lock()?;
+        let cache = self.build_cache();
+        let Schedule {
+            mut file_map,
+            candidates,
+            destinations,
+            derived,
+            skipped,
+            unreadable,
+        } = self.schedule(&cache)?;
+        // fail on unknown keys before result is touched
+        for key in self.preload.iter() {
+            if !file_map.map.contains_key(key) {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("preloaded asset {} isn't in the filemap", key),
+                ));
+            }
+        }
+        for key in self.inline.iter() {
+            if !file_map.map.contains_key(key) {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("inlined asset {} isn't in the filemap", key),
+                ));
+            }
+        }
+        let copies: Vec<(&Candidate, &PathBuf)> =
+            candidates.iter().zip(destinations.iter()).collect();
+        let reused: BTreeSet<PathBuf> = copies
+            .iter()
+            .filter(|(candidate, rel_destination)| self.reusable(candidate, rel_destination))
+            .flat_map(|(candidate, rel_destination)| self.outputs(candidate, rel_destination))
+            .collect();
+        file_map.generations = self.init(&reused)?;
+        self.par_map(&copies, |(candidate, rel_destination)| {
+            let destination = Path::new(&self.result).join(rel_destination);
+            if !reused.contains(&destination) {
+                self.copy(
+                    &candidate.path,
+                    candidate.contents.as_deref(),
+                    rel_destination,
+                    guard.as_ref(),
+                )?;
+                if self.precompresses(candidate) {
+                    self.write_precompressed(&destination)?;
+                }
+                #[cfg(unix)]
+                self.store_outputs(&self.outputs(candidate, rel_destination))?;
+            }
+            self.emit(ProcessEvent::Copied {
+                source: candidate.path.clone(),
+                destination,
+            });
+            Ok(())
+        })?
+        .into_iter()
+        .collect::<Result<(), Error>>()?;
+
+        let mut report = ProcessReport::default();
+        for (candidate, rel_destination) in copies {
+            let destination = Path::new(&self.result).join(rel_destination);
+            if reused.contains(&destination) {
+                report.unchanged += 1;
+            }
+            report.bytes += candidate.size;
+            let outcome = if candidate.hashed() {
+                report.hashed += 1;
+                Outcome::Hashed { destination }
+            } else {
+                report.copied += 1;
+                Outcome::Copied { destination }
+            };
+            report.files.push(FileReport {
+                source: candidate.path.clone(),
+                outcome,
+            });
+        }
+        for (source, rel_destination, contents) in derived.iter() {
+            self.copy(source, Some(contents), rel_destination, None)?;
+            #[cfg(unix)]
+            self.store_outputs(&[Path::new(&self.result).join(rel_destination)])?;
+        }
+        // files of dropped generations were removed by init
+        #[cfg(unix)]
+        if let Some(store) = &self.store {
+            Store::new(store).gc_unlinked()?;
+        }
+        report.skipped = skipped.len();
+        report.unreadable = unreadable;
+        report
+            .files
+            .extend(skipped.into_iter().map(|source| FileReport {
+                source,
+                outcome: Outcome::Skipped,
+            }));
+
+        file_map.preload = self.preload.clone();
+        if let Some(into) = into {
+            let mut merged = Files::from(into.clone());
+            merged.merge(file_map)?;
+            file_map = merged;
+            *into = file_map.clone().into();
+        }
+        report.manifest =
+            file_map.to_env(&manifest, self.manifest_compression, self.manifest_format)?;
+        if self.out_dir {
+            println!(
+                "cargo:rustc-env={}={}",
+                FILEMAP_ENV,
+                report.manifest.display()
+            );
+        }
+        if let Some(codegen) = &self.codegen {
+            self.write_codegen(&candidates, &file_map, codegen)?;
+        }
+        if let Some(codegen_map) = &self.codegen_map {
+            let entries: Vec<(String, String)> = file_map
+                .map
+                .iter()
+                .map(|(source, entry)| (source.clone(), entry.path.clone()))
+                .collect();
+            fs::write(
+                codegen_map,
+                codegen::static_map(&file_map.base_dir, &entries),
+            )
+            .map_err(CacheBusterError::file(codegen_map))?;
+        }
+        for page in self.html_pages.iter() {
+            self.write_html_page(page, &file_map)?;
+        }
+        if let Some(js_manifest) = &self.js_manifest {
+            let entries: Vec<(String, String)> = file_map
+                .map
+                .iter()
+                .map(|(source, entry)| {
+                    let rel = &entry.path[file_map.base_dir.len()..];
+                    let url = crate::filemap::prefixed(file_map.prefix.as_deref(), rel);
+                    (source.clone(), url)
+                })
+                .collect();
+            let typed = js_manifest.ends_with(".ts");
+            fs::write(js_manifest, codegen::js_module(&entries, typed))
+                .map_err(CacheBusterError::file(js_manifest))?;
+        }
+        if let Some(snapshot) = &self.snapshot {
+            self.write_snapshot(&candidates, snapshot)?;
+        }
+        if let Some(warm_list) = &self.warm_list {
+            self.write_warm_list(&candidates, &file_map, warm_list)?;
+        }
+        if self.htaccess {
+            let (immutable, no_cache) = Self::cache_classes(&candidates, &file_map);
+            let file_names = |paths: &BTreeSet<String>| -> BTreeSet<String> {
+                paths
+                    .iter()
+                    .filter_map(|path| path.rsplit('/').next())
+                    .map(|name| self.disk_path(name))
+                    .collect()
+            };
+            let htaccess = headers::htaccess(&file_names(&immutable), &file_names(&no_cache));
+            let path = Path::new(&self.result).join(".htaccess");
+            fs::write(&path, htaccess).map_err(CacheBusterError::file(&path))?;
+        }
+        if let Some(preload_list) = &self.preload_list {
+            Self::write_preload_list(&file_map, preload_list)?;
+        }
+        if let Some(webpack_manifest) = &self.webpack_manifest {
+            Self::write_webpack_manifest(&candidates, &file_map, webpack_manifest)?;
+        }
+        // last, so that every other file written to result is known
+        if self.headers_file {
+            let (immutable, _) = Self::cache_classes(&candidates, &file_map);
+            let rules: BTreeSet<String> = self
+                .headers_rules(&immutable)?
+                .iter()
+                .map(|rule| crate::filemap::prefixed(file_map.prefix.as_deref(), rule))
+                .collect();
+            let path = Path::new(&self.result).join("_headers");
+            if rules.len() > headers::MAX_RULES {
+                println!(
+                    "cargo:warning={} has {} rules, Cloudflare Pages only reads the first {}",
+                    path.display(),
+                    rules.len(),
+                    headers::MAX_RULES
+                );
+            }
+            fs::write(&path, headers::headers_file(&rules))
+                .map_err(CacheBusterError::file(&path))?;
+        }
+        if let Some(path) = &self.cache {
+            self.write_build_cache(&candidates, &destinations, path)?;
+        }
+        self.emit(ProcessEvent::Finished {
+            total: candidates.len(),
+        });
+        report.duration = start.elapsed();
+        Ok(report)
+    }
+
+    // helper fn to walk sources, pick files to process and work out their
+    // destinations and filemap entries, without touching result
+    fn schedule(&self, cache: &BuildCache) -> Result<Schedule, Error> {
+        let mut allowed = self.allowed()?;
+        let mut file_map: Files = Files::new(&self.result);
+        file_map.prefix = self.route_prefix();
+        let include = Self::glob_set(&self.include_globs);
+        let exclude = Self::glob_set(&self.exclude_globs);
+        let no_hash_globs: Vec<String> = self
+            .no_hash
+            .iter()
+            .filter_map(|no_hash| match no_hash {
+                NoHashCategory::Globs(globs) => Some(globs),
+                _ => None,
+            })
+            .flatten()
+            .cloned()
+            .collect();
+        let no_hash_globs = Self::glob_set(&no_hash_globs);
+
+        // paths of picked files along with their paths relative to result
+        // and filemap keys
+        let mut selected: Vec<(PathBuf, PathBuf, String)> = Vec::new();
+        let mut skipped = Vec::new();
+        let mut unreadable = Vec::new();
+        // path relative to result of every candidate, to catch clashes
+        // between source directories
+        let mut claimed: BTreeMap<PathBuf, String> = BTreeMap::new();
+        for (root, dependency) in self.roots() {
+            for path in self.source_files(root, &mut unreadable)? {
+                let path = path.as_path();
+                let rel = path.strip_prefix(root).unwrap();
+                if path.to_str().is_none() {
+                    match self.non_utf8 {
+                        NonUtf8Policy::Fail => {
+                            return Err(CacheBusterError::NonUtf8Path(path.to_path_buf()).into())
+                        }
+                        NonUtf8Policy::SkipWithWarning => println!(
+                            "cargo:warning=skipping {}, path isn't valid UTF-8",
+                            path.display()
+                        ),
+                        NonUtf8Policy::Skip => (),
+                    }
+                    skipped.push(path.to_path_buf());
+                    self.emit(ProcessEvent::Skipped {
+                        source: path.to_path_buf(),
+                    });
+                    continue;
+                }
+                let (rel, key) = match dependency {
+                    Some(name) => (
+                        Path::new(name).join(rel),
+                        format!("@{}/{}", name, slashed(rel)),
+                    ),
+                    None => (rel.to_path_buf(), slashed(path)),
+                };
+                if self.unreadable != UnreadablePolicy::Fail
+                    && fs::File::open(path).is_err()
+                    && self.skip_unreadable(path)
+                {
+                    unreadable.push(path.to_path_buf());
+                    continue;
+                }
+
+                let listed = allowed.as_mut().is_none_or(|allowed| allowed.remove(&rel));
+                if !listed {
+                    println!("cargo:warning=not in allowlist: {}", path.display());
+                }
+
+                if listed && self.selected(path, &rel, &include, &exclude)? {
+                    if let Some(other) = claimed.insert(rel.clone(), key.clone()) {
+                        return Err(Error::new(
+                            ErrorKind::AlreadyExists,
+                            format!("{} and {} both map to {}", other, key, rel.display()),
+                        ));
+                    }
+                    selected.push((path.to_path_buf(), rel, key));
+                } else {
+                    file_map.skipped.insert(slashed(&rel));
+                    skipped.push(path.to_path_buf());
+                    self.emit(ProcessEvent::Skipped {
+                        source: path.to_path_buf(),
+                    });
+                }
+            }
+        }
+        if let Some(missing) = allowed.filter(|allowed| !allowed.is_empty()) {
+            let missing: Vec<String> = missing
+                .iter()
+                .map(|rel| rel.display().to_string())
+                .collect();
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("allowlisted files not found: {}", missing.join(", ")),
+            ));
+        }
+        let mut candidates = self
+            .par_map(&selected, |(path, rel, key)| {
+                let candidate =
+                    self.prepare(path, rel.clone(), key.clone(), &no_hash_globs, cache)?;
+                if let Some(digests) = &candidate.digests {
+                    self.emit(ProcessEvent::Hashed {
+                        source: candidate.path.clone(),
+                        hash: digests.hash.clone(),
+                    });
+                }
+                Ok(candidate)
+            })?
+            .into_iter()
+            .collect::<Result<Vec<Candidate>, Error>>()?;
+        if self.rewrite_js {
+            self.rewrite_js(&mut candidates)?;
+        }
+        self.emit(ProcessEvent::Planned {
+            total: candidates.len(),
+        });
+
+        let build_hash = match self.hash_placement {
+            HashPlacement::FileName => None,
+            HashPlacement::Directory => Some(Self::build_hash(&candidates)),
+        };
+
+        let mut destinations = Vec::with_capacity(candidates.len());
+        #[cfg_attr(not(feature = "images"), allow(unused_mut))]
+        let mut derived = Vec::new();
+        let mut written: BTreeMap<PathBuf, &str> = BTreeMap::new();
+        for candidate in candidates.iter() {
+            let path = candidate.path.as_path();
+            let rel_destination = self.destination(candidate, build_hash.as_deref());
+            if let Some(other) = written.insert(rel_destination.clone(), &candidate.key) {
+                let sanitized = match self.sanitize {
+                    Some(Sanitize::Transliterate) => " once sanitized",
+                    _ => "",
+                };
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!(
+                        "{} and {} both write to {}{}",
+                        other,
+                        candidate.key,
+                        rel_destination.display(),
+                        sanitized
+                    ),
+                ));
+            }
+            let (_, destination) = self.gen_map(path, &rel_destination);
+            let mut entry = Entry::new(slashed(&destination));
+            entry.size = Some(candidate.size);
+            entry.mime = candidate.mime.clone();
+            if let Some(digests) = &candidate.digests {
+                entry.cache_key = Some(digests.hash[..CACHE_KEY_LEN].into());
+                entry.hash = Some(digests.hash.clone());
+                entry.integrity = Some(digests.integrity.clone());
+                entry.chunks = digests.chunks.clone();
+                if self.etag {
+                    entry.etag = Some(format!("\"{}\"", digests.hash));
+                }
+            }
+            if self.inline.contains(&candidate.key) {
+                let csp_hash = match &candidate.contents {
+                    Some(contents) => naming::csp_hash(contents),
+                    None => {
+                        naming::csp_hash(&fs::read(path).map_err(CacheBusterError::file(path))?)
+                    }
+                };
+                entry.csp_hash = Some(csp_hash);
+            }
+            if self.precompresses(candidate) {
+                for precompression in self.precompress.iter() {
+                    entry.precompressed.insert(
+                        precompression.encoding().into(),
+                        format!("{}{}", entry.path, precompression.extension()),
+                    );
+                }
+            }
+            #[cfg(feature = "images")]
+            for (image, rel_destination, contents) in
+                self.image_variants(candidate, build_hash.as_deref())?
+            {
+                entry.images.push(image);
+                derived.push((candidate.path.clone(), rel_destination, contents));
+            }
+            if let Some((logical, variant)) = self.variant(candidate) {
+                file_map
+                    .variants
+                    .entry(logical)
+                    .or_default()
+                    .insert(variant, candidate.key.clone());
+            }
+            if file_map.add(candidate.key.clone(), entry).is_err() {
+                return Err(CacheBusterError::DuplicateKey(candidate.key.clone()).into());
+            }
+            destinations.push(rel_destination);
+        }
+        Ok(Schedule {
+            file_map,
+            candidates,
+            destinations,
+            derived,
+            skipped,
+            unreadable,
+        })
+    }
+
+    // helper fn to load the allowlist, if one is configured
+    fn allowed(&self) -> Result<Option<BTreeSet<PathBuf>>, Error> {
+        let paths = match &self.allowlist {
+            Some(Allowlist::Paths(paths)) => paths.clone(),
+            Some(Allowlist::File(file)) => fs::read_to_string(file)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect(),
+            None => return Ok(None),
+        };
+        Ok(Some(paths.iter().map(PathBuf::from).collect()))
+    }
+
+    // helper fn to list source directories
+    fn sources(&self) -> Vec<&str> {
+        let source = Some(self.source.as_str()).filter(|source| !source.is_empty());
+        source
+            .into_iter()
+            .chain(self.sources.iter().map(|source| source.as_str()))
+            .collect()
+    }
+
+    // helper fn to check if file, relative to source, is path in any of the
+    // source directories
+    fn in_source(&self, file: &str, path: &Path) -> bool {
+        self.sources()
+            .iter()
+            .any(|source| Path::new(source).join(file) == path)
+    }
+
+    // helper fn to list directories to process: sources, followed by assets
+    // of dependencies along with their names
+    fn roots(&self) -> Vec<(&Path, Option<&str>)> {
+        let mut roots: Vec<(&Path, Option<&str>)> = self
+            .sources()
+            .into_iter()
+            .map(|source| (Path::new(source), None))
+            .collect();
+        for dependency in self.dependency_assets.iter() {
+            roots.push((dependency.dir.as_path(), Some(dependency.name.as_str())));
+        }
+        roots
+    }
+
+    // helper fn to capture size and modification time of every file in
+    // source, used to detect changes in watch mode. Files removed between
+    // the walk and reading their metadata are left out
+    pub(crate) fn snapshot(&self) -> Result<BTreeMap<PathBuf, (u64, SystemTime)>, Error> {
+        let mut snapshot = BTreeMap::new();
+        for (root, _) in self.roots() {
+            for path in self.walk(root, &mut Vec::new())? {
+                let metadata = match fs::metadata(&path) {
+                    Ok(metadata) => metadata,
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                };
+                snapshot.insert(path, (metadata.len(), metadata.modified()?));
+            }
+        }
+        Ok(snapshot)
+    }
+
+    // helper fn to get the path the filemap is written to, before
+    // compression
+    fn manifest(&self) -> Cow<'_, str> {
+        if self.out_dir {
+            let out_dir = std::env::var("OUT_DIR").unwrap();
+            let manifest = Path::new(&out_dir)
+                .join(OUT_DIR_MANIFEST)
+                .with_extension(self.manifest_format.extension());
+            return Cow::Owned(manifest.to_string_lossy().into_owned());
+        }
+        match (&self.manifest_path, self.manifest_format) {
+            (Some(path), _) => Cow::Borrowed(path),
+            (None, ManifestFormat::Json) => Cow::Borrowed(CACHE_BUSTER_DATA_FILE),
+            #[allow(unreachable_patterns)]
+            (None, format) => Cow::Owned(
+                Path::new(CACHE_BUSTER_DATA_FILE)
+                    .with_extension(format.extension())
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+        }
+    }
+
+    // helper fn to normalize prefix to a leading slash and no trailing one,
+    // None when it's empty
+    fn route_prefix(&self) -> Option<String> {
+        let prefix = self.prefix.as_deref()?.trim_matches('/');
+        (!prefix.is_empty()).then(|| format!("/{}", prefix))
+    }
+
+    // helper fn to walk root, applying the empty_source policy when it's
+    // missing or holds no files
+    fn source_files(
+        &self,
+        root: &Path,
+        unreadable: &mut Vec<PathBuf>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let files = if root.is_dir() {
+            self.walk(root, unreadable)?
+        } else {
+            Vec::new()
+        };
+        if files.is_empty() {
+            match self.empty_source {
+                EmptySource::Error => {
+                    return Err(CacheBusterError::EmptySource(root.to_path_buf()).into())
+                }
+                EmptySource::Warn => {
+                    println!(
+                        "cargo:warning=source {} is empty or missing",
+                        root.display()
+                    )
+                }
+                EmptySource::Allow => (),
+            }
+        }
+        Ok(files)
+    }
+
+    // helper fn to list files in root, honoring ignore files and pruning
+    // excluded directories when configured. Unreadable entries skipped by
+    // the unreadable policy are added to unreadable
+    fn walk(&self, root: &Path, unreadable: &mut Vec<PathBuf>) -> Result<Vec<PathBuf>, Error> {
+        #[cfg(feature = "jwalk")]
+        if self.parallel_walk {
+            let files = self.walk_parallel(root, unreadable)?;
+            return self.contained(root, files, unreadable);
+        }
+        let mut files = Vec::new();
+        let ignore_hidden = self.include_hidden == Some(HiddenFiles::Ignore);
+        if self.respect_ignore_files {
+            let exclude_dirs = self.exclude_dirs.clone();
+            let walk_root = root.to_path_buf();
+            let mut walker = ignore::WalkBuilder::new(root);
+            walker
+                .require_git(false)
+                .hidden(self.include_hidden.is_none() || ignore_hidden)
+                .follow_links(self.follow_links)
+                .max_depth(self.max_depth)
+                .filter_entry(move |entry| !Self::pruned(&exclude_dirs, &walk_root, entry.path()));
+            for entry in walker.build() {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => match Self::unreadable_path(&e) {
+                        Some(path) if self.skip_unreadable(path) => {
+                            unreadable.push(path.to_path_buf());
+                            continue;
+                        }
+                        _ => return Err(Self::walk_error(e)),
+                    },
+                };
+                if !entry.path().is_dir() {
+                    files.push(entry.into_path());
+                }
+            }
+        } else {
+            let mut walker = WalkDir::new(root).follow_links(self.follow_links);
+            if let Some(max_depth) = self.max_depth {
+                walker = walker.max_depth(max_depth);
+            }
+            let walker = walker.into_iter().filter_entry(|entry| {
+                let hidden =
+                    entry.depth() > 0 && entry.file_name().to_string_lossy().starts_with('.');
+                let pruned = Self::pruned(&self.exclude_dirs, root, entry.path());
+                !(pruned || ignore_hidden && hidden)
+            });
+            for entry in walker {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => match e.path().filter(|_| e.io_error().is_some()) {
+                        Some(path) if self.skip_unreadable(path) => {
+                            unreadable.push(path.to_path_buf());
+                            continue;
+                        }
+                        _ => return Err(e.into()),
+                    },
+                };
+                if !entry.path().is_dir() {
+                    files.push(entry.into_path());
+                }
+            }
+        }
+        self.contained(root, files, unreadable)
+    }
+
+    // helper fn to check if an unreadable entry is to be skipped, warning
+    // about it when configured
+    fn skip_unreadable(&self, path: &Path) -> bool {
+        match self.unreadable {
+            UnreadablePolicy::Fail => false,
+            UnreadablePolicy::Skip => true,
+            UnreadablePolicy::SkipWithWarning => {
+                println!("cargo:warning=skipping unreadable {}", path.display());
+                true
+            }
+        }
+    }
+
+    // helper fn to find the entry an I/O error of ignore's walker is about
+    fn unreadable_path(e: &ignore::Error) -> Option<&Path> {
+        let mut inner = e;
+        let mut path = None;
+        loop {
+            match inner {
+                ignore::Error::WithPath { path: p, err } => {
+                    path = Some(p.as_path());
+                    inner = err;
+                }
+                ignore::Error::WithDepth { err, .. }
+                | ignore::Error::WithLineNumber { err, .. } => inner = err,
+                ignore::Error::Io(_) => return path,
+                _ => return None,
+            }
+        }
+    }
+
+    // helper fn to apply the external_links policy to walked files
+    fn contained(
+        &self,
+        root: &Path,
+        mut files: Vec<PathBuf>,
+        unreadable: &mut Vec<PathBuf>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        if self.external_links == ExternalLinks::Allow {
+            return Ok(files);
+        }
+        let root = fs::canonicalize(root).map_err(CacheBusterError::file(root))?;
+        let mut external = Vec::new();
+        for (i, path) in files.iter().enumerate() {
+            let target = match fs::canonicalize(path) {
+                Ok(target) => target,
+                Err(_) if self.skip_unreadable(path) => {
+                    unreadable.push(path.clone());
+                    external.push(i);
+                    continue;
+                }
+                Err(e) => return Err(CacheBusterError::file(path)(e)),
+            };
+            if target.starts_with(&root) {
+                continue;
+            }
+            if self.external_links == ExternalLinks::Error {
+                return Err(CacheBusterError::ExternalLink {
+                    path: path.clone(),
+                    target,
+                }
+                .into());
+            }
+            println!(
+                "cargo:warning=skipping {}, resolves outside of source to {}",
+                path.display(),
+                target.display()
+            );
+            external.push(i);
+        }
+        for i in external.into_iter().rev() {
+            files.remove(i);
+        }
+        Ok(files)
+    }
+
+    // helper fn to walk on multiple threads with jwalk, pruning and skipping
+    // entries like the single-threaded walk. Files are sorted, as threads
+    // find them in no particular order
+    #[cfg(feature = "jwalk")]
+    fn walk_parallel(
+        &self,
+        root: &Path,
+        unreadable: &mut Vec<PathBuf>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let exclude_dirs = self.exclude_dirs.clone();
+        let walk_root = root.to_path_buf();
+        let mut walker = jwalk::WalkDir::new(root)
+            .follow_links(self.follow_links)
+            .skip_hidden(self.include_hidden == Some(HiddenFiles::Ignore))
+            .parallelism(jwalk::Parallelism::RayonNewPool(self.thread_count()))
+            .process_read_dir(move |_, _, _, children| {
+                children.retain(|child| {
+                    child.as_ref().map_or(true, |entry| {
+                        !Self::pruned(&exclude_dirs, &walk_root, &entry.path())
+                    })
+                });
+            });
+        if let Some(max_depth) = self.max_depth {
+            walker = walker.max_depth(max_depth);
+        }
+        let mut files = Vec::new();
+        for entry in walker {
+            let e = match entry {
+                Ok(entry) => match entry.read_children_error {
+                    Some(e) => e,
+                    None => {
+                        let path = entry.path();
+                        if !path.is_dir() {
+                            files.push(path);
+                        }
+                        continue;
+                    }
+                },
+                Err(e) => e,
+            };
+            if let (Some(link), Some(target)) = (e.path(), e.loop_ancestor()) {
+                return Err(CacheBusterError::SymlinkLoop {
+                    link: link.to_path_buf(),
+                    target: target.to_path_buf(),
+                }
+                .into());
+            }
+            match e.path().filter(|_| e.io_error().is_some()) {
+                Some(path) if self.skip_unreadable(path) => unreadable.push(path.to_path_buf()),
+                _ => return Err(e.into()),
+            }
+        }
+        unreadable.sort();
+        files.sort();
+        Ok(files)
+    }
+
+    // helper fn to report symlink loops found by ignore's walker with the
+    // offending link
+    fn walk_error(e: ignore::Error) -> Error {
+        let mut inner = &e;
+        loop {
+            match inner {
+                ignore::Error::WithPath { err, .. }
+                | ignore::Error::WithDepth { err, .. }
+                | ignore::Error::WithLineNumber { err, .. } => inner = err,
+                ignore::Error::Loop { ancestor, child } => {
+                    return CacheBusterError::SymlinkLoop {
+                        link: child.clone(),
+                        target: ancestor.clone(),
+                    }
+                    .into()
+                }
+                _ => return Error::other(e),
+            }
+        }
+    }
+
+    // helper fn to check if path is a directory excluded with exclude_dirs
+    fn pruned(exclude_dirs: &[String], root: &Path, path: &Path) -> bool {
+        if exclude_dirs.is_empty() || path == root || !path.is_dir() {
+            return false;
+        }
+        let rel = path.strip_prefix(root).unwrap();
+        exclude_dirs.iter().any(|dir| {
+            let dir = dir.trim_end_matches('/');
+            if dir.contains('/') {
+                rel == Path::new(dir)
+            } else {
+                path.file_name().is_some_and(|name| name == dir)
+            }
+        })
+    }
+
+    // helper fn to check if a file is to be copied verbatim for being a
+    // dotfile or in a dot-directory
+    fn copied_hidden(&self, rel: &Path) -> bool {
+        self.include_hidden == Some(HiddenFiles::Copy)
+            && rel
+                .components()
+                .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+    }
+
+    // helper fn to compile globs, validated while building
+    fn glob_set(globs: &[String]) -> GlobSet {
+        let mut set = GlobSetBuilder::new();
+        for glob in globs.iter() {
+            set.add(Glob::new(glob).unwrap());
+        }
+        set.build().unwrap()
+    }
+
+    // helper fn to check if a file is picked up for processing by the
+    // configured filters
+    fn selected(
+        &self,
+        path: &Path,
+        rel: &Path,
+        include: &GlobSet,
+        exclude: &GlobSet,
+    ) -> Result<bool, Error> {
+        if exclude.is_match(rel) || !(self.include_globs.is_empty() || include.is_match(rel)) {
+            return Ok(false);
+        }
+        if let Some(filter) = &self.filter {
+            if !(filter.0)(path) {
+                return Ok(false);
+            }
+        }
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let size = fs::metadata(path)?.len();
+            if self.min_size.is_some_and(|min_size| size < min_size)
+                || self.max_size.is_some_and(|max_size| size > max_size)
+            {
+                return Ok(false);
+            }
+        }
+        if self.copied_hidden(rel) {
+            return Ok(true);
+        }
+        let file_mime = match self.mime(path) {
+            Some(file_mime) => file_mime,
+            None => {
+                return match self.unknown_mime {
+                    Some(UnknownMimePolicy::Skip) => Ok(false),
+                    Some(UnknownMimePolicy::CopyWithoutHash | UnknownMimePolicy::Hash) => Ok(true),
+                    Some(UnknownMimePolicy::Error) => {
+                        Err(CacheBusterError::UnknownMime(path.to_path_buf()).into())
+                    }
+                    None if self.mime_types.is_some() => {
+                        Err(CacheBusterError::UnknownMime(path.to_path_buf()).into())
+                    }
+                    None => Ok(true),
+                };
+            }
+        };
+        Ok(self.copy_unmatched || !self.unmatched(&file_mime))
+    }
+
+    // helper fn to check if a file of MIME type file_mime is filtered out
+    // by mime_types
+    fn unmatched(&self, file_mime: &mime::Mime) -> bool {
+        self.mime_types.as_ref().is_some_and(|mime_types| {
+            !mime_types
+                .iter()
+                .any(|mime_type| Self::mime_matches(mime_type, file_mime))
+        })
+    }
+
+    // helper fn to resolve MIME of a file, consulting overrides first
+    fn mime(&self, path: &Path) -> Option<mime::Mime> {
+        let name = path.file_name()?.to_str()?;
+        self.mime_overrides
+            .iter()
+            .filter(|(ext, _)| {
+                let ext = ext.trim_start_matches('.');
+                name.len() > ext.len() + 1
+                    && name.ends_with(ext)
+                    && name[..name.len() - ext.len()].ends_with('.')
+            })
+            .max_by_key(|(ext, _)| ext.len())
+            .map(|(_, mime)| mime.clone())
+            .or_else(|| mime_guess::from_path(path).first())
+    }
+
+    // helper fn to match a MIME type against a possibly wildcard one
+    fn mime_matches(pattern: &mime::Mime, mime: &mime::Mime) -> bool {
+        if pattern.subtype() == mime::STAR {
+            pattern.type_() == mime::STAR || pattern.type_() == mime.type_()
+        } else {
+            mime == pattern
+        }
+    }
+
+    // helper fn to get the number of threads to process on, one per CPU
+    // unless capped by threads
+    #[cfg(any(feature = "rayon", feature = "jwalk"))]
+    fn thread_count(&self) -> usize {
+        self.threads.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        })
+    }
+
+    // helper fn to apply f to every item, on a pool of self.thread_count()
+    // threads with the rayon feature. Results keep the order of items
+    fn par_map<T, R, F>(&self, items: &[T], f: F) -> Result<Vec<R>, Error>
+    where
+        T: Sync,
+        R: Send,
+        F: Fn(&T) -> R + Sync,
+    {
+        #[cfg(feature = "rayon")]
+        if self.thread_count() > 1 && items.len() > 1 {
+            use rayon::prelude::*;
+
+            // a pool of our own, so that the cap holds whatever else runs
+            // on rayon's global pool
+            let pool = self.pool.get_or_init(|| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(self.thread_count())
+                    .build()
+                    .map_err(|e| e.to_string())
+            });
+            return match pool {
+                Ok(pool) => Ok(pool.install(|| items.par_iter().map(&f).collect())),
+                Err(e) => Err(Error::other(format!("can't start threads: {}", e))),
+            };
+        }
+        Ok(items.iter().map(f).collect())
+    }
+
+    // helper fn to send progress to the observer and events channel, if
+    // configured.
+    // Events are dropped once the receiver hangs up
+    fn emit(&self, event: ProcessEvent) {
+        if let Some(Observer(observer)) = &self.observer {
+            if let Ok(mut observer) = observer.lock() {
+                observer(event.clone());
+            }
+        }
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    // helper fn to classify a file that is to be processed. Files excluded
+    // from hashing are only read when their digests are tracked
+    fn prepare(
+        &self,
+        path: &Path,
+        rel: PathBuf,
+        key: String,
+        no_hash_globs: &GlobSet,
+        cache: &BuildCache,
+    ) -> Result<Candidate, Error> {
+        let file_mime = self.mime(path);
+        let mime = file_mime.as_ref().map(|mime| mime.to_string());
+
+        let no_hash = self.no_hash.iter().any(|no_hash| {
+            match no_hash {
+                NoHashCategory::FilePaths(paths) => {
+                    let no_hash_status = paths
+                        .iter()
+                        .any(|file_path| self.in_source(file_path, path));
+                    no_hash_status
+                }
+                NoHashCategory::FileExtentions(extensions) => {
+                    let mut no_hash_status = false;
+                    if let Some(cur_extention) = path.extension() {
+                        // .unwrap().to_str().unwrap();
+                        if let Some(cur_extention) = cur_extention.to_str() {
+                            no_hash_status = extensions.iter().any(|ext| cur_extention == ext);
+                        }
+                    }
+                    no_hash_status
+                }
+                NoHashCategory::Globs(_) => no_hash_globs.is_match(&rel),
+                NoHashCategory::MimeTypes(mime_types) => {
+                    file_mime.as_ref().is_some_and(|file_mime| {
+                        mime_types
+                            .iter()
+                            .any(|mime_type| Self::mime_matches(mime_type, file_mime))
+                    })
+                }
+            }
+        });
+
+        let no_hash = no_hash
+            || (mime.is_none() && self.unknown_mime == Some(UnknownMimePolicy::CopyWithoutHash))
+            || (self.copy_unmatched
+                && file_mime
+                    .as_ref()
+                    .is_some_and(|file_mime| self.unmatched(file_mime)))
+            || self.copied_hidden(&rel);
+
+        let output_name = self
+            .output_names
+            .iter()
+            .find(|(file_path, _)| self.in_source(file_path, path))
+            .map(|(_, name)| name.to_string());
+
+        let mut transforms = self
+            .transforms
+            .iter()
+            .filter(|(target, _)| match target {
+                TransformTarget::Mime(pattern) => file_mime
+                    .as_ref()
+                    .is_some_and(|file_mime| Self::mime_matches(pattern, file_mime)),
+                TransformTarget::Extension(extension) => path
+                    .extension()
+                    .is_some_and(|ext| ext == extension.as_str()),
+            })
+            .peekable();
+        let transformed = if transforms.peek().is_some() {
+            let mut contents = Self::read_to_string(path)?;
+            for (_, Transform(transform)) in transforms {
+                contents = transform.transform(path, contents)?;
+            }
+            Some(contents)
+        } else {
+            None
+        };
+
+        let metadata = fs::metadata(path)?;
+        let tracked = !no_hash || self.track_no_hash;
+        let cached = match transformed {
+            Some(_) => None,
+            None => cache.get(path, &metadata, tracked),
+        };
+
+        let (size, digests) = match (&transformed, cached) {
+            (Some(contents), _) if !tracked => (contents.len() as u64, None),
+            (Some(contents), _) => (contents.len() as u64, Some(self.digests(contents))),
+            (None, _) if !tracked => (metadata.len(), None),
+            (None, Some(cached)) => (metadata.len(), cached.digests.clone()),
+            // streamed, so that large files aren't held in memory
+            (None, None) => {
+                let mut digester = Digester::new(self.chunk_size);
+                let size = io::copy(&mut fs::File::open(path)?, &mut digester)?;
+                (size, Some(digester.finish()))
+            }
+        };
+
+        Ok(Candidate {
+            path: path.to_path_buf(),
+            rel,
+            key,
+            size,
+            modified: metadata.modified().ok(),
+            mime,
+            cached: cached.map(|cached| cached.destination.clone()),
+            digests,
+            contents: transformed,
+            no_hash,
+            output_name,
+        })
+    }
+
+    // helper fn to compute digests of file contents
+    fn digests(&self, contents: &[u8]) -> Digests {
+        let mut digester = Digester::new(self.chunk_size);
+        digester.write_all(contents).unwrap();
+        digester.finish()
+    }
+
+    // helper fn to point references between JavaScript files at hashed
+    // names. Files are rewritten after the hashed files they reference, as
+    // rewriting changes their hash
+    fn rewrite_js(&self, candidates: &mut [Candidate]) -> Result<(), Error> {
+        let index: BTreeMap<PathBuf, usize> = candidates
+            .iter()
+            .enumerate()
+            .map(|(i, candidate)| (candidate.rel.clone(), i))
+            .collect();
+        // JavaScript files along with the references to rewrite in them
+        let mut pending = BTreeMap::new();
+        for (i, candidate) in candidates.iter().enumerate() {
+            if !rewrite::is_js(&candidate.rel) {
+                continue;
+            }
+            let contents = match &candidate.contents {
+                Some(contents) => contents.clone(),
+                None => Self::read_to_string(&candidate.path)?,
+            };
+            let source = match String::from_utf8(contents) {
+                Ok(source) => source,
+                Err(_) => continue,
+            };
+            let references: Vec<(Range<usize>, usize)> = rewrite::references(&source)
+                .into_iter()
+                .filter_map(|reference| {
+                    let target = *index.get(&reference.target(&source, &candidate.rel)?)?;
+                    (target != i).then_some((reference.span, target))
+                })
+                .collect();
+            if !references.is_empty() {
+                pending.insert(i, (source, references));
+            }
+        }
+
+        while !pending.is_empty() {
+            let ready: Vec<usize> = pending
+                .iter()
+                .filter(|(_, (_, references))| {
+                    references.iter().all(|(_, target)| {
+                        !(pending.contains_key(target) && candidates[*target].hashed())
+                    })
+                })
+                .map(|(i, _)| *i)
+                .collect();
+            if ready.is_empty() {
+                let cycle: Vec<&str> = pending
+                    .keys()
+                    .map(|i| candidates[*i].key.as_str())
+                    .collect();
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "can't rewrite hashed files referencing each other: {}",
+                        cycle.join(", ")
+                    ),
+                ));
+            }
+            for i in ready {
+                let (source, references) = pending.remove(&i).unwrap();
+                let from = self.destination(&candidates[i], None);
+                let mut rewritten = String::with_capacity(source.len());
+                let mut last = 0;
+                for (span, target) in references {
+                    let to = self.destination(&candidates[target], None);
+                    rewritten.push_str(&source[last..span.start]);
+                    rewritten.push_str(&rewrite::relative(&from, &to, &source[span.clone()]));
+                    last = span.end;
+                }
+                rewritten.push_str(&source[last..]);
+
+                let contents = rewritten.into_bytes();
+                let digests = if candidates[i].digests.is_some() {
+                    Some(self.digests(&contents))
+                } else {
+                    None
+                };
+                let candidate = &mut candidates[i];
+                candidate.size = contents.len() as u64;
+                candidate.digests = digests;
+                candidate.contents = Some(contents);
+            }
+        }
+        Ok(())
+    }
+
+    // helper fn to work out the destination of candidate, relative to result
+    fn destination(&self, candidate: &Candidate, build_hash: Option<&str>) -> PathBuf {
+        match &self.name_mangler {
+            Some(mangler) if candidate.hashed() => {
+                PathBuf::from(mangler.0.name(&candidate.rel, candidate.hash()))
+            }
+            _ => {
+                let new_name = self.get_name(candidate);
+                let build_hash = build_hash.filter(|_| candidate.hashed());
+                self.rel_destination(&candidate.rel, &new_name, build_hash)
+            }
+        }
+    }
+
+    // helper fn to encode variants of candidate, if it is a raster image.
+    // Returns filemap records, destinations relative to result and contents
+    #[cfg(feature = "images")]
+    fn image_variants(
+        &self,
+        candidate: &Candidate,
+        build_hash: Option<&str>,
+    ) -> Result<Vec<(ImageVariant, PathBuf, Vec<u8>)>, Error> {
+        let images = match &self.responsive_images {
+            Some(images) => images,
+            None => return Ok(Vec::new()),
+        };
+        let mime = match candidate.mime.as_deref() {
+            Some(mime) if mime.starts_with("image/") && mime != "image/svg+xml" => mime,
+            _ => return Ok(Vec::new()),
+        };
+        let read;
+        let input = match &candidate.contents {
+            Some(contents) => contents,
+            None => {
+                read = Self::read_to_string(&candidate.path)?;
+                &read
+            }
+        };
+        let (stem, extension) = naming::split_name(&candidate.rel, &self.compound_extensions);
+        let widths = std::iter::once(None).chain(images.widths.iter().copied().map(Some));
+
+        let mut variants = Vec::new();
+        for format in images.formats.iter() {
+            for width in widths.clone() {
+                if *format == ImageFormat::Original && width.is_none() {
+                    continue;
+                }
+                let contents =
+                    match images
+                        .encoder
+                        .encode(&candidate.path, input, width, *format)?
+                    {
+                        Some(contents) => contents,
+                        None => continue,
+                    };
+                let name = match width {
+                    Some(width) => format!("{}-{}w", stem, width),
+                    None => stem.to_string(),
+                };
+                let rel = candidate.rel.with_file_name(naming::join_name(
+                    &name,
+                    format.extension().unwrap_or(extension),
+                ));
+                let name = if candidate.hashed() && self.hash_placement == HashPlacement::FileName {
+                    naming::hashed_name(&rel, &naming::hash(&contents), &self.compound_extensions)
+                } else {
+                    rel.file_name().unwrap().to_str().unwrap().into()
+                };
+                let build_hash = build_hash.filter(|_| candidate.hashed());
+                let rel_destination = self.rel_destination(&rel, &name, build_hash);
+                let (_, destination) = self.gen_map(&candidate.path, &rel_destination);
+                let image = ImageVariant {
+                    path: slashed(&destination),
+                    width,
+                    mime: format.mime().unwrap_or(mime).into(),
+                };
+                variants.push((image, rel_destination, contents));
+            }
+        }
+        Ok(variants)
+    }
+
+    // helper fn to compute a single hash over every hashed file, used as the
+    // directory name with HashPlacement::Directory
+    fn build_hash(candidates: &[Candidate]) -> String {
+        let mut hashed: Vec<&Candidate> = candidates.iter().filter(|c| c.hashed()).collect();
+        hashed.sort_by(|a, b| a.key.cmp(&b.key));
+        let mut payload = Vec::new();
+        for candidate in hashed.iter() {
+            payload.extend_from_slice(candidate.key.as_bytes());
+            payload.push(0);
+            payload.extend_from_slice(candidate.hash().as_bytes());
+            payload.push(0);
+        }
+        naming::hash(&payload)
+    }
+
+    // helper fn to find the logical path and variant name of a file that is
+    // a variant: `css/app.dark.css` is variant `dark` of `css/app.css`
+    fn variant(&self, candidate: &Candidate) -> Option<(String, String)> {
+        let (stem, extension) = naming::split_name(&candidate.path, &self.compound_extensions);
+        let (logical_stem, variant) = self
+            .variants
+            .iter()
+            .find_map(|variant| Some((stem.strip_suffix(variant)?.strip_suffix('.')?, variant)))?;
+        if logical_stem.is_empty() {
+            return None;
+        }
+        let (dir, _) = candidate.key.rsplit_once('/')?;
+        Some((
+            format!("{}/{}", dir, naming::join_name(logical_stem, extension)),
+            variant.to_string(),
+        ))
+    }
+
+    // helper fn to generate the file name of a processed file
+    fn get_name(&self, candidate: &Candidate) -> String {
+        if let Some(output_name) = &candidate.output_name {
+            return output_name.clone();
+        }
+        if candidate.no_hash || self.hash_placement == HashPlacement::Directory {
+            // names are kept as is, which works for files without extension too
+            candidate.path.file_name().unwrap().to_str().unwrap().into()
+        } else {
+            naming::hashed_name(&candidate.path, candidate.hash(), &self.compound_extensions)
+        }
+    }
+
+    // helper fn to write typed asset handles
+    fn write_codegen(
+        &self,
+        candidates: &[Candidate],
+        file_map: &Files,
+        path: &str,
+    ) -> Result<(), Error> {
+        let assets: Vec<codegen::Asset> = candidates
+            .iter()
+            .map(|candidate| {
+                let path = file_map.map[&candidate.key].path.clone();
+                let url = crate::filemap::prefixed(
+                    file_map.prefix.as_deref(),
+                    &path[file_map.base_dir.len()..],
+                );
+                codegen::Asset {
+                    rel: slashed(&candidate.rel),
+                    source: candidate.key.clone(),
+                    path,
+                    url,
+                }
+            })
+            .collect();
+        fs::write(path, codegen::asset_enum(&assets)).map_err(CacheBusterError::file(path))
+    }
+
+    // helper fn to write absolute URLs of hashed files
+    fn write_warm_list(
+        &self,
+        candidates: &[Candidate],
+        file_map: &Files,
+        path: &str,
+    ) -> Result<(), Error> {
+        let base_url = self.base_url.as_deref().unwrap().trim_end_matches('/');
+        let mut urls: Vec<String> = candidates
+            .iter()
+            .filter(|candidate| candidate.hashed())
+            .map(|candidate| {
+                let destination = &file_map.map[&candidate.key].path;
+                let url = crate::filemap::prefixed(
+                    file_map.prefix.as_deref(),
+                    &destination[file_map.base_dir.len()..],
+                );
+                format!("{}{}\n", base_url, url)
+            })
+            .collect();
+        urls.sort();
+        fs::write(path, urls.concat()).map_err(CacheBusterError::file(path))
+    }
+
+    // helper fn to write an HTML page with tags for its entry assets
+    fn write_html_page(&self, page: &HtmlPage, file_map: &Files) -> Result<(), Error> {
+        let mut tags = Vec::with_capacity(page.entries.len());
+        for key in page.entries.iter() {
+            let entry = file_map.map.get(key).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!(
+                        "HTML entry {} of {} isn't in the filemap",
+                        key,
+                        page.template.display()
+                    ),
+                )
+            })?;
+            let url = crate::filemap::prefixed(
+                file_map.prefix.as_deref(),
+                &entry.path[file_map.base_dir.len()..],
+            );
+            let integrity = entry.integrity.as_deref().filter(|_| page.integrity);
+            let tag = html::tag(key, &url, integrity).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("HTML entry {} is neither a stylesheet nor a script", key),
+                )
+            })?;
+            tags.push(tag);
+        }
+        let template =
+            fs::read_to_string(&page.template).map_err(CacheBusterError::file(&page.template))?;
+        let output = Path::new(&self.result).join(page.output_path()?);
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&output, html::inject(&template, &tags)).map_err(CacheBusterError::file(&output))
+    }
+
+    // helper fn to split paths of processed files relative to result, with a
+    // leading slash, into hashed ones along with their precompressed and
+    // image variants, and ones excluded from hashing
+    fn cache_classes(
+        candidates: &[Candidate],
+        file_map: &Files,
+    ) -> (BTreeSet<String>, BTreeSet<String>) {
+        let rel = |path: &str| path[file_map.base_dir.len()..].to_string();
+        let mut immutable = BTreeSet::new();
+        let mut no_cache = BTreeSet::new();
+        for candidate in candidates.iter() {
+            let entry = &file_map.map[&candidate.key];
+            if candidate.hashed() {
+                immutable.insert(rel(&entry.path));
+                immutable.extend(entry.precompressed.values().map(|path| rel(path)));
+                immutable.extend(entry.images.iter().map(|image| rel(&image.path)));
+            } else {
+                no_cache.insert(rel(&entry.path));
+                no_cache.extend(entry.precompressed.values().map(|path| rel(path)));
+            }
+        }
+        (immutable, no_cache)
+    }
+
+    // helper fn to turn paths of immutable files, relative to result with a
+    // leading slash, into `_headers` rules. Directories below result that
+    // hold no other files are covered by a single splat rule
+    fn headers_rules(&self, immutable: &BTreeSet<String>) -> Result<BTreeSet<String>, Error> {
+        let result = Path::new(&self.result);
+        let mut mixed = BTreeSet::new();
+        for entry in WalkDir::new(result) {
+            let entry = entry?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let rel = entry.path().strip_prefix(result).unwrap();
+            let rel = match self.sanitize {
+                Some(Sanitize::PercentEncode) => sanitize::path(rel, Sanitize::PercentEncode),
+                _ => rel.to_path_buf(),
+            };
+            let rel = format!("/{}", slashed(&rel));
+            if !immutable.contains(&rel) {
+                mixed.extend(rel.match_indices('/').map(|(i, _)| rel[..=i].to_string()));
+            }
+        }
+        let rules = immutable
+            .iter()
+            .map(|path| {
+                let dir = path
+                    .match_indices('/')
+                    .skip(1)
+                    .map(|(i, _)| &path[..=i])
+                    .find(|dir| !mixed.contains(*dir));
+                match dir {
+                    Some(dir) => format!("{}*", dir),
+                    None => path.clone(),
+                }
+            })
+            .collect();
+        Ok(rules)
+    }
+
+    // helper fn to write Link header values of preloaded assets
+    fn write_preload_list(file_map: &Files, path: &str) -> Result<(), Error> {
+        let links: Vec<String> = file_map
+            .preload
+            .iter()
+            .map(|key| {
+                let entry = &file_map.map[key];
+                let url = crate::filemap::prefixed(
+                    file_map.prefix.as_deref(),
+                    &entry.path[file_map.base_dir.len()..],
+                );
+                crate::filemap::preload_link(&url, entry.mime.as_deref())
+            })
+            .collect();
+        let contents = if path.ends_with(".json") {
+            serde_json::to_vec_pretty(&links)?
+        } else {
+            links
+                .iter()
+                .map(|link| format!("Link: {}\n", link))
+                .collect::<String>()
+                .into_bytes()
+        };
+        fs::write(path, contents).map_err(CacheBusterError::file(path))
+    }
+
+    // helper fn to write a webpack-manifest-plugin style manifest
+    fn write_webpack_manifest(
+        candidates: &[Candidate],
+        file_map: &Files,
+        path: &str,
+    ) -> Result<(), Error> {
+        let manifest: BTreeMap<String, String> = candidates
+            .iter()
+            .map(|candidate| {
+                let destination = &file_map.map[&candidate.key].path;
+                let rel = slashed(&candidate.rel);
+                let url = crate::filemap::prefixed(
+                    file_map.prefix.as_deref(),
+                    &destination[file_map.base_dir.len()..],
+                );
+                (rel.trim_start_matches("./").to_string(), url)
+            })
+            .collect();
+        fs::write(path, serde_json::to_vec_pretty(&manifest)?).map_err(CacheBusterError::file(path))
+    }
+
+    // helper fn to write a snapshot of the processing result
+    fn write_snapshot(&self, candidates: &[Candidate], path: &str) -> Result<(), Error> {
+        let mut snapshot = Snapshot::default();
+        for candidate in candidates.iter() {
+            snapshot.assets.insert(
+                candidate.key.clone(),
+                SnapshotEntry {
+                    size: candidate.size,
+                    mime: candidate.mime.clone(),
+                    hashed: candidate.hashed(),
+                },
+            );
+        }
+        Ok(snapshot.write(path)?)
+    }
+
+    // helper fn to take advisory locks on the filemap and result directory,
+    // held until the returned files are dropped. Concurrent processing, like
+    // build scripts of several crates in a workspace, waits its turn
+    fn lock(&self) -> Result<Vec<fs::File>, Error> {
+        let manifest = self.manifest();
+        if let Some(dir) = Path::new(manifest.as_ref()).parent() {
+            fs::create_dir_all(dir).map_err(CacheBusterError::file(dir))?;
+        }
+        fs::create_dir_all(&self.result).map_err(CacheBusterError::file(&self.result))?;
+        let paths = [
+            PathBuf::from(format!("{}.lock", manifest)),
+            Path::new(&self.result).join(RESULT_LOCK),
+        ];
+        let mut locks = Vec::with_capacity(paths.len());
+        for path in paths {
+            let file = fs::File::options()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .map_err(CacheBusterError::file(&path))?;
+            file.lock().map_err(CacheBusterError::file(path))?;
+            locks.push(file);
+        }
+        Ok(locks)
+    }
+
+    // helper fn to fail early, before walking and hashing source, when the
+    // result directory can't be created or written to
+    fn check_result(&self) -> Result<(), Error> {
+        let not_writable = |source| CacheBusterError::ResultNotWritable {
+            path: PathBuf::from(&self.result),
+            source,
+        };
+        // result is created once processing gets going, until then its
+        // nearest existing ancestor has to take new files
+        let mut dir = Path::new(&self.result);
+        while !dir.exists() {
+            dir = match dir.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+        }
+        let probe = dir.join(format!("{}.{}.probe", RESULT_MARKER, std::process::id()));
+        fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&probe)
+            .map_err(not_writable)?;
+        fs::remove_file(&probe).map_err(not_writable)?;
+        Ok(())
+    }
+
+    // helper fn to load the build cache, empty when none is configured
+    fn build_cache(&self) -> BuildCache {
+        match &self.cache {
+            Some(path) => BuildCache::load(path, self.chunk_size),
+            None => BuildCache::new(self.chunk_size),
+        }
+    }
+
+    // helper fn to write the build cache, recording files that were written
+    // as they are in source
+    fn write_build_cache(
+        &self,
+        candidates: &[Candidate],
+        destinations: &[PathBuf],
+        path: &str,
+    ) -> Result<(), Error> {
+        let mut cache = BuildCache::new(self.chunk_size);
+        for (candidate, rel_destination) in candidates.iter().zip(destinations.iter()) {
+            if let (None, Some(modified)) = (&candidate.contents, candidate.modified) {
+                cache.insert(
+                    candidate.path.clone(),
+                    candidate.size,
+                    modified,
+                    candidate.digests.clone(),
+                    rel_destination.clone(),
+                );
+            }
+        }
+        cache.write(path)
+    }
+
+    // helper fn to check if output of an earlier build can be left in place
+    // for candidate: it is still there, and either candidate is hashed, so
+    // that its destination names its contents, or it is unchanged since the
+    // last build and was written to the same destination
+    fn reusable(&self, candidate: &Candidate, rel_destination: &Path) -> bool {
+        (candidate.hashed()
+            || candidate.contents.is_none() && candidate.cached.as_deref() == Some(rel_destination))
+            && self
+                .outputs(candidate, rel_destination)
+                .iter()
+                .all(|output| output.is_file())
+            && fs::metadata(Path::new(&self.result).join(rel_destination))
+                .is_ok_and(|metadata| metadata.len() == candidate.size)
+    }
+
+    // helper fn to list files written for candidate: its destination and
+    // precompressed variants
+    fn outputs(&self, candidate: &Candidate, rel_destination: &Path) -> Vec<PathBuf> {
+        let destination = Path::new(&self.result).join(rel_destination);
+        let mut outputs = Vec::new();
+        if self.precompresses(candidate) {
+            outputs.extend(self.precompress.iter().map(|precompression| {
+                PathBuf::from(format!(
+                    "{}{}",
+                    destination.display(),
+                    precompression.extension()
+                ))
+            }));
+        }
+        outputs.insert(0, destination);
+        outputs
+    }
+
+    // helper fn to map a path from the filemap to the file on disk, which
+    // keeps its name with Sanitize::PercentEncode
+    fn disk_path(&self, path: &str) -> String {
+        match self.sanitize {
+            Some(Sanitize::PercentEncode) => sanitize::percent_decode(path),
+            _ => path.to_string(),
+        }
+    }
+
+    // helper fn to read file to string
+    fn read_to_string(path: &Path) -> Result<Vec<u8>, Error> {
+        use std::fs::File;
+        use std::io::Read;
+
+        let mut file_content = Vec::new();
+        let mut file = File::open(path).map_err(CacheBusterError::file(path))?;
+        file.read_to_end(&mut file_content)
+            .map_err(CacheBusterError::file(path))?;
+        Ok(file_content)
+    }
+
+    // helper fn to compute destination path of a file, relative to self.result
+    fn rel_destination(&self, rel: &Path, name: &str, build_hash: Option<&str>) -> PathBuf {
+        let rel_location = rel.parent().unwrap();
+        let rel_destination = match build_hash {
+            Some(build_hash) => Path::new(build_hash).join(rel_location).join(name),
+            None => rel_location.join(name),
+        };
+        match self.sanitize {
+            Some(Sanitize::Transliterate) => {
+                sanitize::path(&rel_destination, Sanitize::Transliterate)
+            }
+            _ => rel_destination,
+        }
+    }
+
+    // helper fn to generate filemap
+    fn gen_map<'b>(&self, source: &'b Path, rel_destination: &Path) -> (&'b Path, PathBuf) {
+        let rel_destination = match self.sanitize {
+            Some(Sanitize::PercentEncode) => {
+                sanitize::path(rel_destination, Sanitize::PercentEncode)
+            }
+            _ => rel_destination.to_path_buf(),
+        };
+        // prefix only applies to URLs, which are resolved from the filemap
+        let destination = Path::new(&self.result).join(rel_destination);
+        (source, destination)
+    }
+
+    // helper fn to copy files, or write their transformed contents
+    fn copy(
+        &self,
+        source: &Path,
+        contents: Option<&[u8]>,
+        rel_destination: &Path,
+        guard: Option<&protect::ReadOnlyGuard>,
+    ) -> Result<(), Error> {
+        let destination = Path::new(&self.result).join(rel_destination);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).map_err(CacheBusterError::file(parent))?;
+        }
+        // destination may be a hard link to source left by an earlier run,
+        // writing through it would overwrite source
+        if fs::symlink_metadata(&destination).is_ok() {
+            fs::remove_file(&destination).map_err(CacheBusterError::file(&destination))?;
+        }
+        match contents {
+            Some(contents) => {
+                fs::write(&destination, contents).map_err(CacheBusterError::file(&destination))?
+            }
+            None if self.link_strategy == LinkStrategy::Hardlink => {
+                match fs::hard_link(source, &destination) {
+                    // metadata is shared with source
+                    Ok(()) => return Ok(()),
+                    Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+                        self.copy_file(source, &destination)?;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            None => self.copy_file(source, &destination)?,
+        }
+        if self.preserves_metadata() {
+            self.preserve_metadata(source, &destination)?;
+        }
+        if let Some(guard) = guard {
+            guard.restore_copy(source, &destination)?;
+        }
+        if self.reproducible {
+            // copies of read-only sources are read-only too
+            fs::File::open(&destination)
+                .and_then(|file| file.set_modified(Self::source_date_epoch()))
+                .map_err(CacheBusterError::file(&destination))?;
+        }
+        Ok(())
+    }
+
+    // helper fn to move files written to result into the store, if set
+    #[cfg(unix)]
+    fn store_outputs(&self, outputs: &[PathBuf]) -> Result<(), Error> {
+        if let Some(store) = &self.store {
+            let store = Store::new(store);
+            for output in outputs.iter() {
+                store.link(output)?;
+            }
+        }
+        Ok(())
+    }
+
+    // helper fn to copy source to destination along with its permissions,
+    // like fs::copy, through a buffer of copy_buffer_size bytes if set
+    fn copy_file(&self, source: &Path, destination: &Path) -> Result<(), Error> {
+        use std::io::Read;
+
+        let size = match self.copy_buffer_size {
+            Some(size) => size,
+            None => {
+                return fs::copy(source, destination)
+                    .map(|_| ())
+                    .map_err(CacheBusterError::file(source))
+            }
+        };
+        let mut reader = fs::File::open(source)?;
+        let mut writer = fs::File::create(destination)?;
+        let mut buffer = vec![0; size];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => writer.write_all(&buffer[..n])?,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        writer.set_permissions(reader.metadata()?.permissions())
+    }
+
+    // helper fn to check if metadata of source files is carried over
+    fn preserves_metadata(&self) -> bool {
+        #[cfg(unix)]
+        return self.preserve_metadata || self.preserve_ownership;
+        #[cfg(not(unix))]
+        return self.preserve_metadata;
+    }
+
+    // helper fn to give destination the permissions, times and, if
+    // configured, ownership of source
+    fn preserve_metadata(&self, source: &Path, destination: &Path) -> Result<(), Error> {
+        let metadata = fs::metadata(source)?;
+        let times = fs::FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?);
+        fs::File::open(destination)?.set_times(times)?;
+        #[cfg(unix)]
+        if self.preserve_ownership {
+            use std::os::unix::fs::MetadataExt;
+
+            std::os::unix::fs::chown(destination, Some(metadata.uid()), Some(metadata.gid()))?;
+        }
+        fs::set_permissions(destination, metadata.permissions())
+    }
+
+    // helper fn to check if variants of candidate are precompressed
+    fn precompresses(&self, candidate: &Candidate) -> bool {
+        !self.precompress.is_empty()
+            && (self.precompress_mime_types.is_empty()
+                || candidate
+                    .mime
+                    .as_ref()
+                    .and_then(|mime| mime.parse::<mime::Mime>().ok())
+                    .is_some_and(|mime| {
+                        self.precompress_mime_types
+                            .iter()
+                            .any(|pattern| Self::mime_matches(pattern, &mime))
+                    }))
+    }
+
+    // helper fn to write precompressed variants next to destination
+    fn write_precompressed(&self, destination: &Path) -> Result<(), Error> {
+        let contents = fs::read(destination)?;
+        for precompression in self.precompress.iter() {
+            let path = format!("{}{}", destination.display(), precompression.extension());
+            fs::write(&path, precompression.compress(&contents)?)
+                .map_err(CacheBusterError::file(&path))?;
+            if self.reproducible {
+                fs::File::open(&path)?.set_modified(Self::source_date_epoch())?;
+            }
+        }
+        Ok(())
+    }
+
+    // timestamp used for reproducible builds
+    fn source_date_epoch() -> SystemTime {
+        let secs = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.parse::<u64>().ok())
+            .unwrap_or(0);
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    // helper fn to create directory structure of path, a directory in the
+    // source directory root, in self.base_dir
+    fn create_dir_structure(&self, root: &Path, path: &Path) -> Result<(), Error> {
+        for entry in WalkDir::new(path)
+            .follow_links(self.follow_links)
+            .into_iter()
+        {
+            // unreadable entries were reported while walking
+            let entry = match entry {
+                Err(e) if e.io_error().is_some() && self.unreadable != UnreadablePolicy::Fail => {
+                    continue
+                }
+                entry => entry?,
+            };
+            let entry_path = entry.path();
+            let entry_path = Path::new(&entry_path);
+
+            if entry_path.is_dir() && path != entry_path {
+                Self::create_dir_structure(self, root, entry_path)?;
+            } else if entry_path.is_dir() {
+                let rel_location = entry_path.strip_prefix(root).unwrap();
+                let destination = match self.sanitize {
+                    Some(Sanitize::Transliterate) => Path::new(&self.result)
+                        .join(sanitize::path(rel_location, Sanitize::Transliterate)),
+                    _ => Path::new(&self.result).join(rel_location),
+                };
+                if !destination.exists() {
+                    fs::create_dir(destination)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+/// Filemap struct
+///
+/// maps original names to generated names. Entries are kept sorted so that
+/// the written filemap doesn't change between builds unless the files do
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct Files {
+    /// layout version, see [SCHEMA_VERSION][crate::SCHEMA_VERSION]
+    #[serde(default = "crate::filemap::legacy_schema_version")]
+    schema_version: u32,
+    /// filemap<original-path, entry>. Hashes, integrity digests and ETags
+    /// aren't recorded for files excluded with [NoHashCategory], unless
+    /// [Buster::track_no_hash] is set
+    pub map: BTreeMap<String, Entry>,
+    base_dir: String,
+    /// files skipped by the MIME filter, globs or size filters, relative to source
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    skipped: BTreeSet<String>,
+    /// variants<logical-path, <variant, original-path>>
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    variants: BTreeMap<String, BTreeMap<String, String>>,
+    /// route base_dir is served under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+    /// files of previous runs kept in base_dir, newest run first, see
+    /// [Buster::keep_generations]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    generations: Vec<Vec<String>>,
+    /// original paths of critical assets, see [Buster::preload]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    preload: Vec<String>,
+}
+
+impl From<crate::Files> for Files {
+    fn from(files: crate::Files) -> Self {
+        Files {
+            schema_version: files.schema_version,
+            map: files.map.into_iter().collect(),
+            base_dir: files.base_dir,
+            skipped: files.skipped.into_iter().collect(),
+            variants: files
+                .variants
+                .into_iter()
+                .map(|(logical, variants)| (logical, variants.into_iter().collect()))
+                .collect(),
+            prefix: files.prefix,
+            generations: files.generations,
+            preload: files.preload,
+        }
+    }
+}
+
+impl From<Files> for crate::Files {
+    fn from(files: Files) -> Self {
+        crate::Files {
+            schema_version: files.schema_version,
+            map: files.map.into_iter().collect(),
+            base_dir: files.base_dir,
+            skipped: files.skipped.into_iter().collect(),
+            variants: files
+                .variants
+                .into_iter()
+                .map(|(logical, variants)| (logical, variants.into_iter().collect()))
+                .collect(),
+            prefix: files.prefix,
+            generations: files.generations,
+            preload: files.preload,
+        }
+    }
+}
+
+impl Files {
+    /// Initialize map
+    fn new(base_dir: &str) -> Self {
+        Files {
+            schema_version: crate::SCHEMA_VERSION,
+            map: BTreeMap::default(),
+            base_dir: base_dir.into(),
+            skipped: BTreeSet::new(),
+            variants: BTreeMap::new(),
+            prefix: None,
+            generations: Vec::new(),
+            preload: Vec::new(),
+        }
+    }
+
+    /// Create file map: map original path to modified paths
+    fn add(&mut self, k: String, v: Entry) -> Result<(), &'static str> {
+        if let std::collections::btree_map::Entry::Vacant(e) = self.map.entry(k) {
+            e.insert(v);
+            Ok(())
+        } else {
+            Err("key exists")
+        }
+    }
+
+    /// Merge entries of other into this filemap. base_dir becomes the closest
+    /// directory containing both base directories, unless this filemap is empty
+    fn merge(&mut self, other: Files) -> Result<(), Error> {
+        if self.map.is_empty() && self.skipped.is_empty() {
+            self.base_dir = other.base_dir.clone();
+            self.prefix = other.prefix.clone();
+        }
+        // merged filemaps are written in the current layout
+        self.schema_version = crate::SCHEMA_VERSION;
+        if self.prefix != other.prefix {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("prefixes {:?} and {:?} differ", self.prefix, other.prefix),
+            ));
+        }
+        let base_dir: PathBuf = Path::new(&self.base_dir)
+            .components()
+            .zip(Path::new(&other.base_dir).components())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a)
+            .collect();
+        if base_dir.as_os_str().is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "base directories {} and {} have nothing in common",
+                    self.base_dir, other.base_dir
+                ),
+            ));
+        }
+        self.base_dir = slashed(&base_dir);
+        for (k, v) in other.map.into_iter() {
+            if self.add(k.clone(), v).is_err() {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} is in both filemaps", k),
+                ));
+            }
+        }
+        self.skipped.extend(other.skipped);
+        self.preload.extend(other.preload);
+        for (logical, variants) in other.variants.into_iter() {
+            self.variants.entry(logical).or_default().extend(variants);
+        }
+        for (i, generation) in other.generations.into_iter().enumerate() {
+            match self.generations.get_mut(i) {
+                Some(files) => files.extend(generation),
+                None => self.generations.push(generation),
+            }
+        }
+        Ok(())
+    }
+
+    /// This crate uses compile-time environment variables to transfer
+    /// data to the main program. This funtction sets that variable
+    fn to_env(
+        &self,
+        path: &str,
+        compression: Option<ManifestCompression>,
+        format: ManifestFormat,
+    ) -> Result<PathBuf, Error> {
+        let contents = format.serialize(self)?;
+        match compression {
+            Some(compression) => {
+                let compressed = compression.path_for(path);
+                Self::write_atomic(&compressed, &compression.compress(&contents)?)?;
+                // a stale uncompressed filemap would be picked up instead
+                let res = Path::new(path);
+                if res.exists() {
+                    fs::remove_file(res)?;
+                }
+                Ok(compressed.into())
+            }
+            None => {
+                Self::write_atomic(path, &contents)?;
+                Ok(path.into())
+            }
+        }
+    }
+
+    // helper fn to write to a temporary file next to path and rename it into
+    // place, so that readers never see a partially written filemap
+    fn write_atomic(path: &str, contents: &[u8]) -> Result<(), Error> {
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, contents).map_err(CacheBusterError::file(&tmp))?;
+        fs::rename(&tmp, path).map_err(CacheBusterError::file(path))
+    }
+
+    #[cfg(test)]
+    /// Load filemap in main program. Should be called from main program
+    fn load() -> Self {
+        let map = fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap();
+        let res: Files = serde_json::from_str(&map).unwrap();
+        res
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn cleanup(config: &Buster) {
+        let _ = fs::remove_dir_all(&config.result);
+        delete_file();
+    }
+
+    pub(crate) fn delete_file() {
+        let _ = fs::remove_file(CACHE_BUSTER_DATA_FILE);
+    }
+
+    /// create a source directory with the given files for tests that need
+    /// file names which can't be checked into ./dist
+    pub(crate) fn fixture(name: &str, files: &[(&str, &str)]) -> String {
+        let dir = Path::new("/tmp/cache-buster-fixtures").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        for (file, contents) in files.iter() {
+            let file = dir.join(file);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, contents).unwrap();
+        }
+        dir.to_str().unwrap().into()
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn slashed_works() {
+        assert_eq!(slashed(Path::new(r".\dist\a\b.svg")), "./dist/a/b.svg");
+        assert_eq!(slashed(&Path::new("./dist").join("a")), "./dist/a");
+    }
+
+    #[test]
+    fn source_result_validation_works() {
+        let config = |source: &str, result: &str| {
+            BusterBuilder::default()
+                .source(source)
+                .result(result)
+                .follow_links(true)
+                .build()
+        };
+        assert!(config("./dist", "/tmp/prodvalidation").is_ok());
+        assert!(config("./nonexistent", "/tmp/prodvalidation").is_err());
+        assert!(config("./dist", "./dist").is_err());
+        assert!(config("./dist", "./dist/../dist/").is_err());
+        assert!(config("./dist", "./dist/prod").is_err());
+    }
+
+    #[test]
+    fn no_hash_validation_works() {
+        let types = vec![
+            mime::IMAGE_PNG,
+            mime::IMAGE_SVG,
+            mime::IMAGE_JPEG,
+            mime::IMAGE_GIF,
+        ];
+
+        let no_hash =
+            NoHashCategory::file_paths(["bbell.svg", "eye.svg", "a/b/c/d/s/d/svg/10.svg"]);
+
+        assert!(BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prod2i")
+            .mime_types(types)
+            .follow_links(true)
+            .prefix("/test")
+            .no_hash(vec![no_hash.clone()])
+            .build()
+            .is_err())
+    }
+
+    fn no_specific_mime() {
+        delete_file();
+        //use std::{thread, time};
+
+        //let sleep = time::Duration::from_secs(4);
+
+        //thread::sleep(sleep);
+
+        const WASM: &str = "858fd6c482cc75111d54.module.wasm";
+        let no_hash_files = vec![WASM, "bell.svg", "eye.svg", "a/b/c/d/s/d/svg/10.svg"];
+        let no_hash = NoHashCategory::file_paths(no_hash_files.clone());
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prod2ii")
+            .follow_links(true)
+            .no_hash(vec![no_hash.clone()])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let no_hash_file = Path::new(&config.result).join(WASM);
+        assert!(files.map.iter().any(|(k, v)| {
+            let source = Path::new(&config.source).join(k);
+            let dest = Path::new(&v.path);
+            dest.file_name() == no_hash_file.file_name()
+                && dest.exists()
+                && source.file_name() == dest.file_name()
+        }));
+
+        no_hash_files.iter().for_each(|file| {
+            assert!(files.map.iter().any(|(k, v)| {
+                let source = Path::new(k);
+                let dest = Path::new(&v.path);
+                let no_hash = Path::new(file);
+                source == Path::new(&config.source).join(file)
+                    && dest.exists()
+                    && no_hash.file_name() == dest.file_name()
+            }));
+        });
+
+        for (k, v) in files.map.iter() {
+            let src = Path::new(&k);
+            let dest = Path::new(&v.path);
+
+            assert_eq!(src.exists(), dest.exists());
+        }
+
+        cleanup(&config);
+    }
+
+    fn prefix_works() {
+        delete_file();
+        let types = vec![
+            mime::IMAGE_PNG,
+            mime::IMAGE_SVG,
+            mime::IMAGE_JPEG,
+            mime::IMAGE_GIF,
+        ];
+
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prod2i")
+            .mime_types(types)
+            .follow_links(true)
+            .prefix("/test")
+            .build()
+            .unwrap();
+
+        config.process().unwrap();
+        let files = Files::load();
+
+        assert_eq!(files.prefix.as_deref(), Some("/test"));
+        for (k, v) in files.map.into_iter() {
+            let src = Path::new(&k);
+            let dest = Path::new(&v.path);
+
+            assert!(dest.starts_with(&config.result));
+            assert_eq!(src.exists(), dest.exists());
+        }
+        let runtime =
+            crate::Files::try_new(&fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap()).unwrap();
+        assert_eq!(runtime.schema_version(), crate::SCHEMA_VERSION);
+        let github = runtime.get("./dist/github.svg").unwrap();
+        assert_eq!(
+            runtime.get_url("./dist/github.svg").unwrap(),
+            format!("/test{}", github)
+        );
+
+        cleanup(&config);
+
+        for prefix in ["test", "test/", "/test/", "//test//"] {
+            let config = BusterBuilder::default()
+                .source("./dist")
+                .result("/tmp/prod2i")
+                .mime_types(vec![mime::IMAGE_SVG])
+                .follow_links(true)
+                .prefix(prefix)
+                .build()
+                .unwrap();
+            config.process().unwrap();
+            let runtime =
+                crate::Files::try_new(&fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap())
+                    .unwrap();
+            let github = runtime.get("./dist/github.svg").unwrap();
+            assert_eq!(
+                runtime.get_url("./dist/github.svg").unwrap(),
+                format!("/test{}", github)
+            );
+            cleanup(&config);
+        }
+
+        for prefix in ["", "/"] {
+            let config = BusterBuilder::default()
+                .source("./dist")
+                .result("/tmp/prod2i")
+                .mime_types(vec![mime::IMAGE_SVG])
+                .follow_links(true)
+                .prefix(prefix)
+                .build()
+                .unwrap();
+            config.process().unwrap();
+            let files = Files::load();
+            assert!(files.prefix.is_none());
+            let runtime =
+                crate::Files::try_new(&fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap())
+                    .unwrap();
+            assert_eq!(
+                runtime.get_url("./dist/github.svg"),
+                runtime.get("./dist/github.svg").map(String::from)
+            );
+            cleanup(&config);
+        }
+
+        for prefix in ["/test/../prod", "/test?v=1"] {
+            assert!(BusterBuilder::default()
+                .source("./dist")
+                .result("/tmp/prod2i")
+                .follow_links(true)
+                .prefix(prefix)
+                .build()
+                .is_err());
+        }
+    }
+
+    fn no_hash_extension_works() {
+        delete_file();
+        use std::{thread, time};
+
+        let sleep = time::Duration::from_secs(4);
+        const APPLICATION_WASM: &str = "wasm";
+        const WASM: &str = "858fd6c482cc75111d54.module.wasm";
+
+        thread::sleep(sleep);
+
+        let no_hash_extensions = vec![APPLICATION_WASM];
+        let no_hash_ext = NoHashCategory::file_extensions(no_hash_extensions.clone());
+
+        let no_hash_paths = vec!["bell.svg", "eye.svg", "a/b/c/d/s/d/svg/10.svg"];
+        let no_hash_cat = NoHashCategory::file_paths(no_hash_paths.clone());
+        let no_hash = vec![no_hash_cat, no_hash_ext];
+
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodnohashextension")
+            .follow_links(true)
+            .no_hash(no_hash.clone())
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        assert!(files.map.iter().any(|(_k, v)| {
+            let dest = Path::new(&v.path);
+            dest.extension().unwrap().to_str().unwrap() == APPLICATION_WASM && dest.exists()
+        }));
+
+        let no_hash_file = Path::new(&config.result).join(WASM);
+        assert!(files.map.iter().any(|(k, v)| {
+            let source = Path::new(&config.source).join(k);
+            let dest = Path::new(&v.path);
+            dest.file_name() == no_hash_file.file_name()
+                && dest.exists()
+                && source.file_name() == dest.file_name()
+        }));
+
+        no_hash_paths.iter().for_each(|file| {
+            assert!(files.map.iter().any(|(k, v)| {
+                let source = Path::new(k);
+                let dest = Path::new(&v.path);
+                let no_hash = Path::new(file);
+                source == Path::new(&config.source).join(file)
+                    && dest.exists()
+                    && no_hash.file_name() == dest.file_name()
+            }));
+        });
+
+        for (k, v) in files.map.iter() {
+            let src = Path::new(&k);
+            let dest = Path::new(&v.path);
+
+            assert_eq!(src.exists(), dest.exists());
+        }
+
+        cleanup(&config);
+    }
+
+    fn sanitize_works() {
+        delete_file();
+        let source = fixture(
+            "sanitize",
+            &[
+                ("my logo#1.svg", "<svg></svg>"),
+                ("caf\u{e9} icons/menu.svg", "<svg />"),
+            ],
+        );
+
+        let config = BusterBuilder::default()
+            .source(source.as_str())
+            .result("/tmp/prodsanitizepercent")
+            .follow_links(true)
+            .sanitize(Sanitize::PercentEncode)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let logo = &files.map[&format!("{}/my logo#1.svg", source)].path;
+        assert!(logo.starts_with("/tmp/prodsanitizepercent/my%20logo%231."));
+        let menu = &files.map[&format!("{}/caf\u{e9} icons/menu.svg", source)].path;
+        assert!(menu.starts_with("/tmp/prodsanitizepercent/caf%C3%A9%20icons/menu."));
+        // files on disk keep their names
+        for v in files.map.values() {
+            let name = v
+                .path
+                .replace("%20", " ")
+                .replace("%23", "#")
+                .replace("%C3%A9", "\u{e9}");
+            assert!(Path::new(&name).exists());
+        }
+
+        // stale outputs are found on disk and .htaccess names them as they are
+        let stale = sanitize::percent_decode(logo);
+        fs::write(format!("{}/my logo#1.svg", source), "<svg><g/></svg>").unwrap();
+        let config = BusterBuilder::default()
+            .source(source.as_str())
+            .result("/tmp/prodsanitizepercent")
+            .follow_links(true)
+            .sanitize(Sanitize::PercentEncode)
+            .htaccess(true)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert!(!Path::new(&stale).exists());
+        let logo = sanitize::percent_decode(&files.map[&format!("{}/my logo#1.svg", source)].path);
+        assert!(Path::new(&logo).exists());
+        let htaccess = fs::read_to_string("/tmp/prodsanitizepercent/.htaccess").unwrap();
+        let name = logo.rsplit('/').next().unwrap().replace('.', "\\.");
+        assert!(htaccess.contains(&name));
+        assert!(!htaccess.contains('%'));
+        cleanup(&config);
+
+        let config = BusterBuilder::default()
+            .source(source.as_str())
+            .result("/tmp/prodsanitizetransliterate")
+            .follow_links(true)
+            .sanitize(Sanitize::Transliterate)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let logo = &files.map[&format!("{}/my logo#1.svg", source)].path;
+        assert!(logo.starts_with("/tmp/prodsanitizetransliterate/my-logo_1."));
+        let menu = &files.map[&format!("{}/caf\u{e9} icons/menu.svg", source)].path;
+        assert!(menu.starts_with("/tmp/prodsanitizetransliterate/cafe-icons/menu."));
+        for v in files.map.values() {
+            assert!(Path::new(&v.path).exists());
+        }
+        assert!(!Path::new("/tmp/prodsanitizetransliterate/caf\u{e9} icons").exists());
+        cleanup(&config);
+
+        // names that only differ in what gets replaced can't share a path
+        for names in [
+            ["a b.svg", "a-b.svg"],
+            ["\u{65e5}\u{672c}.svg", "\u{4e2d}\u{6587}.svg"],
+        ] {
+            let source = fixture(
+                "sanitize-collision",
+                &[(names[0], "<svg></svg>"), (names[1], "<svg />")],
+            );
+            let config = BusterBuilder::default()
+                .source(source.as_str())
+                .result("/tmp/prodsanitizecollision")
+                .follow_links(true)
+                .no_hash(vec![NoHashCategory::file_paths(names)])
+                .sanitize(Sanitize::Transliterate)
+                .build()
+                .unwrap();
+            let err = io::Error::from(config.process().unwrap_err());
+            assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+            assert!(err.to_string().ends_with("once sanitized"));
+            cleanup(&config);
+        }
+    }
+
+    fn compound_extensions_works() {
+        delete_file();
+        let source = fixture(
+            "compound",
+            &[
+                ("app.min.js", "console.log(1)"),
+                ("vendor.js.map", "{}"),
+                ("style.css", "body {}"),
+                ("min.js", "console.log(2)"),
+            ],
+        );
+
+        let config = BusterBuilder::default()
+            .source(source.as_str())
+            .result("/tmp/prodcompound")
+            .follow_links(true)
+            .compound_extensions(vec!["min.js", "js.map"])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let get = |name: &str| {
+            let dest = &files.map[&format!("{}/{}", source, name)].path;
+            assert!(Path::new(dest).exists());
+            Path::new(dest)
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_owned()
+        };
+        let hash_of = |contents: &str| naming::hash(contents.as_bytes());
+
+        assert_eq!(
+            get("app.min.js"),
+            format!("app.{}.min.js", hash_of("console.log(1)"))
+        );
+        assert_eq!(
+            get("vendor.js.map"),
+            format!("vendor.{}.js.map", hash_of("{}"))
+        );
+        assert_eq!(
+            get("style.css"),
+            format!("style.{}.css", hash_of("body {}"))
+        );
+        // a compound extension needs a stem in front of it
+        assert_eq!(
+            get("min.js"),
+            format!("min.{}.js", hash_of("console.log(2)"))
+        );
+        cleanup(&config);
+    }
+
+    fn codegen_works() {
+        delete_file();
+        const CODEGEN: &str = "/tmp/cache-buster-codegen-assets.rs";
+        const CODEGEN_MAP: &str = "/tmp/cache-buster-codegen-filemap.rs";
+        const JS_MANIFEST: &str = "/tmp/cache-buster-codegen-manifest.ts";
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodcodegen")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .codegen(CODEGEN)
+            .codegen_map(CODEGEN_MAP)
+            .js_manifest(JS_MANIFEST)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let src = fs::read_to_string(CODEGEN).unwrap();
+        assert!(src.contains("pub enum Asset {"));
+        let github = &files.map["./dist/github.svg"].path;
+        assert!(src.contains(&format!("Asset::GithubSvg => {:?},", github)));
+        let url = &github[files.base_dir.len()..];
+        assert!(src.contains(&format!("Asset::GithubSvg => {:?},", url)));
+        assert!(src.contains("    ABCDSDSvgCreditCardSvg,\n"));
+        assert_eq!(src.matches("    /// `./dist/").count(), files.map.len());
+
+        let src = fs::read_to_string(CODEGEN_MAP).unwrap();
+        assert!(src.contains(&format!("(\"./dist/github.svg\", {:?}),\n", github)));
+        assert_eq!(src.matches("(\"./dist/").count(), files.map.len());
+
+        let src = fs::read_to_string(JS_MANIFEST).unwrap();
+        let url = &github[files.base_dir.len()..];
+        assert!(src.contains(&format!("  \"./dist/github.svg\": {:?},\n", url)));
+        assert!(src.contains("  | \"./dist/github.svg\"\n"));
+
+        let _ = fs::remove_file(CODEGEN);
+        let _ = fs::remove_file(CODEGEN_MAP);
+        let _ = fs::remove_file(JS_MANIFEST);
+        cleanup(&config);
+
+        let missing = "/tmp/cache-buster-codegen-missing/filemap.rs";
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodcodegen")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .codegen_map(missing)
+            .build()
+            .unwrap();
+        let err = config.process().unwrap_err();
+        assert!(err.to_string().contains(missing), "{}", err);
+        cleanup(&config);
+    }
+
+    fn hash_placement_directory_works() {
+        delete_file();
+        let no_hash = NoHashCategory::file_paths(["bell.svg"]);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodhashdir")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .hash_placement(HashPlacement::Directory)
+            .no_hash(vec![no_hash])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let build_dir = Path::new(&files.map["./dist/github.svg"].path)
+            .strip_prefix(&config.result)
+            .unwrap()
+            .components()
+            .next()
+            .unwrap()
+            .as_os_str()
+            .to_owned();
+        assert_eq!(build_dir.len(), 64);
+
+        for (k, v) in files.map.iter() {
+            assert!(Path::new(&v.path).exists());
+            let rel = Path::new(k).strip_prefix(&config.source).unwrap();
+            if k == "./dist/bell.svg" {
+                assert_eq!(Path::new(&v.path), Path::new(&config.result).join(rel));
+            } else {
+                assert_eq!(
+                    Path::new(&v.path),
+                    Path::new(&config.result).join(&build_dir).join(rel)
+                );
+            }
+        }
+        cleanup(&config);
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn manifest_compression_works() {
+        let mut compressions = Vec::new();
+        #[cfg(feature = "gzip")]
+        compressions.push(ManifestCompression::Gzip);
+        #[cfg(feature = "zstd")]
+        compressions.push(ManifestCompression::Zstd);
+
+        for compression in compressions.drain(..) {
+            delete_file();
+            let config = BusterBuilder::default()
+                .source("./dist")
+                .result("/tmp/prodcompressed")
+                .mime_types(vec![mime::IMAGE_SVG])
+                .follow_links(true)
+                .manifest_compression(compression)
+                .build()
+                .unwrap();
+            config.process().unwrap();
+
+            assert!(!Path::new(CACHE_BUSTER_DATA_FILE).exists());
+            let compressed = fs::read(compression.path()).unwrap();
+            let files = crate::Files::try_from_bytes(&compressed).unwrap();
+            let github = files.get_full_path("./dist/github.svg").unwrap();
+            assert!(Path::new(github).exists());
+
+            let _ = fs::remove_file(compression.path());
+            cleanup(&config);
+        }
+    }
+
+    #[cfg(feature = "images")]
+    fn responsive_images_works() {
+        use crate::images::{ImageEncoder, ImageFormat};
+
+        // writes what was asked for, skipping 960px WebP
+        struct Describe;
+
+        impl ImageEncoder for Describe {
+            fn encode(
+                &self,
+                _path: &Path,
+                _input: &[u8],
+                width: Option<u32>,
+                format: ImageFormat,
+            ) -> Result<Option<Vec<u8>>, Error> {
+                if format == ImageFormat::WebP && width == Some(960) {
+                    return Ok(None);
+                }
+                Ok(Some(format!("{:?} {:?}", format, width).into_bytes()))
+            }
+        }
+
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodresponsiveimages")
+            .mime_types(vec![mime::IMAGE_SVG, mime::IMAGE_PNG])
+            .follow_links(true)
+            .responsive_images(
+                Describe,
+                [480, 960],
+                [ImageFormat::WebP, ImageFormat::Original],
+            )
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        assert!(files.map["./dist/github.svg"].images.is_empty());
+        let icon = &files.map["./dist/icon.png"];
+        let images: Vec<(Option<u32>, &str)> = icon
+            .images
+            .iter()
+            .map(|image| (image.width, image.mime.as_str()))
+            .collect();
+        assert_eq!(
+            images,
+            vec![
+                (None, "image/webp"),
+                (Some(480), "image/webp"),
+                (Some(480), "image/png"),
+                (Some(960), "image/png"),
+            ]
+        );
+        let webp = &icon.images[1];
+        assert!(webp
+            .path
+            .starts_with("/tmp/prodresponsiveimages/icon-480w."));
+        assert!(webp.path.ends_with(".webp"));
+        assert_eq!(fs::read_to_string(&webp.path).unwrap(), "WebP Some(480)");
+
+        let runtime =
+            crate::Files::try_new(&fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap()).unwrap();
+        assert_eq!(
+            runtime.get_srcset("./dist/icon.png", "image/webp").unwrap(),
+            format!("{} 480w", &webp.path["/tmp/prodresponsiveimages".len()..])
+        );
+        cleanup(&config);
+    }
+
+    #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+    fn precompress_works() {
+        delete_file();
+        let precompress = vec![
+            #[cfg(feature = "gzip")]
+            Precompression::Gzip,
+            #[cfg(feature = "brotli")]
+            Precompression::Brotli,
+            #[cfg(feature = "zstd")]
+            Precompression::Zstd,
+        ];
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodprecompress")
+            .mime_types(vec![mime::IMAGE_SVG, mime::IMAGE_PNG])
+            .follow_links(true)
+            .precompress(precompress.clone())
+            .precompress_mime_types(vec![mime::IMAGE_SVG])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let github = &files.map["./dist/github.svg"];
+        assert_eq!(github.precompressed.len(), precompress.len());
+        for precompression in precompress.iter() {
+            let path = &github.precompressed[precompression.encoding()];
+            assert_eq!(
+                path,
+                &format!("{}{}", github.path, precompression.extension())
+            );
+            assert!(Path::new(path).exists());
+        }
+        #[cfg(feature = "gzip")]
+        {
+            use std::io::Read;
+
+            let mut decoded = Vec::new();
+            flate2::read::GzDecoder::new(
+                fs::read(&github.precompressed["gzip"]).unwrap().as_slice(),
+            )
+            .read_to_end(&mut decoded)
+            .unwrap();
+            assert_eq!(decoded, fs::read(&github.path).unwrap());
+        }
+        assert!(files.map["./dist/icon.png"].precompressed.is_empty());
+        cleanup(&config);
+    }
+
+    fn output_names_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodoutputnames")
+            .mime_types(vec![mime::IMAGE_SVG, mime::IMAGE_PNG])
+            .follow_links(true)
+            .output_names(vec![
+                ("icon.png", "favicon.png"),
+                ("svg/github.svg", "github.svg"),
+            ])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        assert_eq!(
+            files.map["./dist/icon.png"].path,
+            "/tmp/prodoutputnames/favicon.png"
+        );
+        assert_eq!(
+            files.map["./dist/svg/github.svg"].path,
+            "/tmp/prodoutputnames/svg/github.svg"
+        );
+        let icon = fs::read("./dist/icon.png").unwrap();
+        assert_eq!(
+            files.map["./dist/icon.png"].hash.as_ref().unwrap(),
+            &naming::hash(&icon)
+        );
+        for v in files.map.values() {
+            assert!(Path::new(&v.path).exists());
+            assert!(v.hash.is_some());
+        }
+        cleanup(&config);
+
+        // two files written to one destination
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodoutputnames")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .output_names(vec![("svg/github.svg", "bell.svg")])
+            .no_hash(vec![NoHashCategory::file_paths(["svg/bell.svg"])])
+            .build()
+            .unwrap();
+        let err = io::Error::from(config.process().unwrap_err());
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert!(err.to_string().contains("both write to svg/bell.svg"));
+        assert!(!Path::new(CACHE_BUSTER_DATA_FILE).exists());
+        cleanup(&config);
+
+        assert!(BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodoutputnames")
+            .follow_links(true)
+            .output_names(vec![("nonexistent.png", "favicon.png")])
+            .build()
+            .is_err());
+    }
+
+    fn reproducible_works() {
+        let run = || {
+            delete_file();
+            let config = BusterBuilder::default()
+                .source("./dist")
+                .result("/tmp/prodreproducible")
+                .mime_types(vec![mime::IMAGE_SVG])
+                .follow_links(true)
+                .reproducible(true)
+                .build()
+                .unwrap();
+            config.process().unwrap();
+            let manifest = fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap();
+            for v in Files::load().map.values() {
+                let mtime = fs::metadata(&v.path).unwrap().modified().unwrap();
+                assert_eq!(mtime, Buster::source_date_epoch());
+            }
+            cleanup(&config);
+            manifest
+        };
+        let first = run();
+        assert_eq!(first, run());
+
+        let files: Files = serde_json::from_str(&first).unwrap();
+        let keys: Vec<&String> = files.map.keys().collect();
+        let positions: Vec<usize> = keys
+            .iter()
+            .map(|k| first.find(k.as_str()).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            delete_file();
+            let source = fixture("reproducible-readonly", &[("logo.svg", "<svg></svg>")]);
+            let logo = format!("{}/logo.svg", source);
+            fs::set_permissions(&logo, fs::Permissions::from_mode(0o444)).unwrap();
+            let config = BusterBuilder::default()
+                .source(source.as_str())
+                .result("/tmp/prodreproduciblereadonly")
+                .follow_links(true)
+                .reproducible(true)
+                .build()
+                .unwrap();
+            config.process().unwrap();
+            let copy = fs::metadata(&Files::load().map[&logo].path).unwrap();
+            assert!(copy.permissions().readonly());
+            assert_eq!(copy.modified().unwrap(), Buster::source_date_epoch());
+            cleanup(&config);
+            fs::set_permissions(&logo, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+    }
+
+    fn events_works() {
+        delete_file();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodevents")
+            .mime_types(vec![mime::IMAGE_PNG])
+            .follow_links(true)
+            .events(tx)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let events: Vec<ProcessEvent> = rx.try_iter().collect();
+        let files = Files::load();
+
+        let count = |f: fn(&ProcessEvent) -> bool| events.iter().filter(|e| f(e)).count();
+        assert_eq!(count(|e| matches!(e, ProcessEvent::Hashed { .. })), 1);
+        assert_eq!(count(|e| matches!(e, ProcessEvent::Copied { .. })), 1);
+        assert!(count(|e| matches!(e, ProcessEvent::Skipped { .. })) > 1);
+        assert_eq!(
+            events[events.len() - 2..],
+            [
+                ProcessEvent::Copied {
+                    source: PathBuf::from("./dist/icon.png"),
+                    destination: PathBuf::from(&files.map["./dist/icon.png"].path),
+                },
+                ProcessEvent::Finished { total: 1 },
+            ]
+        );
+        cleanup(&config);
+    }
+
+    fn observer_works() {
+        delete_file();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodobserver")
+            .mime_types(vec![mime::IMAGE_PNG])
+            .follow_links(true)
+            .observer(move |event| sink.lock().unwrap().push(event))
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            seen.iter()
+                .filter(|e| matches!(e, ProcessEvent::Hashed { .. }))
+                .count(),
+            1
+        );
+        assert!(seen
+            .iter()
+            .any(|e| matches!(e, ProcessEvent::Skipped { .. })));
+        assert_eq!(seen.last(), Some(&ProcessEvent::Finished { total: 1 }));
+        cleanup(&config);
+    }
+
+    fn etag_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodetag")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .etag(true)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        for (k, v) in files.map.iter() {
+            let contents = fs::read(k).unwrap();
+            let etag = v.etag.as_ref().unwrap();
+            assert_eq!(etag, &format!("\"{}\"", naming::hash(&contents)));
+        }
+        cleanup(&config);
+    }
+
+    fn hash_file_matches_process() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodhashfile")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let options = config.hash_options();
+        for (k, v) in files.map.iter() {
+            let hashed = crate::hash_file(k, &options).unwrap();
+            assert!(v.path.ends_with(&format!("/{}", hashed.name)));
+        }
+        cleanup(&config);
+    }
+
+    fn watch_works() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        use crate::watch::WatchOptionsBuilder;
+
+        delete_file();
+        let source = fixture("watch", &[("a.svg", "<svg></svg>")]);
+        let config = BusterBuilder::default()
+            .source(source.as_str())
+            .result("/tmp/prodwatch")
+            .follow_links(true)
+            .build()
+            .unwrap();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let summaries = Arc::new(Mutex::new(Vec::new()));
+        let recorded = summaries.clone();
+        let options = WatchOptionsBuilder::default()
+            .interval(Duration::from_millis(20))
+            .quiet(true)
+            .stop(stop.clone())
+            .on_rebuild(move |summary| recorded.lock().unwrap().push(summary.clone()))
+            .build()
+            .unwrap();
+
+        let watcher = {
+            let config = config.clone();
+            std::thread::spawn(move || config.watch(&options))
+        };
+        let wait_for = |count: usize| {
+            for _ in 0..250 {
+                if summaries.lock().unwrap().len() >= count {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            panic!("no rebuild");
+        };
+
+        wait_for(1);
+        fs::write(format!("{}/b.svg", source), "<svg />").unwrap();
+        wait_for(2);
+        // removing source is reported, and watching carries on
+        fs::remove_dir_all(&source).unwrap();
+        wait_for(3);
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(summaries.lock().unwrap().len(), 3);
+        fixture("watch", &[("a.svg", "<svg></svg>")]);
+        wait_for(4);
+        stop.store(true, Ordering::SeqCst);
+        watcher.join().unwrap().unwrap();
+
+        let summaries = summaries.lock().unwrap();
+        assert_eq!(summaries[0].files, 1);
+        assert_eq!(summaries[1].rebuild, 2);
+        assert_eq!(summaries[1].files, 2);
+        assert!(summaries[..2].iter().all(|summary| summary.error.is_none()));
+        assert!(summaries[2].error.is_some());
+        assert_eq!(summaries[3].files, 1);
+        assert!(summaries[3].error.is_none());
+        cleanup(&config);
+    }
+
+    fn entry_metadata_works() {
+        delete_file();
+        let no_hash = NoHashCategory::file_paths(["bell.svg"]);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodentrymetadata")
+            .mime_types(vec![mime::IMAGE_SVG, mime::IMAGE_PNG])
+            .follow_links(true)
+            .no_hash(vec![no_hash])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        for (k, v) in files.map.iter() {
+            assert_eq!(v.size, Some(fs::metadata(k).unwrap().len()));
+        }
+        let icon = &files.map["./dist/icon.png"];
+        assert_eq!(icon.mime.as_deref(), Some("image/png"));
+        assert!(icon.hash.is_some());
+        let bell = &files.map["./dist/bell.svg"];
+        assert_eq!(bell.mime.as_deref(), Some("image/svg+xml"));
+        assert!(bell.hash.is_none());
+        assert!(bell.cache_key.is_none());
+
+        let runtime =
+            crate::Files::try_new(&fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap()).unwrap();
+        assert_eq!(runtime.get_size("./dist/icon.png"), icon.size);
+        assert_eq!(runtime.get_mime("./dist/icon.png"), Some("image/png"));
+        cleanup(&config);
+    }
+
+    fn globs_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodglobs")
+            .follow_links(true)
+            .include_globs(vec!["**/*.svg"])
+            .exclude_globs(vec!["svg/**"])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        assert!(files.map.contains_key("./dist/github.svg"));
+        assert!(files.map.keys().all(|k| k.ends_with(".svg")));
+        assert!(!files.map.keys().any(|k| k.starts_with("./dist/svg/")));
+        assert!(files.skipped.contains("svg/github.svg"));
+        assert!(files.skipped.contains("icon.png"));
+        cleanup(&config);
+
+        let invalid = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodglobs")
+            .follow_links(true)
+            .exclude_globs(vec!["a/**[/b"])
+            .build();
+        assert!(invalid.is_err());
+    }
+
+    fn exclude_dirs_works() {
+        delete_file();
+        let source = fixture(
+            "exclude-dirs",
+            &[
+                ("a.css", "a {}"),
+                ("node_modules/lib/b.css", "b {}"),
+                ("css/node_modules/c.css", "c {}"),
+                ("docs/drafts/d.css", "d {}"),
+                ("docs/e.css", "e {}"),
+                ("drafts/f.css", "f {}"),
+            ],
+        );
+        for respect_ignore_files in [false, true] {
+            let config = BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/prodexcludedirs")
+                .follow_links(true)
+                .respect_ignore_files(respect_ignore_files)
+                .exclude_dirs(vec!["node_modules", "docs/drafts/"])
+                .build()
+                .unwrap();
+            config.process().unwrap();
+            let files = Files::load();
+            let mut keys: Vec<&str> = files.map.keys().map(|k| &k[source.len() + 1..]).collect();
+            keys.sort();
+            assert_eq!(keys, vec!["a.css", "docs/e.css", "drafts/f.css"]);
+            assert!(files.skipped.is_empty());
+            cleanup(&config);
+        }
+    }
+
+    fn max_depth_works() {
+        delete_file();
+        let source = fixture(
+            "max-depth",
+            &[
+                ("a.css", "a {}"),
+                ("css/b.css", "b {}"),
+                ("cache/x/y/c.css", "c {}"),
+            ],
+        );
+        for respect_ignore_files in [false, true] {
+            let config = BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/prodmaxdepth")
+                .follow_links(true)
+                .respect_ignore_files(respect_ignore_files)
+                .max_depth(2)
+                .build()
+                .unwrap();
+            config.process().unwrap();
+            let files = Files::load();
+            assert_eq!(files.map.len(), 2);
+            assert!(files.map.contains_key(&format!("{}/css/b.css", source)));
+            cleanup(&config);
+        }
+    }
+
+    fn sources_works() {
+        delete_file();
+        let dist = fixture("sources-dist", &[("a.css", "a {}"), ("vendor.js", "v")]);
+        let icons = fixture("sources-icons", &[("icons/b.svg", "<svg/>")]);
+        let config = BusterBuilder::default()
+            .sources(vec![dist.clone(), icons.clone()])
+            .result("/tmp/prodsources")
+            .follow_links(true)
+            .no_hash(vec![NoHashCategory::file_paths([
+                "vendor.js",
+                "icons/b.svg",
+            ])])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(files.map.len(), 3);
+        assert!(Path::new(&files.map[&format!("{}/a.css", dist)].path).exists());
+        assert_eq!(
+            files.map[&format!("{}/icons/b.svg", icons)].path,
+            "/tmp/prodsources/icons/b.svg"
+        );
+        assert_eq!(
+            files.map[&format!("{}/vendor.js", dist)].path,
+            "/tmp/prodsources/vendor.js"
+        );
+        cleanup(&config);
+
+        let clash = fixture("sources-clash", &[("a.css", "b {}")]);
+        let config = BusterBuilder::default()
+            .source(dist)
+            .sources(vec![clash])
+            .result("/tmp/prodsources")
+            .follow_links(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.process().unwrap_err().kind(),
+            ErrorKind::AlreadyExists
+        );
+        cleanup(&config);
+
+        // overlapping sources pick up files twice, under one key
+        let config = BusterBuilder::default()
+            .sources(vec![icons.clone(), format!("{}/icons", icons)])
+            .result("/tmp/prodsources")
+            .follow_links(true)
+            .build()
+            .unwrap();
+        let err = config.process().unwrap_err();
+        let key = format!("{}/icons/b.svg", icons);
+        assert!(matches!(err, CacheBusterError::DuplicateKey(k) if k == key));
+        cleanup(&config);
+
+        assert!(BusterBuilder::default()
+            .result("/tmp/prodsources")
+            .follow_links(true)
+            .build()
+            .is_err());
+    }
+
+    fn process_into_works() {
+        delete_file();
+        let app = fixture("process-into-app", &[("a.css", "a {}")]);
+        let icons = fixture("process-into-icons", &[("b.svg", "<svg/>")]);
+        let mut files = crate::Files::default();
+        let mut configs = Vec::new();
+        for (source, result) in [
+            (&app, "/tmp/prodprocessinto/app"),
+            (&icons, "/tmp/prodprocessinto/icons"),
+        ] {
+            let config = BusterBuilder::default()
+                .source(source.clone())
+                .result(result)
+                .follow_links(true)
+                .build()
+                .unwrap();
+            config.process_into(&mut files).unwrap();
+            configs.push(config);
+        }
+        let written = Files::load();
+        assert_eq!(written.map.len(), 2);
+        assert_eq!(written.base_dir, "/tmp/prodprocessinto");
+        let a = files.get(format!("{}/a.css", app)).unwrap();
+        assert!(a.starts_with("/app/a."));
+        assert!(Path::new("/tmp/prodprocessinto").join(&a[1..]).exists());
+        assert!(files
+            .get(format!("{}/b.svg", icons))
+            .unwrap()
+            .starts_with("/icons/b."));
+
+        assert_eq!(
+            configs[1].process_into(&mut files).unwrap_err().kind(),
+            ErrorKind::AlreadyExists
+        );
+
+        let mut relative =
+            crate::Files::try_new(r#"{"map": {"./dist/x.css": "prod/x.css"}, "base_dir": "prod"}"#)
+                .unwrap();
+        assert_eq!(
+            configs[0].process_into(&mut relative).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(relative.get("./dist/x.css"), Some("/x.css"));
+        for config in configs.iter() {
+            cleanup(config);
+        }
+        let _ = fs::remove_dir_all("/tmp/prodprocessinto");
+    }
+
+    fn clean_works() {
+        delete_file();
+        let result = "/tmp/prodclean";
+        let _ = fs::remove_dir_all(result);
+        fs::create_dir_all(format!("{}/pkg", result)).unwrap();
+        fs::write(format!("{}/pkg/app_bg.wasm", result), "wasm").unwrap();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result(result)
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .clean(false)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert!(Path::new(&files.map["./dist/github.svg"].path).exists());
+        assert!(Path::new(result).join("pkg/app_bg.wasm").exists());
+
+        let github = files.map["./dist/github.svg"].path.clone();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result(result)
+            .mime_types(vec![mime::IMAGE_PNG])
+            .follow_links(true)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        assert!(!Path::new(&github).exists());
+        assert!(Path::new(result).join("pkg/app_bg.wasm").exists());
+        cleanup(&config);
+    }
+
+    fn keep_generations_works() {
+        delete_file();
+        let source = fixture("keep-generations", &[("a.css", "a { color: red; }")]);
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodkeepgenerations")
+            .follow_links(true)
+            .keep_generations(1)
+            .build()
+            .unwrap();
+        let key = format!("{}/a.css", source);
+        let mut paths = Vec::new();
+        for color in ["red", "green", "blue"] {
+            fs::write(&key, format!("a {{ color: {}; }}", color)).unwrap();
+            config.process().unwrap();
+            paths.push(Files::load().map[&key].path.clone());
+        }
+        assert!(!Path::new(&paths[0]).exists());
+        assert!(Path::new(&paths[1]).exists());
+        assert!(Path::new(&paths[2]).exists());
+        assert_eq!(Files::load().generations, vec![vec![paths[1].clone()]]);
+        cleanup(&config);
+    }
+
+    #[cfg(unix)]
+    fn store_works() {
+        use std::os::unix::fs::MetadataExt;
+
+        delete_file();
+        let source = fixture("store", &[("a.css", "a {}"), ("b.css", "b {}")]);
+        let store = "/tmp/cache-buster-store-processing";
+        let _ = fs::remove_dir_all(store);
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodstore")
+            .follow_links(true)
+            .hash_placement(HashPlacement::Directory)
+            .keep_generations(1)
+            .store(store)
+            .build()
+            .unwrap();
+        let a = format!("{}/a.css", source);
+        let b = format!("{}/b.css", source);
+        let mut paths = Vec::new();
+        for color in ["red", "green", "blue"] {
+            fs::write(&a, format!("a {{ color: {}; }}", color)).unwrap();
+            config.process().unwrap();
+            paths.push(Files::load().map[&b].path.clone());
+        }
+        // b.css of both kept generations is one blob
+        assert_ne!(paths[1], paths[2]);
+        let kept = fs::metadata(&paths[2]).unwrap();
+        assert_eq!(kept.ino(), fs::metadata(&paths[1]).unwrap().ino());
+        assert_eq!(kept.nlink(), 3);
+        // a.css of the dropped generation was collected
+        assert!(!Path::new(&paths[0]).exists());
+        assert_eq!(fs::read_dir(store).unwrap().count(), 3);
+
+        let inside = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodstore")
+            .store("/tmp/prodstore/store")
+            .build();
+        assert!(inside.is_err());
+        cleanup(&config);
+        fs::remove_dir_all(store).unwrap();
+    }
+
+    fn plan_works() {
+        delete_file();
+        let result = "/tmp/prodplan";
+        let _ = fs::remove_dir_all(result);
+        let no_hash = NoHashCategory::file_paths(["bell.svg"]);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result(result)
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .no_hash(vec![no_hash])
+            .build()
+            .unwrap();
+        let plan = config.plan().unwrap();
+        assert!(!Path::new(result).exists());
+        assert!(!Path::new(CACHE_BUSTER_DATA_FILE).exists());
+        assert_eq!(plan.copies.len(), plan.map.len());
+        assert_eq!(
+            plan.map["./dist/bell.svg"].path,
+            format!("{}/bell.svg", result)
+        );
+        assert!(plan.skipped.contains(&"icon.png".to_string()));
+        assert!(plan.copies.contains(&PlannedCopy {
+            source: PathBuf::from("./dist/bell.svg"),
+            destination: PathBuf::from(format!("{}/bell.svg", result)),
+        }));
+
+        config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(files.map, plan.map);
+        for copy in plan.copies.iter() {
+            assert!(copy.destination.exists());
+        }
+        cleanup(&config);
+    }
+
+    fn process_report_works() {
+        delete_file();
+        let no_hash = NoHashCategory::file_paths(["bell.svg"]);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodreport")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .no_hash(vec![no_hash])
+            .build()
+            .unwrap();
+        let report = config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(report.hashed + report.copied, files.map.len());
+        assert_eq!(report.copied, 1);
+        assert_eq!(report.skipped, files.skipped.len());
+        assert_eq!(
+            report.files.len(),
+            report.hashed + report.copied + report.skipped
+        );
+        assert_eq!(
+            report.bytes,
+            files
+                .map
+                .values()
+                .map(|entry| entry.size.unwrap())
+                .sum::<u64>()
+        );
+        assert!(report.files.contains(&FileReport {
+            source: PathBuf::from("./dist/bell.svg"),
+            outcome: Outcome::Copied {
+                destination: PathBuf::from("/tmp/prodreport/bell.svg")
+            },
+        }));
+        assert!(report.files.contains(&FileReport {
+            source: PathBuf::from("./dist/icon.png"),
+            outcome: Outcome::Skipped,
+        }));
+        cleanup(&config);
+    }
+
+    fn snapshot_works() {
+        delete_file();
+        let path = "/tmp/cache-buster-snapshot.json";
+        let no_hash = NoHashCategory::file_paths(["bell.svg"]);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodsnapshot")
+            .follow_links(true)
+            .no_hash(vec![no_hash])
+            .snapshot(path)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let first = fs::read_to_string(path).unwrap();
+        config.process().unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), first);
+
+        let snapshot = Snapshot::load(path).unwrap();
+        assert_eq!(snapshot.assets.len(), Files::load().map.len());
+        assert!(snapshot.assets["./dist/github.svg"].hashed);
+        assert!(!snapshot.assets["./dist/bell.svg"].hashed);
+        assert_eq!(
+            snapshot.assets["./dist/icon.png"].size,
+            fs::metadata("./dist/icon.png").unwrap().len()
+        );
+        fs::remove_file(path).unwrap();
+        cleanup(&config);
+    }
+
+    fn filter_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodfilter")
+            .follow_links(true)
+            .filter(|path| path.file_name().unwrap() == "github.svg")
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(files.map.len(), 3);
+        assert!(files.map.keys().all(|k| k.ends_with("/github.svg")));
+        cleanup(&config);
+    }
+
+    fn size_filters_works() {
+        delete_file();
+        let source = fixture(
+            "size-filters",
+            &[
+                ("tiny.css", "a{}"),
+                ("ok.css", "a { color: red; }"),
+                ("huge.css", &"a".repeat(4096)),
+            ],
+        );
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodsizefilters")
+            .follow_links(true)
+            .min_size(10)
+            .max_size(1024)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(files.map.len(), 1);
+        assert!(files.map.contains_key(&format!("{}/ok.css", source)));
+        assert!(files.skipped.contains("tiny.css"));
+        assert!(files.skipped.contains("huge.css"));
+        cleanup(&config);
+
+        assert!(BusterBuilder::default()
+            .source(source)
+            .result("/tmp/prodsizefilters")
+            .follow_links(true)
+            .min_size(1024)
+            .max_size(10)
+            .build()
+            .is_err());
+    }
+
+    fn include_hidden_works() {
+        delete_file();
+        let source = fixture(
+            "include-hidden",
+            &[
+                ("a.css", "a {}"),
+                (
+                    ".well-known/security.txt",
+                    "Contact: mailto:security@example.com",
+                ),
+                (".well-known/apple-app-site-association", "{}"),
+            ],
+        );
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodincludehidden")
+            .follow_links(true)
+            .include_hidden(false)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(files.map.len(), 1);
+        assert!(files.map.contains_key(&format!("{}/a.css", source)));
+        cleanup(&config);
+
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodincludehidden")
+            .mime_types(vec![mime::TEXT_CSS])
+            .follow_links(true)
+            .include_hidden(HiddenFiles::Copy)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(files.map.len(), 3);
+        for name in [
+            ".well-known/security.txt",
+            ".well-known/apple-app-site-association",
+        ] {
+            assert_eq!(
+                files.map[&format!("{}/{}", source, name)].path,
+                format!("/tmp/prodincludehidden/{}", name)
+            );
+        }
+        assert_ne!(
+            files.map[&format!("{}/a.css", source)].path,
+            "/tmp/prodincludehidden/a.css"
+        );
+        cleanup(&config);
+    }
+
+    fn protect_source_works() {
+        delete_file();
+        let source = fixture("protect-source", &[("a.css", "a {}")]);
+        let inside = format!("{}/assets.rs", source);
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodprotectsource")
+            .codegen(inside.clone())
+            .follow_links(true)
+            .protect_source(SourceProtection::Verify)
+            .build()
+            .unwrap();
+        assert!(config.process().is_err());
+        assert!(!Path::new(&inside).exists());
+
+        let inside = format!("{}/urls.txt", source);
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodprotectsource")
+            .base_url("https://example.com")
+            .warm_list(inside.clone())
+            .follow_links(true)
+            .protect_source(SourceProtection::Verify)
+            .build()
+            .unwrap();
+        assert!(config.process().is_err());
+        assert!(!Path::new(&inside).exists());
+
+        let inside = format!("{}/preload.txt", source);
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodprotectsource")
+            .preload_list(inside.clone())
+            .follow_links(true)
+            .protect_source(SourceProtection::Verify)
+            .build()
+            .unwrap();
+        assert!(config.process().is_err());
+        assert!(!Path::new(&inside).exists());
+
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodprotectsource")
+            .follow_links(true)
+            .filter(|path| fs::metadata(path).unwrap().permissions().readonly())
+            .protect_source(SourceProtection::Enforce)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        assert_eq!(Files::load().map.len(), 1);
+        let a = Path::new(&source).join("a.css");
+        assert!(!fs::metadata(a).unwrap().permissions().readonly());
+        let copy = Files::load().map.values().next().unwrap().path.clone();
+        assert!(!fs::metadata(copy).unwrap().permissions().readonly());
+        cleanup(&config);
+    }
+
+    fn dependency_assets_works() {
+        delete_file();
+        let source = fixture("dependency-assets-app", &[("app.css", "a {}")]);
+        let ui = fixture(
+            "dependency-assets-ui",
+            &[("button.css", "button {}"), ("icons/close.svg", "<svg/>")],
+        );
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/proddependencyassets")
+            .follow_links(true)
+            .dependency_assets(vec![DependencyAssets::new("ui", ui)])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        assert_eq!(files.map.len(), 3);
+        assert!(files.map.contains_key(&format!("{}/app.css", source)));
+        for (key, dir) in [
+            ("@ui/button.css", "/tmp/proddependencyassets/ui/button."),
+            (
+                "@ui/icons/close.svg",
+                "/tmp/proddependencyassets/ui/icons/close.",
+            ),
+        ] {
+            let path = &files.map[key].path;
+            assert!(path.starts_with(dir));
+            assert!(Path::new(path).exists());
+        }
+        cleanup(&config);
+    }
+
+    fn respect_ignore_files_works() {
+        delete_file();
+        let source = fixture(
+            "respect-ignore-files",
+            &[
+                (".gitignore", "*.swp\ngenerated/\n"),
+                ("a.css", "a {}"),
+                (".a.css.swp", "junk"),
+                ("b.css.swp", "junk"),
+                (".DS_Store", "junk"),
+                ("generated/c.css", "c {}"),
+            ],
+        );
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodrespectignorefiles")
+            .follow_links(true)
+            .respect_ignore_files(true)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(
+            files.map.keys().collect::<Vec<_>>(),
+            vec![&format!("{}/a.css", source)]
+        );
+        cleanup(&config);
+    }
+
+    fn track_no_hash_works() {
+        delete_file();
+        let no_hash = NoHashCategory::file_paths(["bell.svg"]);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodtracknohash")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .no_hash(vec![no_hash])
+            .etag(true)
+            .track_no_hash(true)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+
+        let files =
+            crate::Files::try_new(&fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap()).unwrap();
+        assert_eq!(files.get("./dist/bell.svg"), Some("/bell.svg"));
+        let contents = fs::read("./dist/bell.svg").unwrap();
+        let hash = naming::hash(&contents);
+        assert_eq!(files.get_hash("./dist/bell.svg"), Some(hash.as_str()));
+        assert_eq!(
+            files.get_integrity("./dist/bell.svg"),
+            Some(naming::integrity(&contents).as_str())
+        );
+        assert_eq!(
+            files.get_etag("./dist/bell.svg"),
+            Some(format!("\"{}\"", hash).as_str())
+        );
+        assert_eq!(
+            files.get_size("./dist/bell.svg"),
+            Some(contents.len() as u64)
+        );
+        cleanup(&config);
+    }
+
+    fn mime_wildcard_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodmimewildcard")
+            .mime_types(vec![mime::IMAGE_STAR])
+            .follow_links(true)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert!(files.map.contains_key("./dist/icon.png"));
+        assert!(files.map.contains_key("./dist/github.svg"));
+        assert!(files
+            .map
+            .values()
+            .all(|v| v.mime.as_deref().unwrap().starts_with("image/")));
+        cleanup(&config);
+    }
+
+    fn allowlist_works() {
+        delete_file();
+        let source = fixture(
+            "allowlist",
+            &[("a.css", "a {}"), ("b/b.css", "b {}"), ("c.css", "c {}")],
+        );
+        let list = "/tmp/cache-buster-fixtures/allowlist.txt";
+        fs::write(list, "# shipped\na.css\n\nb/b.css\n").unwrap();
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodallowlist")
+            .follow_links(true)
+            .allowlist(Allowlist::File(list.into()))
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(files.map.len(), 2);
+        assert!(files.map.contains_key(&format!("{}/b/b.css", source)));
+        assert!(files.skipped.contains("c.css"));
+
+        let config = BusterBuilder::default()
+            .source(source)
+            .result("/tmp/prodallowlist")
+            .follow_links(true)
+            .allowlist(Allowlist::Paths(vec!["a.css".into(), "d.css".into()]))
+            .build()
+            .unwrap();
+        let err = config.process().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(err.to_string().contains("d.css"));
+        cleanup(&config);
+        fs::remove_file(list).unwrap();
+    }
+
+    fn mime_overrides_works() {
+        delete_file();
+        let source = fixture(
+            "mime-overrides",
+            &[
+                ("site.webmanifest", "{}"),
+                ("app.cbx", "x"),
+                ("notes.txt", "notes"),
+            ],
+        );
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodmimeoverrides")
+            .follow_links(true)
+            .mime_types(vec![mime::APPLICATION_JSON, mime::TEXT_PLAIN])
+            .mime_overrides(vec![
+                ("webmanifest", mime::APPLICATION_JSON),
+                (".cbx", mime::TEXT_PLAIN),
+            ])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(files.map.len(), 3);
+        let mime = |name: &str| files.map[&format!("{}/{}", source, name)].mime.clone();
+        assert_eq!(mime("site.webmanifest").unwrap(), "application/json");
+        assert_eq!(mime("app.cbx").unwrap(), "text/plain");
+        cleanup(&config);
+    }
+
+    fn unknown_mime_works() {
+        let source = fixture("unknown-mime", &[("a.css", "a {}"), ("b.zzqq", "b")]);
+        let config = |policy| {
+            BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/produnknownmime")
+                .follow_links(true)
+                .mime_types(vec![mime::TEXT_CSS])
+                .unknown_mime(policy)
+                .build()
+                .unwrap()
+        };
+        let b = format!("{}/b.zzqq", source);
+
+        delete_file();
+        config(UnknownMimePolicy::Skip).process().unwrap();
+        let files = Files::load();
+        assert_eq!(files.map.len(), 1);
+        assert!(files.skipped.contains("b.zzqq"));
+
+        delete_file();
+        config(UnknownMimePolicy::CopyWithoutHash)
+            .process()
+            .unwrap();
+        let files = Files::load();
+        assert_eq!(files.map[&b].path, "/tmp/produnknownmime/b.zzqq");
+        assert!(files.map[&b].mime.is_none());
+
+        delete_file();
+        config(UnknownMimePolicy::Hash).process().unwrap();
+        let files = Files::load();
+        assert!(files.map[&b].hash.is_some());
+        assert_ne!(files.map[&b].path, "/tmp/produnknownmime/b.zzqq");
+
+        delete_file();
+        let err = config(UnknownMimePolicy::Error).process().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(matches!(err, CacheBusterError::UnknownMime(path) if path == Path::new(&b)));
+
+        // without a policy, processing fails instead of panicking
+        delete_file();
+        let err = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/produnknownmime")
+            .follow_links(true)
+            .mime_types(vec![mime::TEXT_CSS])
+            .build()
+            .unwrap()
+            .process()
+            .unwrap_err();
+        assert!(matches!(err, CacheBusterError::UnknownMime(_)));
+        cleanup(&config(UnknownMimePolicy::Error));
+    }
+
+    fn name_mangler_works() {
+        struct HashDir;
+
+        impl NameMangler for HashDir {
+            fn name(&self, path: &Path, hash: &str) -> String {
+                format!("{}/{}", &hash[..8], path.display())
+            }
+        }
+
+        delete_file();
+        let no_hash = NoHashCategory::file_paths(["bell.svg"]);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodnamemangler")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .no_hash(vec![no_hash])
+            .name_mangler(HashDir)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let github = &files.map["./dist/svg/github.svg"];
+        let hash = github.hash.as_ref().unwrap();
+        assert_eq!(
+            github.path,
+            format!("/tmp/prodnamemangler/{}/svg/github.svg", &hash[..8])
+        );
+        assert!(Path::new(&github.path).exists());
+        assert_eq!(
+            files.map["./dist/bell.svg"].path,
+            "/tmp/prodnamemangler/bell.svg"
+        );
+        cleanup(&config);
+    }
+
+    fn no_hash_globs_works() {
+        delete_file();
+        let source = fixture(
+            "no-hash-globs",
+            &[
+                ("app.js", "app"),
+                ("vendor/a/lib.js", "lib"),
+                ("vendor/a/lib.css", "lib {}"),
+            ],
+        );
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodnohashglobs")
+            .follow_links(true)
+            .no_hash(vec![NoHashCategory::globs(["vendor/**/*.js"])])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        let path = |name: &str| files.map[&format!("{}/{}", source, name)].path.clone();
+        assert_eq!(
+            path("vendor/a/lib.js"),
+            "/tmp/prodnohashglobs/vendor/a/lib.js"
+        );
+        assert_ne!(
+            path("vendor/a/lib.css"),
+            "/tmp/prodnohashglobs/vendor/a/lib.css"
+        );
+        assert_ne!(path("app.js"), "/tmp/prodnohashglobs/app.js");
+        cleanup(&config);
+
+        let invalid = BusterBuilder::default()
+            .source(source)
+            .result("/tmp/prodnohashglobs")
+            .follow_links(true)
+            .no_hash(vec![NoHashCategory::globs(["vendor/[*.js"])])
+            .build();
+        assert!(invalid.is_err());
+    }
+
+    fn warm_list_works() {
+        delete_file();
+        let path = "/tmp/cache-buster-warm-list.txt";
+        let no_hash = NoHashCategory::file_paths(["bell.svg"]);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("./prod")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .no_hash(vec![no_hash])
+            .base_url("https://example.com/")
+            .warm_list(path)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files =
+            crate::Files::try_new(&fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap()).unwrap();
+
+        let list = fs::read_to_string(path).unwrap();
+        let urls: Vec<&str> = list.lines().collect();
+        assert_eq!(urls.len(), files.entries().count() - 1);
+        let github = files.get_url("./dist/github.svg").unwrap();
+        assert!(github.starts_with("/github."));
+        assert!(urls.contains(&format!("https://example.com{}", github).as_str()));
+        assert!(!urls.contains(&"https://example.com/bell.svg"));
+        fs::remove_file(path).unwrap();
+        cleanup(&config);
+
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("./prod")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .prefix("/static/")
+            .base_url("https://example.com")
+            .warm_list(path)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files =
+            crate::Files::try_new(&fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap()).unwrap();
+        let list = fs::read_to_string(path).unwrap();
+        let github = files.get_url("./dist/github.svg").unwrap();
+        assert!(github.starts_with("/static/github."));
+        assert!(list
+            .lines()
+            .any(|url| url == format!("https://example.com{}", github)));
+        fs::remove_file(path).unwrap();
+        cleanup(&config);
+
+        let invalid = BusterBuilder::default()
+            .source("./dist")
+            .result("./prod")
+            .follow_links(true)
+            .warm_list(path)
+            .build();
+        assert!(invalid.is_err());
+    }
+
+    fn no_hash_mime_types_works() {
+        delete_file();
+        let no_hash =
+            NoHashCategory::MimeTypes(vec!["application/wasm".parse().unwrap(), mime::IMAGE_STAR]);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodnohashmimetypes")
+            .follow_links(true)
+            .no_hash(vec![no_hash])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        assert_eq!(
+            files.map["./dist/858fd6c482cc75111d54.module.wasm"].path,
+            "/tmp/prodnohashmimetypes/858fd6c482cc75111d54.module.wasm"
+        );
+        assert_eq!(
+            files.map["./dist/icon.png"].path,
+            "/tmp/prodnohashmimetypes/icon.png"
+        );
+        assert!(files.map["./dist/main.1ed8da86f47dbdff4959.bundle.js"]
+            .hash
+            .is_some());
+        cleanup(&config);
+    }
+
+    fn variants_works() {
+        delete_file();
+        let source = fixture(
+            "variants",
+            &[
+                ("css/app.light.css", "light {}"),
+                ("css/app.dark.css", "dark {}"),
+                ("css/other.css", "other {}"),
+                ("dark.css", "dark {}"),
+            ],
+        );
+        let config = BusterBuilder::default()
+            .source(source.clone())
+            .result("/tmp/prodvariants")
+            .follow_links(true)
+            .variants(vec!["light", "dark"])
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files =
+            crate::Files::try_new(&fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap()).unwrap();
+
+        let app = format!("{}/css/app.css", source);
+        let dark = files.get(format!("{}/css/app.dark.css", source)).unwrap();
+        assert!(dark.starts_with("/css/app.dark."));
+        assert_eq!(files.get_variant(&app, "dark"), Some(dark));
+        assert!(files.get_variant(&app, "light").is_some());
+        assert!(files.get_variant(&app, "sepia").is_none());
+        assert!(files.get(&app).is_none());
+        assert!(files
+            .get_variant(format!("{}/css/other.css", source), "dark")
+            .is_none());
+        cleanup(&config);
+    }
+
+    fn chunk_size_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodchunks")
+            .follow_links(true)
+            .chunk_size(16 * 1024)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let wasm = &files.map["./dist/858fd6c482cc75111d54.module.wasm"];
+        let chunks = wasm.chunks.as_ref().unwrap();
+        let contents = fs::read("./dist/858fd6c482cc75111d54.module.wasm").unwrap();
+        assert_eq!(chunks.size, 16 * 1024);
+        assert_eq!(chunks.hashes.len(), contents.len().div_ceil(16 * 1024));
+        assert_eq!(chunks.hashes[0], naming::hash(&contents[..16 * 1024]));
+        assert!(files.map["./dist/bell.svg"].chunks.is_none());
+        cleanup(&config);
+
+        assert!(BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodchunks")
+            .follow_links(true)
+            .chunk_size(0)
+            .build()
+            .is_err());
+    }
+
+    fn transform_works() {
+        struct Upper;
+
+        impl AssetTransform for Upper {
+            fn transform(&self, _path: &Path, input: Vec<u8>) -> Result<Vec<u8>, Error> {
+                Ok(input.to_ascii_uppercase())
+            }
+        }
+
+        delete_file();
+        let source = fixture(
+            "transform",
+            &[("a.css", "a {}"), ("b.js", "let b;"), ("c.txt", "c")],
+        );
+        let config = BusterBuilder::default()
+            .source(source.as_str())
+            .result("/tmp/prodtransform")
+            .mime_types(vec![
+                mime::TEXT_CSS,
+                mime::TEXT_JAVASCRIPT,
+                mime::TEXT_PLAIN,
+            ])
+            .follow_links(true)
+            .transform(mime::TEXT_CSS, Upper)
+            .transform(TransformTarget::extension("js"), Upper)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        let read = |name: &str| {
+            let entry = &files.map[&format!("{}/{}", source, name)];
+            let contents = fs::read(&entry.path).unwrap();
+            assert_eq!(entry.size, Some(contents.len() as u64));
+            assert!(entry.path.contains(&naming::hash(&contents)[..8]));
+            String::from_utf8(contents).unwrap()
+        };
+        assert_eq!(read("a.css"), "A {}");
+        assert_eq!(read("b.js"), "LET B;");
+        assert_eq!(read("c.txt"), "c");
+        cleanup(&config);
+    }
+
+    fn rewrite_js_works() {
+        delete_file();
+        let source = fixture(
+            "rewrite-js",
+            &[
+                (
+                    "main.js",
+                    "import \"./chunk.js\";\nimport { a } from './lib/a.js';\n//# sourceMappingURL=main.js.map\n",
+                ),
+                ("main.js.map", "{}"),
+                ("chunk.js", "export const chunk = 1;\n"),
+                ("lib/a.js", "export { chunk as a } from \"../chunk.js\";\n"),
+            ],
+        );
+        let config = BusterBuilder::default()
+            .source(source.as_str())
+            .result("/tmp/prodrewritejs")
+            .mime_types(vec![mime::TEXT_STAR])
+            .follow_links(true)
+            .rewrite_js(true)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        let path = |name: &str| files.map[&format!("{}/{}", source, name)].path.clone();
+        let name = |name: &str| {
+            Path::new(&path(name))
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_owned()
+        };
+
+        let main = fs::read_to_string(path("main.js")).unwrap();
+        assert_eq!(
+            main,
+            format!(
+                "import \"./{}\";\nimport {{ a }} from './lib/{}';\n//# sourceMappingURL={}\n",
+                name("chunk.js"),
+                name("lib/a.js"),
+                name("main.js.map")
+            )
+        );
+        assert!(path("main.js").contains(&naming::hash(main.as_bytes())[..8]));
+        assert_eq!(
+            fs::read_to_string(path("lib/a.js")).unwrap(),
+            format!(
+                "export {{ chunk as a }} from \"../{}\";\n",
+                name("chunk.js")
+            )
+        );
+        cleanup(&config);
+
+        let source = fixture(
+            "rewrite-js-cycle",
+            &[
+                ("a.js", "import \"./b.js\";"),
+                ("b.js", "import \"./a.js\";"),
+            ],
+        );
+        let config = BusterBuilder::default()
+            .source(source.as_str())
+            .result("/tmp/prodrewritejs")
+            .mime_types(vec![mime::TEXT_STAR])
+            .follow_links(true)
+            .rewrite_js(true)
+            .build()
+            .unwrap();
+        assert!(config.process().is_err());
+        cleanup(&config);
+
+        assert!(BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodrewritejs")
+            .rewrite_js(true)
+            .hash_placement(HashPlacement::Directory)
+            .build()
+            .is_err());
+    }
+
+    fn preserve_metadata_works() {
+        delete_file();
+        let source = fixture("preserve-metadata", &[("run.js", "run();")]);
+        let script = Path::new(&source).join("run.js");
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&script)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o750)).unwrap();
+        }
+
+        let mut builder = BusterBuilder::default();
+        builder
+            .source(source.as_str())
+            .result("/tmp/prodpreservemetadata")
+            .mime_types(vec![mime::TEXT_JAVASCRIPT])
+            .follow_links(true)
+            .preserve_metadata(true);
+        #[cfg(unix)]
+        builder.preserve_ownership(true);
+        let config = builder.build().unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+
+        let copy = fs::metadata(&files.map[script.to_str().unwrap()].path).unwrap();
+        assert_eq!(copy.modified().unwrap(), modified);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            assert_eq!(copy.permissions().mode() & 0o777, 0o750);
+        }
+        cleanup(&config);
+
+        assert!(builder.reproducible(true).build().is_err());
+    }
+
+    fn link_strategy_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodlinkstrategy")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .link_strategy(LinkStrategy::Hardlink)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        // processing again replaces the links
+        config.process().unwrap();
+        let files = Files::load();
+
+        let github = &files.map["./dist/github.svg"].path;
+        assert_eq!(
+            fs::read(github).unwrap(),
+            fs::read("./dist/github.svg").unwrap()
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let source = fs::metadata("./dist/github.svg").unwrap();
+            assert_eq!(fs::metadata(github).unwrap().ino(), source.ino());
+        }
+        cleanup(&config);
+
+        assert!(BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodlinkstrategy")
+            .link_strategy(LinkStrategy::Hardlink)
+            .reproducible(true)
+            .build()
+            .is_err());
+
+        // copying over links of an earlier run leaves source alone
+        let source = fixture("link-strategy", &[("sw.js", "self.skipWaiting();")]);
+        let builder = |link_strategy| {
+            BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/prodlinkstrategycopy")
+                .follow_links(true)
+                .no_hash(vec![NoHashCategory::file_paths(["sw.js"])])
+                .link_strategy(link_strategy)
+                .clean(false)
+                .build()
+                .unwrap()
+        };
+        builder(LinkStrategy::Hardlink).process().unwrap();
+        let config = builder(LinkStrategy::Copy);
+        config.process().unwrap();
+        let sw = Path::new(&source).join("sw.js");
+        assert_eq!(fs::read_to_string(&sw).unwrap(), "self.skipWaiting();");
+        assert_eq!(
+            fs::read_to_string("/tmp/prodlinkstrategycopy/sw.js").unwrap(),
+            "self.skipWaiting();"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let copy = fs::metadata("/tmp/prodlinkstrategycopy/sw.js").unwrap();
+            assert_ne!(copy.ino(), fs::metadata(&sw).unwrap().ino());
+        }
+        cleanup(&config);
+    }
+
+    fn threads_works() {
+        delete_file();
+        let single = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodthreads")
+            .mime_types(vec![mime::IMAGE_SVG, mime::IMAGE_PNG])
+            .follow_links(true)
+            .build()
+            .unwrap();
+        let expected = single.plan().unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodthreads")
+            .mime_types(vec![mime::IMAGE_SVG, mime::IMAGE_PNG])
+            .follow_links(true)
+            .threads(4)
+            .events(tx)
+            .build()
+            .unwrap();
+        assert_eq!(config.plan().unwrap(), expected);
+        #[cfg(feature = "rayon")]
+        let pool: *const rayon::ThreadPool = config.pool.get().unwrap().as_ref().unwrap();
+        let report = config.process().unwrap();
+        assert_eq!(report.hashed + report.copied, expected.copies.len());
+        // runs share one pool
+        #[cfg(feature = "rayon")]
+        assert!(std::ptr::eq(
+            pool,
+            config.pool.get().unwrap().as_ref().unwrap()
+        ));
+        let files = Files::load();
+        for copy in expected.copies.iter() {
+            assert!(copy.destination.exists());
+        }
+        assert_eq!(files.map.len(), expected.map.len());
+        let copied = rx
+            .try_iter()
+            .filter(|e| matches!(e, ProcessEvent::Copied { .. }))
+            .count();
+        assert_eq!(copied, expected.copies.len());
+        cleanup(&config);
+
+        assert!(BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodthreads")
+            .threads(0)
+            .build()
+            .is_err());
+    }
+
+    #[cfg(feature = "jwalk")]
+    fn parallel_walk_works() {
+        let config = |parallel_walk| {
+            BusterBuilder::default()
+                .source("./dist")
+                .result("/tmp/prodparallelwalk")
+                .mime_types(vec![mime::IMAGE_SVG, mime::IMAGE_PNG])
+                .follow_links(true)
+                .exclude_dirs(["svg"])
+                .parallel_walk(parallel_walk)
+                .threads(4)
+                .build()
+                .unwrap()
+        };
+        let mut expected = config(false).plan().unwrap();
+        let mut plan = config(true).plan().unwrap();
+        expected.copies.sort_by(|a, b| a.source.cmp(&b.source));
+        plan.copies.sort_by(|a, b| a.source.cmp(&b.source));
+        expected.skipped.sort();
+        plan.skipped.sort();
+        assert_eq!(plan, expected);
+        assert!(!plan.map.contains_key("./dist/svg/github.svg"));
+
+        assert!(BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodparallelwalk")
+            .parallel_walk(true)
+            .respect_ignore_files(true)
+            .build()
+            .is_err());
+    }
+
+    // builder walking source on multiple threads when parallel_walk is set,
+    // which takes the jwalk feature
+    fn walk_builder(parallel_walk: bool) -> BusterBuilder {
+        #[cfg_attr(not(feature = "jwalk"), allow(unused_mut))]
+        let mut builder = BusterBuilder::default();
+        #[cfg(feature = "jwalk")]
+        builder.parallel_walk(parallel_walk);
+        #[cfg(not(feature = "jwalk"))]
+        assert!(!parallel_walk);
+        builder
+    }
+
+    // walks to run tests with, parallel ones with the jwalk feature
+    const WALKS: &[bool] = if cfg!(feature = "jwalk") {
+        &[false, true]
+    } else {
+        &[false]
+    };
+
+    fn existing_destinations_are_kept() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodexisting")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .no_hash(vec![NoHashCategory::file_paths(["bell.svg"])])
+            .build()
+            .unwrap();
+        let report = config.process().unwrap();
+        assert_eq!(report.unchanged, 0);
+        let github = Files::load().map["./dist/github.svg"].path.clone();
+        let written = fs::metadata(&github).unwrap().modified().unwrap();
+
+        // hashed files are left in place, others are copied again
+        let report = config.process().unwrap();
+        assert_eq!(report.unchanged, report.hashed);
+        assert_eq!(fs::metadata(&github).unwrap().modified().unwrap(), written);
+        cleanup(&config);
+    }
+
+    fn copy_buffer_size_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodcopybuffer")
+            .mime_types(vec![mime::IMAGE_SVG, mime::IMAGE_PNG])
+            .follow_links(true)
+            .copy_buffer_size(7)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        for (source, entry) in Files::load().map.iter() {
+            assert_eq!(fs::read(&entry.path).unwrap(), fs::read(source).unwrap());
+        }
+        cleanup(&config);
+
+        assert!(BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodcopybuffer")
+            .copy_buffer_size(0)
+            .build()
+            .is_err());
+    }
+
+    fn concurrent_processing_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodconcurrent")
+            .mime_types(vec![mime::IMAGE_SVG, mime::IMAGE_PNG])
+            .follow_links(true)
+            .build()
+            .unwrap();
+        std::thread::scope(|scope| {
+            let builds: Vec<_> = (0..4).map(|_| scope.spawn(|| config.process())).collect();
+            for build in builds {
+                build.join().unwrap().unwrap();
+            }
+        });
+        for entry in Files::load().map.values() {
+            assert!(Path::new(&entry.path).exists());
+        }
+        cleanup(&config);
+    }
+
+    fn manifest_write_is_atomic() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodatomic")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .build()
+            .unwrap();
+        // leftover of an interrupted write
+        fs::write(format!("{}.tmp", CACHE_BUSTER_DATA_FILE), "{\"map\":").unwrap();
+        config.process().unwrap();
+        assert!(!Path::new(&format!("{}.tmp", CACHE_BUSTER_DATA_FILE)).exists());
+        assert!(Files::load().map.contains_key("./dist/github.svg"));
+        cleanup(&config);
+    }
+
+    fn result_marker_works() {
+        delete_file();
+        let source = fixture("result-marker", &[("a.css", "a {}")]);
+        let config = |force| {
+            BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/prodmarker")
+                .follow_links(true)
+                .force(force)
+                .build()
+                .unwrap()
+        };
+        config(false).process().unwrap();
+        let marker = Path::new("/tmp/prodmarker").join(RESULT_MARKER);
+        assert!(marker.exists());
+
+        // files aren't removed from unmarked directories
+        fs::remove_file(&marker).unwrap();
+        fs::write(format!("{}/a.css", source), "b {}").unwrap();
+        let a = Files::load().map[&format!("{}/a.css", source)].path.clone();
+        let err = config(false).process().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert!(Path::new(&a).exists());
+
+        config(true).process().unwrap();
+        assert!(!Path::new(&a).exists());
+        assert!(marker.exists());
+        cleanup(&config(false));
+
+        assert!(BusterBuilder::default()
+            .source("./dist")
+            .result("/")
+            .build()
+            .is_err());
+    }
+
+    fn cache_works() {
+        delete_file();
+        let out_dir = "/tmp/cache-buster-out";
+        let _ = fs::remove_dir_all(out_dir);
+        let cache = "/tmp/cache-buster-out/build/cache.json";
+        let source = fixture(
+            "cache",
+            &[("main.css", "body {}"), ("main.js", "console.log(1);")],
+        );
+        let config = BusterBuilder::default()
+            .source(source.as_str())
+            .result("/tmp/prodcache")
+            .mime_types(vec![mime::TEXT_CSS, mime::TEXT_JAVASCRIPT])
+            .follow_links(true)
+            .cache(cache)
+            .build()
+            .unwrap();
+        let report = config.process().unwrap();
+        assert_eq!(report.unchanged, 0);
+        let css = Files::load().map[&format!("{}/main.css", source)]
+            .path
+            .clone();
+        let js = Files::load().map[&format!("{}/main.js", source)]
+            .path
+            .clone();
+        let written = fs::metadata(&css).unwrap().modified().unwrap();
+
+        // unchanged files are left in place
+        let report = config.process().unwrap();
+        assert_eq!(report.unchanged, 2);
+        assert_eq!(fs::metadata(&css).unwrap().modified().unwrap(), written);
+        assert_eq!(Files::load().map[&format!("{}/main.css", source)].path, css);
+
+        fs::write(format!("{}/main.js", source), "console.log(2, 3);").unwrap();
+        let report = config.process().unwrap();
+        assert_eq!(report.unchanged, 1);
+        assert!(Path::new(&css).exists());
+        assert!(!Path::new(&js).exists());
+        let js = &Files::load().map[&format!("{}/main.js", source)].path;
+        assert_eq!(fs::read_to_string(js).unwrap(), "console.log(2, 3);");
+
+        cleanup(&config);
+        fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    fn symlink_loop_works() {
+        let source = fixture("symlink-loop", &[("css/main.css", "a {}")]);
+        let link = Path::new(&source).join("css/loop");
+        std::os::unix::fs::symlink(&source, &link).unwrap();
+        for &parallel_walk in WALKS {
+            let config = walk_builder(parallel_walk)
+                .source(source.clone())
+                .result("/tmp/prodsymlinkloop")
+                .mime_types(vec![mime::TEXT_CSS])
+                .follow_links(true)
+                .build()
+                .unwrap();
+            let err = config.process().unwrap_err();
+            assert!(
+                matches!(&err, CacheBusterError::SymlinkLoop { link: l, target } if l == &link && target == Path::new(&source)),
+                "{:?}",
+                err
+            );
+            assert!(err.to_string().contains("css/loop"));
+            cleanup(&config);
+        }
+    }
+
+    #[cfg(unix)]
+    fn external_links_works() {
+        let outside = fixture("external-links-outside", &[("secret.css", "a {}")]);
+        let source = fixture("external-links", &[("main.css", "b {}")]);
+        let link = Path::new(&source).join("secret.css");
+        std::os::unix::fs::symlink(Path::new(&outside).join("secret.css"), &link).unwrap();
+        let config = |policy| {
+            BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/prodexternallinks")
+                .mime_types(vec![mime::TEXT_CSS])
+                .follow_links(true)
+                .external_links(policy)
+                .build()
+                .unwrap()
+        };
+
+        delete_file();
+        let err = config(ExternalLinks::Error).process().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert!(matches!(err, CacheBusterError::ExternalLink { path, .. } if path == link));
+
+        delete_file();
+        config(ExternalLinks::Skip).process().unwrap();
+        let files = Files::load();
+        assert_eq!(files.map.len(), 1);
+        assert!(files.map.contains_key(&format!("{}/main.css", source)));
+
+        delete_file();
+        config(ExternalLinks::Allow).process().unwrap();
+        assert_eq!(Files::load().map.len(), 2);
+
+        cleanup(&config(ExternalLinks::Error));
+    }
+
+    fn empty_source_works() {
+        let source = "/tmp/cache-buster-fixtures/empty-source";
+        let _ = fs::remove_dir_all(source);
+        fs::create_dir_all(source).unwrap();
+        let config = |policy| {
+            BusterBuilder::default()
+                .source(source)
+                .result("/tmp/prodemptysource")
+                .follow_links(true)
+                .empty_source(policy)
+                .build()
+                .unwrap()
+        };
+
+        delete_file();
+        let err = config(EmptySource::Error).process().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(matches!(err, CacheBusterError::EmptySource(path) if path == Path::new(source)));
+
+        config(EmptySource::Warn).process().unwrap();
+        assert!(Files::load().map.is_empty());
+
+        // missing sources are only accepted with Allow
+        fs::remove_dir(source).unwrap();
+        assert!(BusterBuilder::default()
+            .source(source)
+            .result("/tmp/prodemptysource")
+            .follow_links(true)
+            .build()
+            .is_err());
+        delete_file();
+        config(EmptySource::Allow).process().unwrap();
+        assert!(Files::load().map.is_empty());
+
+        cleanup(&config(EmptySource::Allow));
+    }
+
+    #[cfg(unix)]
+    fn unreadable_works() {
+        let source = fixture("unreadable", &[("main.css", "a {}")]);
+        let link = Path::new(&source).join("dangling.css");
+        std::os::unix::fs::symlink("/tmp/cache-buster-fixtures/nope.css", &link).unwrap();
+        for &parallel_walk in WALKS {
+            let config = |policy| {
+                walk_builder(parallel_walk)
+                    .source(source.clone())
+                    .result("/tmp/produnreadable")
+                    .mime_types(vec![mime::TEXT_CSS])
+                    .follow_links(true)
+                    .unreadable(policy)
+                    .build()
+                    .unwrap()
+            };
+
+            delete_file();
+            assert!(config(UnreadablePolicy::Fail).process().is_err());
+
+            for policy in [UnreadablePolicy::Skip, UnreadablePolicy::SkipWithWarning] {
+                delete_file();
+                let report = config(policy).process().unwrap();
+                assert_eq!(report.unreadable, vec![link.clone()]);
+                assert_eq!(report.hashed, 1);
+                assert_eq!(Files::load().map.len(), 1);
+            }
+            cleanup(&config(UnreadablePolicy::Fail));
+        }
+    }
+
+    fn copy_unmatched_works() {
+        let source = fixture(
+            "copy-unmatched",
+            &[("main.css", "a {}"), ("robots.txt", "User-agent: *")],
+        );
+        let config = |copy_unmatched| {
+            BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/prodcopyunmatched")
+                .mime_types(vec![mime::TEXT_CSS])
+                .follow_links(true)
+                .copy_unmatched(copy_unmatched)
+                .build()
+                .unwrap()
+        };
+        let robots = format!("{}/robots.txt", source);
+
+        delete_file();
+        let report = config(false).process().unwrap();
+        assert_eq!((report.hashed, report.copied, report.skipped), (1, 0, 1));
+        assert!(!Files::load().map.contains_key(&robots));
+        assert!(!Path::new("/tmp/prodcopyunmatched/robots.txt").exists());
+
+        delete_file();
+        let report = config(true).process().unwrap();
+        assert_eq!((report.hashed, report.copied, report.skipped), (1, 1, 0));
+        let files = Files::load();
+        assert_eq!(files.map[&robots].path, "/tmp/prodcopyunmatched/robots.txt");
+        assert_eq!(
+            fs::read_to_string("/tmp/prodcopyunmatched/robots.txt").unwrap(),
+            "User-agent: *"
+        );
+        assert_ne!(
+            files.map[&format!("{}/main.css", source)].path,
+            "/tmp/prodcopyunmatched/main.css"
+        );
+
+        cleanup(&config(false));
     }
 
-    // helper fn to create directory structure in self.base_dir
-    fn create_dir_structure(&self, path: &Path) -> Result<(), Error> {
-        for entry in WalkDir::new(&path)
-            .follow_links(self.follow_links)
-            .into_iter()
+    fn result_check_works() {
+        let result = "/tmp/prodresultcheck";
+        let _ = fs::remove_dir_all(result);
+        fs::write(result, "").unwrap();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result(result)
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .build()
+            .unwrap();
+
+        delete_file();
+        let err = config.process().unwrap_err();
+        assert!(
+            matches!(&err, CacheBusterError::ResultNotWritable { path, .. } if path == Path::new(result))
+        );
+        assert!(!Path::new(CACHE_BUSTER_DATA_FILE).exists());
+
+        // the nearest existing ancestor is probed, nothing is created
+        let nested = format!("{}/nested/dir", result);
+        let err = BusterBuilder::default()
+            .source("./dist")
+            .result(nested.as_str())
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .build()
+            .unwrap()
+            .process()
+            .unwrap_err();
+        assert!(
+            matches!(&err, CacheBusterError::ResultNotWritable { path, .. } if path == Path::new(&nested))
+        );
+        fs::remove_file(result).unwrap();
+
+        #[cfg(unix)]
         {
-            let entry = entry?;
-            let entry_path = entry.path();
-            let entry_path = Path::new(&entry_path);
+            use std::os::unix::fs::PermissionsExt;
 
-            if entry_path.is_dir() && path != entry_path {
-                Self::create_dir_structure(self, entry_path)?;
-            } else if entry_path.is_dir() {
-                let rel_location = entry_path.strip_prefix(&self.source).unwrap();
-                let destination = Path::new(&self.result).join(rel_location);
-                if !destination.exists() {
-                    fs::create_dir(destination)?
-                }
+            fs::create_dir(result).unwrap();
+            fs::set_permissions(result, fs::Permissions::from_mode(0o555)).unwrap();
+            // permission bits don't bind root
+            let probe = Path::new(result).join("probe");
+            if fs::write(&probe, "").is_ok() {
+                fs::remove_file(&probe).unwrap();
+            } else {
+                let err = config.process().unwrap_err();
+                assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+                assert!(matches!(err, CacheBusterError::ResultNotWritable { .. }));
             }
+            fs::set_permissions(result, fs::Permissions::from_mode(0o755)).unwrap();
         }
-        Ok(())
-    }
-}
-/// Filemap struct
-///
-/// maps original names to generated names
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
-struct Files {
-    /// filemap<original-path, modified-path>
-    pub map: HashMap<String, String>,
-    base_dir: String,
-}
 
-impl Files {
-    /// Initialize map
-    fn new(base_dir: &str) -> Self {
-        Files {
-            map: HashMap::default(),
-            base_dir: base_dir.into(),
-        }
+        // writable ancestors pass, result is created while processing
+        let missing = format!("{}/missing/dir", result);
+        let missing = BusterBuilder::default()
+            .source("./dist")
+            .result(missing.as_str())
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .build()
+            .unwrap();
+        missing.process().unwrap();
+        assert!(Path::new(&missing.result).join(RESULT_MARKER).exists());
+        cleanup(&config);
     }
 
-    /// Create file map: map original path to modified paths
-    fn add(&mut self, k: String, v: String) -> Result<(), &'static str> {
-        if let std::collections::hash_map::Entry::Vacant(e) = self.map.entry(k) {
-            e.insert(v);
-            Ok(())
-        } else {
-            Err("key exists")
-        }
-    }
+    #[cfg(unix)]
+    fn non_utf8_works() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
 
-    /// This crate uses compile-time environment variables to transfer
-    /// data to the main program. This funtction sets that variable
-    fn to_env(&self) {
-        let json = serde_json::to_string(&self).unwrap();
-        let res = Path::new(CACHE_BUSTER_DATA_FILE);
-        if res.exists() {
-            fs::remove_file(&res).unwrap();
+        let source = fixture("non-utf8", &[("main.css", "a {}")]);
+        let bad = Path::new(&source).join(OsStr::from_bytes(b"bad\xff.css"));
+        fs::write(&bad, "b {}").unwrap();
+        let config = |policy| {
+            BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/prodnonutf8")
+                .mime_types(vec![mime::TEXT_CSS])
+                .follow_links(true)
+                .non_utf8(policy)
+                .build()
+                .unwrap()
+        };
+
+        delete_file();
+        let err = config(NonUtf8Policy::Fail).process().unwrap_err();
+        assert!(matches!(err, CacheBusterError::NonUtf8Path(path) if path == bad));
+
+        for policy in [NonUtf8Policy::Skip, NonUtf8Policy::SkipWithWarning] {
+            delete_file();
+            let report = config(policy).process().unwrap();
+            assert_eq!((report.hashed, report.skipped), (1, 1));
+            assert!(report.files.contains(&FileReport {
+                source: bad.clone(),
+                outcome: Outcome::Skipped,
+            }));
+            assert_eq!(Files::load().map.len(), 1);
         }
-        fs::write(CACHE_BUSTER_DATA_FILE, &json).unwrap();
+        cleanup(&config(NonUtf8Policy::Fail));
     }
 
-    #[cfg(test)]
-    /// Load filemap in main program. Should be called from main program
-    fn load() -> Self {
-        let map = fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap();
-        let res: Files = serde_json::from_str(&map).unwrap();
-        res
+    fn manifest_path_works() {
+        let out_dir = "/tmp/cache-buster-out/manifest";
+        let _ = fs::remove_dir_all(out_dir);
+        let manifest = format!("{}/filemap.json", out_dir);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodmanifestpath")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .manifest_path(manifest.clone())
+            .build()
+            .unwrap();
+
+        delete_file();
+        let report = config.process().unwrap();
+        assert_eq!(report.manifest, Path::new(&manifest));
+        assert!(!Path::new(CACHE_BUSTER_DATA_FILE).exists());
+        let files = crate::Files::try_new(&fs::read_to_string(&manifest).unwrap()).unwrap();
+        let github = files.get_full_path("./dist/github.svg").unwrap();
+        assert!(Path::new(github).exists());
+
+        // the previous run is found at the configured path when cleaning
+        fs::write("/tmp/prodmanifestpath/stale.svg", "").unwrap();
+        let mut previous: Files =
+            serde_json::from_str(&fs::read_to_string(&manifest).unwrap()).unwrap();
+        previous.map.insert(
+            "./dist/stale.svg".into(),
+            Entry::new("/tmp/prodmanifestpath/stale.svg".into()),
+        );
+        fs::write(&manifest, serde_json::to_string(&previous).unwrap()).unwrap();
+        config.process().unwrap();
+        assert!(!Path::new("/tmp/prodmanifestpath/stale.svg").exists());
+
+        cleanup(&config);
+        fs::remove_dir_all(out_dir).unwrap();
     }
-}
 
-#[cfg(test)]
-pub mod tests {
-    use super::*;
+    fn out_dir_works() {
+        let out_dir = "/tmp/cache-buster-out/out-dir";
+        let _ = fs::remove_dir_all(out_dir);
+        let builder = || {
+            let mut builder = BusterBuilder::default();
+            builder
+                .source("./dist")
+                .result("/tmp/prodoutdir")
+                .mime_types(vec![mime::IMAGE_SVG])
+                .follow_links(true)
+                .out_dir(true);
+            builder
+        };
+        assert!(builder().build().is_err());
 
-    pub fn cleanup(config: &Buster<'_>) {
-        let _ = fs::remove_dir_all(&config.result);
+        std::env::set_var("OUT_DIR", out_dir);
+        assert!(builder().manifest_path("filemap.json").build().is_err());
+        let config = builder().build().unwrap();
         delete_file();
-    }
+        let report = config.process().unwrap();
+        std::env::remove_var("OUT_DIR");
 
-    pub fn delete_file() {
-        let _ = fs::remove_file(&CACHE_BUSTER_DATA_FILE);
+        let manifest = Path::new(out_dir).join("cache_buster_data.json");
+        assert_eq!(report.manifest, manifest);
+        assert!(!Path::new(CACHE_BUSTER_DATA_FILE).exists());
+        let files = crate::Files::try_from_bytes(&fs::read(&manifest).unwrap()).unwrap();
+        assert!(files.get("./dist/github.svg").is_some());
+
+        cleanup(&config);
+        fs::remove_dir_all(out_dir).unwrap();
     }
 
-    #[test]
-    fn no_hash_validation_works() {
-        let types = vec![
-            mime::IMAGE_PNG,
-            mime::IMAGE_SVG,
-            mime::IMAGE_JPEG,
-            mime::IMAGE_GIF,
-        ];
+    #[cfg(feature = "toml")]
+    fn manifest_format_works() {
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodmanifestformat")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .manifest_format(ManifestFormat::Toml)
+            .build()
+            .unwrap();
 
-        let no_hash =
-            NoHashCategory::FilePaths(vec!["bbell.svg", "eye.svg", "a/b/c/d/s/d/svg/10.svg"]);
+        delete_file();
+        let report = config.process().unwrap();
+        assert_eq!(report.manifest, Path::new("./src/cache_buster_data.toml"));
+        assert!(!Path::new(CACHE_BUSTER_DATA_FILE).exists());
+        let toml = fs::read_to_string(&report.manifest).unwrap();
+        let files: crate::Files = toml::from_str(&toml).unwrap();
+        assert_eq!(files.schema_version(), crate::SCHEMA_VERSION);
+        let github = files.get_full_path("./dist/github.svg").unwrap();
+        assert!(Path::new(github).exists());
+
+        // the previous filemap is read back when cleaning
+        config.process().unwrap();
 
+        #[cfg(feature = "gzip")]
         assert!(BusterBuilder::default()
             .source("./dist")
-            .result("/tmp/prod2i")
-            .mime_types(types)
+            .result("/tmp/prodmanifestformat")
             .follow_links(true)
-            .prefix("/test")
-            .no_hash(vec![no_hash.clone()])
+            .manifest_format(ManifestFormat::Toml)
+            .manifest_compression(ManifestCompression::Gzip)
             .build()
-            .is_err())
+            .is_err());
+
+        fs::remove_file(&report.manifest).unwrap();
+        fs::remove_file("./src/cache_buster_data.toml.lock").unwrap();
+        cleanup(&config);
     }
 
-    fn no_specific_mime() {
+    #[cfg(feature = "yaml")]
+    fn yaml_manifest_works() {
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodyamlmanifest")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .manifest_format(ManifestFormat::Yaml)
+            .build()
+            .unwrap();
+
         delete_file();
-        //use std::{thread, time};
+        let report = config.process().unwrap();
+        assert_eq!(report.manifest, Path::new("./src/cache_buster_data.yaml"));
+        let yaml = fs::read_to_string(&report.manifest).unwrap();
+        let files: crate::Files = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(files.schema_version(), crate::SCHEMA_VERSION);
+        let github = files.get_full_path("./dist/github.svg").unwrap();
+        assert!(Path::new(github).exists());
 
-        //let sleep = time::Duration::from_secs(4);
+        // the previous filemap is read back when cleaning
+        config.process().unwrap();
 
-        //thread::sleep(sleep);
+        fs::remove_file(&report.manifest).unwrap();
+        fs::remove_file("./src/cache_buster_data.yaml.lock").unwrap();
+        cleanup(&config);
+    }
 
-        const WASM: &str = "858fd6c482cc75111d54.module.wasm";
-        let no_hash_files = vec![WASM, "bell.svg", "eye.svg", "a/b/c/d/s/d/svg/10.svg"];
-        let no_hash = NoHashCategory::FilePaths(no_hash_files.clone());
+    #[cfg(feature = "cbor")]
+    fn cbor_manifest_works() {
         let config = BusterBuilder::default()
             .source("./dist")
-            .result("/tmp/prod2ii")
+            .result("/tmp/prodcbormanifest")
+            .mime_types(vec![mime::IMAGE_SVG])
             .follow_links(true)
-            .no_hash(vec![no_hash.clone()])
+            .manifest_format(ManifestFormat::Cbor)
             .build()
             .unwrap();
-        config.process().unwrap();
-        let files = Files::load();
-
-        let no_hash_file = Path::new(&config.result).join(WASM);
-        assert!(files.map.iter().any(|(k, v)| {
-            let source = Path::new(&config.source).join(k);
-            let dest = Path::new(&v);
-            dest.file_name() == no_hash_file.file_name()
-                && dest.exists()
-                && source.file_name() == dest.file_name()
-        }));
-
-        no_hash_files.iter().for_each(|file| {
-            assert!(files.map.iter().any(|(k, v)| {
-                let source = Path::new(k);
-                let dest = Path::new(&v);
-                let no_hash = Path::new(file);
-                source == Path::new(&config.source).join(file)
-                    && dest.exists()
-                    && no_hash.file_name() == dest.file_name()
-            }));
-        });
 
-        for (k, v) in files.map.iter() {
-            let src = Path::new(&k);
-            let dest = Path::new(&v);
+        delete_file();
+        let report = config.process().unwrap();
+        assert_eq!(report.manifest, Path::new("./src/cache_buster_data.cbor"));
+        let cbor = fs::read(&report.manifest).unwrap();
+        assert!(cbor.starts_with(crate::filemap::CBOR_MAGIC));
+        let files = crate::Files::try_from_bytes(&cbor).unwrap();
+        assert_eq!(files.schema_version(), crate::SCHEMA_VERSION);
+        let github = files.get_full_path("./dist/github.svg").unwrap();
+        assert!(Path::new(github).exists());
 
-            assert_eq!(src.exists(), dest.exists());
-        }
+        // the previous filemap is read back when cleaning
+        config.process().unwrap();
 
+        fs::remove_file(&report.manifest).unwrap();
+        fs::remove_file("./src/cache_buster_data.cbor.lock").unwrap();
         cleanup(&config);
     }
 
-    fn prefix_works() {
+    fn webpack_manifest_works() {
         delete_file();
-        let types = vec![
-            mime::IMAGE_PNG,
-            mime::IMAGE_SVG,
-            mime::IMAGE_JPEG,
-            mime::IMAGE_GIF,
-        ];
-
+        let path = "/tmp/cache-buster-webpack-manifest.json";
         let config = BusterBuilder::default()
             .source("./dist")
-            .result("/tmp/prod2i")
-            .mime_types(types)
+            .result("/tmp/prodwebpackmanifest")
+            .mime_types(vec![mime::IMAGE_SVG])
             .follow_links(true)
-            .prefix("/test")
+            .prefix("/static")
+            .webpack_manifest(path)
             .build()
             .unwrap();
-
         config.process().unwrap();
-        let mut files = Files::load();
+        let map = fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap();
+        let files = crate::Files::try_new(&map).unwrap();
 
-        if let Some(prefix) = &config.prefix {
-            for (k, v) in files.map.drain() {
-                let src = Path::new(&k);
-                let dest = Path::new(&v[prefix.len()..]);
+        let manifest: BTreeMap<String, String> =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(manifest.len(), files.entries().count());
+        assert_eq!(
+            manifest["github.svg"],
+            files.get_url("./dist/github.svg").unwrap()
+        );
+        assert!(manifest["github.svg"].starts_with("/static/github."));
+        assert_eq!(
+            manifest["a/b/c/d/s/d/svg/credit-card.svg"],
+            files
+                .get_url("./dist/a/b/c/d/s/d/svg/credit-card.svg")
+                .unwrap()
+        );
 
-                assert_eq!(src.exists(), dest.exists());
-            }
-        }
+        fs::remove_file(path).unwrap();
+        cleanup(&config);
+    }
+
+    fn html_pages_works() {
+        delete_file();
+        let source = fixture(
+            "html-pages",
+            &[("css/app.css", "a {}"), ("js/app.js", "let a = 1;")],
+        );
+        let templates = fixture(
+            "html-pages-templates",
+            &[(
+                "index.html",
+                "<html><head><title>t</title></head><body></body></html>",
+            )],
+        );
+        let template = format!("{}/index.html", templates);
+        let entries = [
+            format!("{}/css/app.css", source),
+            format!("{}/js/app.js", source),
+        ];
+        let builder = |page: HtmlPage| {
+            BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/prodhtmlpages")
+                .follow_links(true)
+                .prefix("/static")
+                .html_pages(vec![page])
+                .build()
+                .unwrap()
+        };
+
+        let config = builder(HtmlPage::new(&template, entries.clone()).integrity(true));
+        config.process().unwrap();
+        let files = Files::load();
+        let css = &files.map[&entries[0]];
+        let js = &files.map[&entries[1]];
+        let page = fs::read_to_string("/tmp/prodhtmlpages/index.html").unwrap();
+        assert_eq!(
+            page,
+            format!(
+                concat!(
+                    "<html><head><title>t</title>",
+                    "<link rel=\"stylesheet\" href=\"/static{}\" integrity=\"{}\" crossorigin=\"anonymous\">\n",
+                    "</head><body>",
+                    "<script defer src=\"/static{}\" integrity=\"{}\" crossorigin=\"anonymous\"></script>\n",
+                    "</body></html>"
+                ),
+                &css.path[files.base_dir.len()..],
+                css.integrity.as_ref().unwrap(),
+                &js.path[files.base_dir.len()..],
+                js.integrity.as_ref().unwrap(),
+            )
+        );
+        cleanup(&config);
 
+        let config = builder(HtmlPage::new(&template, entries.clone()).output("pages/home.html"));
+        config.process().unwrap();
+        let page = fs::read_to_string("/tmp/prodhtmlpages/pages/home.html").unwrap();
+        assert!(!page.contains("integrity"));
         cleanup(&config);
+
+        let missing = builder(HtmlPage::new(&template, ["./dist/nope.css"]));
+        let err = missing.process().unwrap_err();
+        assert_eq!(io::Error::from(err).kind(), ErrorKind::NotFound);
+        cleanup(&missing);
     }
 
-    fn no_hash_extension_works() {
+    fn preload_works() {
         delete_file();
-        use std::{thread, time};
+        let source = fixture(
+            "preload",
+            &[
+                ("app.css", "a {}"),
+                ("app.js", "let a = 1;"),
+                ("fonts/inter.woff2", "font"),
+            ],
+        );
+        let key = |file: &str| format!("{}/{}", source, file);
+        let builder = |preload: Vec<String>, list: &str| {
+            BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/prodpreload")
+                .follow_links(true)
+                .prefix("/static")
+                .preload(preload)
+                .preload_list(list)
+                .build()
+                .unwrap()
+        };
+        let preload = vec![key("app.css"), key("fonts/inter.woff2"), key("app.js")];
 
-        let sleep = time::Duration::from_secs(4);
-        const APPLICATION_WASM: &str = "wasm";
-        const WASM: &str = "858fd6c482cc75111d54.module.wasm";
+        let list = "/tmp/cache-buster-preload.json";
+        let config = builder(preload.clone(), list);
+        config.process().unwrap();
+        let map = fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap();
+        let files = crate::Files::try_new(&map).unwrap();
+        let links = files.get_preload_links();
+        assert_eq!(
+            links,
+            vec![
+                format!(
+                    "<{}>; rel=preload; as=style",
+                    files.get_url(key("app.css")).unwrap()
+                ),
+                format!(
+                    "<{}>; rel=preload; as=font; crossorigin",
+                    files.get_url(key("fonts/inter.woff2")).unwrap()
+                ),
+                format!(
+                    "<{}>; rel=preload; as=script",
+                    files.get_url(key("app.js")).unwrap()
+                ),
+            ]
+        );
+        let written: Vec<String> =
+            serde_json::from_str(&fs::read_to_string(list).unwrap()).unwrap();
+        assert_eq!(written, links);
+        fs::remove_file(list).unwrap();
+        cleanup(&config);
 
-        thread::sleep(sleep);
+        let list = "/tmp/cache-buster-preload-headers";
+        let config = builder(preload, list);
+        config.process().unwrap();
+        let headers = fs::read_to_string(list).unwrap();
+        let expected: String = links
+            .iter()
+            .map(|link| format!("Link: {}\n", link))
+            .collect();
+        assert_eq!(headers, expected);
+        fs::remove_file(list).unwrap();
 
-        let no_hash_extensions = vec![APPLICATION_WASM];
-        let no_hash_ext = NoHashCategory::FileExtentions(no_hash_extensions.clone());
+        // result is left alone
+        let css = Files::load().map[&key("app.css")].path.clone();
+        fs::write(&css, "tampered").unwrap();
+        let missing = builder(vec![key("nope.css")], list);
+        let err = missing.process().unwrap_err();
+        assert_eq!(io::Error::from(err).kind(), ErrorKind::NotFound);
+        assert_eq!(fs::read_to_string(&css).unwrap(), "tampered");
+        cleanup(&missing);
+    }
 
-        let no_hash_paths = vec!["bell.svg", "eye.svg", "a/b/c/d/s/d/svg/10.svg"];
-        let no_hash_cat = NoHashCategory::FilePaths(no_hash_paths.clone());
-        let no_hash = vec![no_hash_cat, no_hash_ext];
+    fn inline_works() {
+        delete_file();
+        let source = fixture(
+            "inline",
+            &[
+                ("theme.js", "document.body.className = 'dark';"),
+                ("critical.css", "body { margin: 0 }"),
+                ("app.js", "let a = 1;"),
+            ],
+        );
+        let key = |file: &str| format!("{}/{}", source, file);
+        let builder = |inline: Vec<String>| {
+            BusterBuilder::default()
+                .source(source.clone())
+                .result("/tmp/prodinline")
+                .follow_links(true)
+                .inline(inline)
+                .build()
+                .unwrap()
+        };
+
+        let config = builder(vec![key("theme.js"), key("critical.css")]);
+        config.process().unwrap();
+        let map = fs::read_to_string(CACHE_BUSTER_DATA_FILE).unwrap();
+        let files = crate::Files::try_new(&map).unwrap();
+        // printf "document.body.className = 'dark';" | openssl dgst -sha256 -binary | openssl base64 -A
+        let theme = "'sha256-GRVZjmcw/7aJsFAPd2KkAMrIP9VfPFLcPSfFObC5pwM='";
+        assert_eq!(files.get_csp_hash(key("theme.js")), Some(theme));
+        assert!(files.get_csp_hash(key("app.js")).is_none());
+        let js = files.get_mime(key("theme.js")).unwrap();
+        assert_eq!(files.get_csp_hashes(js), vec![theme]);
+        assert_eq!(
+            files.get_csp_hashes("text/css"),
+            vec![naming::csp_hash(b"body { margin: 0 }").as_str()]
+        );
 
+        // result is left alone
+        let js = Files::load().map[&key("app.js")].path.clone();
+        fs::write(&js, "tampered").unwrap();
+        let missing = builder(vec![key("nope.js")]);
+        let err = missing.process().unwrap_err();
+        assert_eq!(io::Error::from(err).kind(), ErrorKind::NotFound);
+        assert_eq!(fs::read_to_string(&js).unwrap(), "tampered");
+        cleanup(&missing);
+    }
+
+    fn htaccess_works() {
+        delete_file();
+        let no_hash = NoHashCategory::file_paths(["bell.svg"]);
         let config = BusterBuilder::default()
             .source("./dist")
-            .result("/tmp/prodnohashextension")
+            .result("/tmp/prodhtaccess")
+            .mime_types(vec![mime::IMAGE_SVG])
             .follow_links(true)
-            .no_hash(no_hash.clone())
+            .no_hash(vec![no_hash])
+            .htaccess(true)
             .build()
             .unwrap();
         config.process().unwrap();
         let files = Files::load();
 
-        assert!(files.map.iter().any(|(_k, v)| {
-            let dest = Path::new(&v);
-            dest.extension().unwrap().to_str().unwrap() == APPLICATION_WASM && dest.exists()
-        }));
+        let htaccess = fs::read_to_string("/tmp/prodhtaccess/.htaccess").unwrap();
+        let github = Path::new(&files.map["./dist/github.svg"].path);
+        let github = github.file_name().unwrap().to_str().unwrap();
+        let immutable = htaccess
+            .split("</FilesMatch>")
+            .find(|block| block.contains(headers::IMMUTABLE))
+            .unwrap();
+        assert!(immutable.contains(&github.replace('.', "\\.")));
+        assert!(!immutable.contains("|bell\\.svg|"));
+        assert!(htaccess.contains(&format!(
+            "    <FilesMatch \"^(bell\\.svg)$\">\n        Header set Cache-Control \"{}\"\n",
+            headers::NO_CACHE
+        )));
+        assert_eq!(htaccess.matches("<FilesMatch ").count(), 4);
+        cleanup(&config);
 
-        let no_hash_file = Path::new(&config.result).join(WASM);
-        assert!(files.map.iter().any(|(k, v)| {
-            let source = Path::new(&config.source).join(k);
-            let dest = Path::new(&v);
-            dest.file_name() == no_hash_file.file_name()
-                && dest.exists()
-                && source.file_name() == dest.file_name()
-        }));
+        let invalid = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodhtaccess")
+            .follow_links(true)
+            .hash_placement(HashPlacement::Directory)
+            .htaccess(true)
+            .build();
+        assert!(invalid.is_err());
+    }
 
-        no_hash_paths.iter().for_each(|file| {
-            assert!(files.map.iter().any(|(k, v)| {
-                let source = Path::new(k);
-                let dest = Path::new(&v);
-                let no_hash = Path::new(file);
-                source == Path::new(&config.source).join(file)
-                    && dest.exists()
-                    && no_hash.file_name() == dest.file_name()
-            }));
-        });
+    fn headers_file_works() {
+        delete_file();
+        let no_hash = NoHashCategory::file_paths(["bell.svg"]);
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodheadersfile")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .prefix("/static")
+            .no_hash(vec![no_hash])
+            .headers_file(true)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
 
-        for (k, v) in files.map.iter() {
-            let src = Path::new(&k);
-            let dest = Path::new(&v);
+        let headers = fs::read_to_string("/tmp/prodheadersfile/_headers").unwrap();
+        let github = &files.map["./dist/github.svg"].path[files.base_dir.len()..];
+        assert!(headers.contains(&format!(
+            "\n/static{}\n  Cache-Control: {}\n",
+            github,
+            headers::IMMUTABLE
+        )));
+        // directories of hashed files get a splat
+        assert!(headers.contains("\n/static/svg/*\n"));
+        assert!(headers.contains("\n/static/a/*\n"));
+        assert!(!headers.contains("/static/svg/github."));
+        assert!(!headers.contains("bell.svg"));
+        let root = files
+            .map
+            .values()
+            .filter(|entry| !entry.path[files.base_dir.len() + 1..].contains('/'))
+            .count();
+        assert_eq!(headers.matches("  Cache-Control: ").count(), root - 1 + 2);
+        cleanup(&config);
 
-            assert_eq!(src.exists(), dest.exists());
-        }
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodheadersfile")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .prefix("/static")
+            .no_hash(vec![NoHashCategory::file_paths(["bell.svg"])])
+            .hash_placement(HashPlacement::Directory)
+            .headers_file(true)
+            .build()
+            .unwrap();
+        config.process().unwrap();
+        let files = Files::load();
+        let headers = fs::read_to_string("/tmp/prodheadersfile/_headers").unwrap();
+        let github = &files.map["./dist/github.svg"].path[files.base_dir.len()..];
+        let build_dir = &github[..github[1..].find('/').unwrap() + 2];
+        assert_eq!(
+            headers,
+            format!(
+                "# @generated by cache-buster. Do not edit.\n/static{}*\n  Cache-Control: {}\n",
+                build_dir,
+                headers::IMMUTABLE
+            )
+        );
+        cleanup(&config);
+    }
 
+    #[cfg(feature = "tokio")]
+    fn process_async_works() {
+        delete_file();
+        let config = BusterBuilder::default()
+            .source("./dist")
+            .result("/tmp/prodasync")
+            .mime_types(vec![mime::IMAGE_SVG])
+            .follow_links(true)
+            .build()
+            .unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let report = runtime.block_on(config.process_async()).unwrap();
+        assert_eq!(report.hashed + report.copied, Files::load().map.len());
+        let github = &Files::load().map["./dist/github.svg"].path;
+        assert!(Path::new(github).exists());
         cleanup(&config);
     }
 
-    pub fn runner() {
+    pub(crate) fn runner() {
         prefix_works();
         no_specific_mime();
         no_hash_extension_works();
+        sanitize_works();
+        compound_extensions_works();
+        codegen_works();
+        hash_placement_directory_works();
+        output_names_works();
+        reproducible_works();
+        events_works();
+        etag_works();
+        hash_file_matches_process();
+        watch_works();
+        entry_metadata_works();
+        track_no_hash_works();
+        mime_wildcard_works();
+        allowlist_works();
+        mime_overrides_works();
+        unknown_mime_works();
+        name_mangler_works();
+        no_hash_globs_works();
+        warm_list_works();
+        no_hash_mime_types_works();
+        variants_works();
+        globs_works();
+        snapshot_works();
+        filter_works();
+        protect_source_works();
+        dependency_assets_works();
+        respect_ignore_files_works();
+        chunk_size_works();
+        size_filters_works();
+        include_hidden_works();
+        exclude_dirs_works();
+        max_depth_works();
+        sources_works();
+        process_into_works();
+        clean_works();
+        keep_generations_works();
+        #[cfg(unix)]
+        store_works();
+        plan_works();
+        process_report_works();
+        observer_works();
+        transform_works();
+        rewrite_js_works();
+        preserve_metadata_works();
+        link_strategy_works();
+        threads_works();
+        #[cfg(feature = "jwalk")]
+        parallel_walk_works();
+        existing_destinations_are_kept();
+        copy_buffer_size_works();
+        cache_works();
+        result_marker_works();
+        manifest_write_is_atomic();
+        concurrent_processing_works();
+        #[cfg(unix)]
+        symlink_loop_works();
+        #[cfg(unix)]
+        external_links_works();
+        empty_source_works();
+        #[cfg(unix)]
+        unreadable_works();
+        copy_unmatched_works();
+        result_check_works();
+        manifest_path_works();
+        out_dir_works();
+        #[cfg(feature = "toml")]
+        manifest_format_works();
+        #[cfg(feature = "yaml")]
+        yaml_manifest_works();
+        #[cfg(feature = "cbor")]
+        cbor_manifest_works();
+        webpack_manifest_works();
+        html_pages_works();
+        preload_works();
+        inline_works();
+        htaccess_works();
+        headers_file_works();
+        #[cfg(unix)]
+        non_utf8_works();
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        manifest_compression_works();
+        #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+        precompress_works();
+        #[cfg(feature = "images")]
+        responsive_images_works();
+        #[cfg(feature = "tokio")]
+        process_async_works();
     }
 }
@@ -0,0 +1,111 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Keeps processing from writing into the source directory, see
+//! [SourceProtection][crate::processor::SourceProtection]
+use std::collections::HashMap;
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+/// Resolve `path` to an absolute path without requiring it to exist: the
+/// longest existing ancestor is canonicalized and the rest appended
+pub(crate) fn resolve(path: &Path) -> Result<PathBuf, Error> {
+    let path = std::path::absolute(path)?;
+    let mut rest = Vec::new();
+    let mut existing = path.as_path();
+    while !existing.exists() {
+        rest.push(existing.file_name().unwrap_or_default().to_owned());
+        existing = match existing.parent() {
+            Some(parent) => parent,
+            None => break,
+        };
+    }
+    let mut resolved = existing.canonicalize()?;
+    resolved.extend(rest.iter().rev());
+    Ok(resolved)
+}
+
+/// Fail when any of `outputs` resolves inside `source`, or `source` resolves
+/// inside one of them
+pub(crate) fn check(source: &str, outputs: &[&str]) -> Result<(), Error> {
+    let source_dir = resolve(Path::new(source))?;
+    for output in outputs.iter() {
+        let resolved = resolve(Path::new(output))?;
+        if resolved.starts_with(&source_dir) || source_dir.starts_with(&resolved) {
+            return Err(Error::other(format!(
+                "{} resolves to {:?}, which overlaps with source {:?}",
+                output, resolved, source_dir
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Marks files in source directories read-only until dropped. Nothing
+/// restores them when the process dies without dropping the guard, see
+/// [SourceProtection::Enforce][crate::processor::SourceProtection::Enforce]
+pub(crate) struct ReadOnlyGuard {
+    // files along with their original permissions
+    files: HashMap<PathBuf, fs::Permissions>,
+}
+
+impl ReadOnlyGuard {
+    pub(crate) fn new(sources: &[&str], follow_links: bool) -> Result<Self, Error> {
+        let mut guard = Self {
+            files: HashMap::new(),
+        };
+        for source in sources.iter() {
+            for entry in WalkDir::new(source).follow_links(follow_links) {
+                let entry = entry?;
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+                let original = entry.metadata()?.permissions();
+                if !original.readonly() {
+                    let mut permissions = original.clone();
+                    permissions.set_readonly(true);
+                    fs::set_permissions(entry.path(), permissions)?;
+                    guard.files.insert(entry.path().to_path_buf(), original);
+                }
+            }
+        }
+        Ok(guard)
+    }
+
+    /// Give a copy of `source` the permissions source had before it was
+    /// marked read-only
+    pub(crate) fn restore_copy(&self, source: &Path, copy: &Path) -> Result<(), Error> {
+        match self.files.get(source) {
+            Some(permissions) => fs::set_permissions(copy, permissions.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ReadOnlyGuard {
+    fn drop(&mut self) {
+        for (file, permissions) in self.files.drain() {
+            let _ = fs::set_permissions(file, permissions);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_works() {
+        assert!(check("./dist", &["./prod", "./src/cache_buster_data.json"]).is_ok());
+        assert!(check("./dist", &["./dist/prod"]).is_err());
+        assert!(check("./dist", &["./dist/../dist/prod"]).is_err());
+        assert!(check("./dist", &["."]).is_err());
+        assert!(check("./dist/", &["./dist"]).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Helpers to point references between JavaScript files at hashed names, see
+//! [BusterBuilder::rewrite_js][crate::BusterBuilder::rewrite_js]
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+/// Reference to another file in JavaScript source
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Reference {
+    /// byte range of the referenced path in source, without quotes
+    pub(crate) span: Range<usize>,
+    /// is this a `//# sourceMappingURL=` comment?
+    pub(crate) source_map: bool,
+}
+
+/// Is the file at `path` a JavaScript file?
+pub(crate) fn is_js(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "js" || ext == "mjs")
+}
+
+// characters that can be part of an identifier
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || c == '.'
+}
+
+/// `//# sourceMappingURL=` comments and quoted specifiers following `import`
+/// and `from`, sorted by position
+pub(crate) fn references(source: &str) -> Vec<Reference> {
+    let mut references = Vec::new();
+    for (start, _) in source.match_indices("sourceMappingURL=") {
+        let before = &source[..start];
+        if !(before.ends_with("//# ") || before.ends_with("//@ ")) {
+            continue;
+        }
+        let start = start + "sourceMappingURL=".len();
+        let len = source[start..]
+            .find(char::is_whitespace)
+            .unwrap_or(source.len() - start);
+        if len > 0 {
+            references.push(Reference {
+                span: start..start + len,
+                source_map: true,
+            });
+        }
+    }
+    for keyword in ["import", "from"] {
+        for (start, _) in source.match_indices(keyword) {
+            let end = start + keyword.len();
+            if source[..start].chars().next_back().is_some_and(is_ident)
+                || source[end..].chars().next().is_some_and(is_ident)
+            {
+                continue;
+            }
+            let rest = source[end..].trim_start();
+            let quote = match rest.chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => continue,
+            };
+            let start = source.len() - rest.len() + 1;
+            let len = match source[start..].find([quote, '\n']) {
+                Some(len) if source[start + len..].starts_with(quote) => len,
+                _ => continue,
+            };
+            references.push(Reference {
+                span: start..start + len,
+                source_map: false,
+            });
+        }
+    }
+    references.sort_by_key(|reference| reference.span.start);
+    references
+}
+
+impl Reference {
+    /// Path relative to result of the file referenced from `rel`, also
+    /// relative to result. None for bare module specifiers, URLs and paths
+    /// leaving result
+    pub(crate) fn target(&self, source: &str, rel: &Path) -> Option<PathBuf> {
+        let specifier = &source[self.span.clone()];
+        let relative = if self.source_map {
+            !specifier.contains(':') && !specifier.starts_with('/')
+        } else {
+            specifier.starts_with("./") || specifier.starts_with("../")
+        };
+        if !relative || specifier.contains(['?', '#']) {
+            return None;
+        }
+        let mut target: Vec<&str> = rel
+            .parent()
+            .map(|parent| parent.iter().map(|c| c.to_str().unwrap()).collect())
+            .unwrap_or_default();
+        for segment in specifier.split('/') {
+            match segment {
+                "" | "." => (),
+                ".." => {
+                    target.pop()?;
+                }
+                segment => target.push(segment),
+            }
+        }
+        Some(target.iter().collect())
+    }
+}
+
+/// `to` relative to the directory of `from`, both relative to result. Keeps
+/// the leading `./` of `original`
+pub(crate) fn relative(from: &Path, to: &Path, original: &str) -> String {
+    let from_dir: Vec<Component<'_>> = from
+        .parent()
+        .map(|parent| parent.components().collect())
+        .unwrap_or_default();
+    let to: Vec<Component<'_>> = to.components().collect();
+    let common = from_dir
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut segments = vec![".."; from_dir.len() - common];
+    segments.extend(to[common..].iter().map(|c| c.as_os_str().to_str().unwrap()));
+    let path = segments.join("/");
+    if segments[0] != ".." && original.starts_with("./") {
+        format!("./{}", path)
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_helpers_work() {
+        let source = r#"import "./a.js";
+import { b } from '../b.js';
+export * from "./c.js";
+import lodash from "lodash";
+const from = "./nope.js";
+import("./dynamic.js");
+//# sourceMappingURL=main.js.map"#;
+        let specifiers: Vec<&str> = references(source)
+            .iter()
+            .map(|reference| &source[reference.span.clone()])
+            .collect();
+        assert_eq!(
+            specifiers,
+            vec!["./a.js", "../b.js", "./c.js", "lodash", "main.js.map"]
+        );
+
+        let rel = Path::new("js/main.js");
+        let targets: Vec<Option<PathBuf>> = references(source)
+            .iter()
+            .map(|reference| reference.target(source, rel))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                Some(PathBuf::from("js/a.js")),
+                Some(PathBuf::from("b.js")),
+                Some(PathBuf::from("js/c.js")),
+                None,
+                Some(PathBuf::from("js/main.js.map")),
+            ]
+        );
+
+        let from = Path::new("js/main.abc.js");
+        assert_eq!(relative(from, Path::new("js/a.1.js"), "./a.js"), "./a.1.js");
+        assert_eq!(relative(from, Path::new("b.2.js"), "../b.js"), "../b.2.js");
+        assert_eq!(
+            relative(from, Path::new("js/main.js.3.map"), "main.js.map"),
+            "main.js.3.map"
+        );
+    }
+}
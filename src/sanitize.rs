@@ -0,0 +1,158 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Helpers to make generated destination names safe to use in URLs
+use std::path::{Component, Path, PathBuf};
+
+use crate::processor::Sanitize;
+
+/// Sanitize every component of a relative path
+pub(crate) fn path(rel: &Path, mode: Sanitize) -> PathBuf {
+    rel.components()
+        .map(|component| match component {
+            Component::Normal(name) => {
+                let name = name.to_str().unwrap();
+                match mode {
+                    Sanitize::PercentEncode => percent_encode(name),
+                    Sanitize::Transliterate => transliterate(name),
+                }
+            }
+            other => other.as_os_str().to_str().unwrap().to_owned(),
+        })
+        .collect()
+}
+
+// characters that can appear in a path segment without being encoded
+fn is_url_safe(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&c)
+}
+
+/// Percent-encode characters that aren't safe in a URL path segment
+pub(crate) fn percent_encode(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if is_url_safe(byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Reverse [percent_encode], leaving malformed escapes as they are
+pub(crate) fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Replace whitespace with `-`, strip common diacritics and replace anything
+/// else that isn't URL safe with `_`
+pub(crate) fn transliterate(name: &str) -> String {
+    let mut res = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_whitespace() {
+            res.push('-');
+        } else if c.is_ascii() {
+            if is_url_safe(c as u8) {
+                res.push(c);
+            } else {
+                res.push('_');
+            }
+        } else if let Some(ascii) = latin_to_ascii(c) {
+            res.push_str(ascii);
+        } else {
+            res.push('_');
+        }
+    }
+    res
+}
+
+fn latin_to_ascii(c: char) -> Option<&'static str> {
+    let ascii = match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' => "A",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'ç' | 'ć' | 'č' => "c",
+        'Ç' | 'Ć' | 'Č' => "C",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ę' | 'Ě' => "E",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ī' => "I",
+        'ñ' | 'ń' | 'ň' => "n",
+        'Ñ' | 'Ń' | 'Ň' => "N",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' => "O",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ß' => "ss",
+        'ś' | 'š' => "s",
+        'Ś' | 'Š' => "S",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' => "u",
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' | 'Ů' => "U",
+        'ý' | 'ÿ' => "y",
+        'Ý' | 'Ÿ' => "Y",
+        'ź' | 'ż' | 'ž' => "z",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        _ => return None,
+    };
+    Some(ascii)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encode_works() {
+        assert_eq!(percent_encode("logo.svg"), "logo.svg");
+        assert_eq!(percent_encode("my logo#1?.svg"), "my%20logo%231%3F.svg");
+        assert_eq!(percent_encode("café.svg"), "caf%C3%A9.svg");
+        for name in ["my logo#1?.svg", "café.svg", "100%.svg"] {
+            assert_eq!(percent_decode(&percent_encode(name)), name);
+        }
+        assert_eq!(percent_decode("50%.svg"), "50%.svg");
+    }
+
+    #[test]
+    fn transliterate_works() {
+        assert_eq!(transliterate("logo.svg"), "logo.svg");
+        assert_eq!(transliterate("my logo#1?.svg"), "my-logo_1_.svg");
+        assert_eq!(transliterate("Crème Brûlée.png"), "Creme-Brulee.png");
+        assert_eq!(transliterate("日本.png"), "__.png");
+    }
+
+    #[test]
+    fn path_sanitizes_every_component() {
+        assert_eq!(
+            path(Path::new("my dir/sub dir/a b.svg"), Sanitize::PercentEncode),
+            Path::new("my%20dir/sub%20dir/a%20b.svg")
+        );
+        assert_eq!(
+            path(Path::new("my dir/a b.svg"), Sanitize::Transliterate),
+            Path::new("my-dir/a-b.svg")
+        );
+    }
+}
@@ -0,0 +1,183 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Starter configuration for an existing static directory
+//!
+//! [scaffold] inspects the directory and suggests a configuration, which can
+//! be written out as a starter `build.rs`:
+//!
+//! ```no_run
+//! let scaffold = cache_buster::scaffold("./static").unwrap();
+//! println!("{}", scaffold.build_rs("./prod"));
+//! scaffold.write("./build.rs", "./prod").unwrap();
+//! ```
+//!
+//! The same is available from the command line, with
+//! `cache-buster init ./static`.
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use walkdir::WalkDir;
+
+use crate::CacheBusterError;
+
+/// names of service workers, which have to keep their URL to stay registered
+const SERVICE_WORKERS: [&str; 3] = ["sw.js", "service-worker.js", "serviceworker.js"];
+
+/// shortest run of hex digits in a file name taken for a hash added by
+/// another bundler
+const MIN_HASH_LEN: usize = 16;
+
+/// Suggested configuration for a static directory, see [scaffold]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scaffold {
+    /// static directory that was inspected
+    pub source: String,
+    /// MIME types of files in source, sorted
+    pub mime_types: Vec<String>,
+    /// files that likely shouldn't be hashed, relative to source: WASM
+    /// modules, service workers and files already hashed by another bundler
+    pub no_hash: Vec<String>,
+    /// files whose MIME type can't be resolved, relative to source
+    pub unknown: Vec<String>,
+}
+
+/// Inspect `source` and suggest a configuration for it
+pub fn scaffold(source: impl AsRef<str>) -> Result<Scaffold, CacheBusterError> {
+    let source = source.as_ref();
+    let mut mime_types = BTreeSet::new();
+    let mut no_hash = BTreeSet::new();
+    let mut unknown = BTreeSet::new();
+    for entry in WalkDir::new(source).follow_links(true) {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        let rel = path
+            .strip_prefix(source)
+            .unwrap()
+            .to_str()
+            .ok_or_else(|| CacheBusterError::NonUtf8Path(path.to_path_buf()))?
+            .to_string();
+        match mime_guess::from_path(path).first() {
+            Some(mime) => {
+                mime_types.insert(mime.to_string());
+            }
+            None => {
+                unknown.insert(rel.clone());
+            }
+        }
+        if is_no_hash_candidate(path) {
+            no_hash.insert(rel);
+        }
+    }
+    Ok(Scaffold {
+        source: source.into(),
+        mime_types: mime_types.into_iter().collect(),
+        no_hash: no_hash.into_iter().collect(),
+        unknown: unknown.into_iter().collect(),
+    })
+}
+
+// WASM modules, service workers and files already hashed by another bundler
+fn is_no_hash_candidate(path: &Path) -> bool {
+    let name = path.file_name().unwrap().to_str().unwrap_or_default();
+    path.extension().is_some_and(|ext| ext == "wasm")
+        || SERVICE_WORKERS.contains(&name)
+        || name.split(['.', '-', '_']).any(|segment| {
+            segment.len() >= MIN_HASH_LEN && segment.chars().all(|c| c.is_ascii_hexdigit())
+        })
+}
+
+impl Scaffold {
+    /// Starter `build.rs` writing results to `result`. Needs `cache-buster`
+    /// and `mime` as build dependencies
+    pub fn build_rs(&self, result: &str) -> String {
+        let mut build_rs = String::new();
+        if self.no_hash.is_empty() {
+            build_rs.push_str("use cache_buster::BusterBuilder;\n");
+        } else {
+            build_rs.push_str("use cache_buster::{BusterBuilder, NoHashCategory};\n");
+        }
+        build_rs.push_str("\nfn main() {\n    let types: Vec<mime::Mime> = vec![\n");
+        for mime in self.mime_types.iter() {
+            build_rs.push_str(&format!("        {:?}.parse().unwrap(),\n", mime));
+        }
+        build_rs.push_str("    ];\n");
+        if !self.no_hash.is_empty() {
+            build_rs.push_str("    let no_hash = NoHashCategory::file_paths([\n");
+            for file in self.no_hash.iter() {
+                build_rs.push_str(&format!("        {:?},\n", file));
+            }
+            build_rs.push_str("    ]);\n");
+        }
+        build_rs.push_str("\n    let config = BusterBuilder::default()\n");
+        build_rs.push_str(&format!("        .source({:?})\n", self.source));
+        build_rs.push_str(&format!("        .result({:?})\n", result));
+        build_rs.push_str("        .mime_types(types)\n");
+        if !self.no_hash.is_empty() {
+            build_rs.push_str("        .no_hash(vec![no_hash])\n");
+        }
+        if !self.unknown.is_empty() {
+            build_rs.push_str(
+                "        .unknown_mime(cache_buster::UnknownMimePolicy::CopyWithoutHash)\n",
+            );
+        }
+        build_rs.push_str("        .follow_links(true)\n");
+        build_rs.push_str("        .build()\n        .unwrap();\n\n");
+        build_rs.push_str("    config.process().unwrap();\n}\n");
+        build_rs
+    }
+
+    /// Write [build_rs][Self::build_rs] to `path`. Fails if `path` exists
+    pub fn write(&self, path: impl AsRef<Path>, result: &str) -> Result<(), CacheBusterError> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(CacheBusterError::Io(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            )));
+        }
+        fs::write(path, self.build_rs(result)).map_err(CacheBusterError::file(path))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaffold_works() {
+        let scaffold = scaffold("./dist").unwrap();
+        assert!(scaffold.mime_types.contains(&"image/svg+xml".to_string()));
+        assert!(scaffold
+            .mime_types
+            .contains(&"application/wasm".to_string()));
+        assert_eq!(
+            scaffold.no_hash,
+            vec![
+                "858fd6c482cc75111d54.module.wasm",
+                "main.1ed8da86f47dbdff4959.bundle.js",
+                "main.78421bba57d23c4c0969.css",
+            ]
+        );
+
+        let build_rs = scaffold.build_rs("./prod");
+        assert!(build_rs.contains(".source(\"./dist\")"));
+        assert!(build_rs.contains("\"858fd6c482cc75111d54.module.wasm\","));
+
+        let path = "/tmp/cache-buster-scaffold-build.rs";
+        let _ = fs::remove_file(path);
+        scaffold.write(path, "./prod").unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), build_rs);
+        assert!(scaffold.write(path, "./prod").is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Diffable snapshots of processing results, for regression checks in CI
+//!
+//! Snapshots written with [BusterBuilder::snapshot][crate::BusterBuilder::snapshot]
+//! are sorted and free of timestamps and hashes, so they only change when the
+//! set of assets does. Commit one and compare new builds against it:
+//!
+//! ```no_run
+//! use cache_buster::snapshot::Snapshot;
+//!
+//! let committed = Snapshot::load("./assets.snapshot.json").unwrap();
+//! let current = Snapshot::load("./target/assets.snapshot.json").unwrap();
+//! let regressions = current.compare(&committed, 1.5);
+//! for regression in regressions.iter() {
+//!     eprintln!("{}", regression);
+//! }
+//! assert!(regressions.is_empty());
+//! ```
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::CacheBusterError;
+
+/// Processing result of all assets, keyed by filemap key
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// assets, keyed by filemap key, so that files at the same path in
+    /// different [sources][crate::BusterBuilder::sources] are told apart
+    pub assets: BTreeMap<String, SnapshotEntry>,
+}
+
+/// Processing result of a single asset
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// size in bytes
+    pub size: u64,
+    /// resolved MIME type
+    pub mime: Option<String>,
+    /// is the hash part of the generated path?
+    pub hashed: bool,
+}
+
+/// Difference between two snapshots worth a second look, see [Snapshot::compare]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Regression {
+    /// asset is new
+    Added {
+        /// filemap key
+        path: String,
+    },
+    /// asset is gone
+    Removed {
+        /// filemap key
+        path: String,
+    },
+    /// asset grew beyond the threshold
+    SizeJump {
+        /// filemap key
+        path: String,
+        /// previous size in bytes
+        before: u64,
+        /// current size in bytes
+        after: u64,
+    },
+    /// asset was hashed before, but isn't anymore
+    NoLongerHashed {
+        /// filemap key
+        path: String,
+    },
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { path } => write!(f, "new asset: {}", path),
+            Self::Removed { path } => write!(f, "removed asset: {}", path),
+            Self::SizeJump {
+                path,
+                before,
+                after,
+            } => write!(f, "{} grew from {} to {} bytes", path, before, after),
+            Self::NoLongerHashed { path } => write!(f, "{} is no longer hashed", path),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Load snapshot from file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CacheBusterError> {
+        let path = path.as_ref();
+        let snapshot = fs::read_to_string(path).map_err(CacheBusterError::file(path))?;
+        Ok(serde_json::from_str(&snapshot)?)
+    }
+
+    /// Write snapshot to file, one field per line so that it diffs well
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), CacheBusterError> {
+        let path = path.as_ref();
+        let mut snapshot = serde_json::to_string_pretty(self)?;
+        snapshot.push('\n');
+        fs::write(path, snapshot).map_err(CacheBusterError::file(path))?;
+        Ok(())
+    }
+
+    /// Compare against a previous snapshot. Assets that grew by at least
+    /// `size_threshold`, like `1.5` for 50%, are flagged as size jumps
+    pub fn compare(&self, previous: &Snapshot, size_threshold: f64) -> Vec<Regression> {
+        let mut regressions = Vec::new();
+        for (path, entry) in self.assets.iter() {
+            let before = match previous.assets.get(path) {
+                Some(before) => before,
+                None => {
+                    regressions.push(Regression::Added { path: path.clone() });
+                    continue;
+                }
+            };
+            if entry.size as f64 >= before.size as f64 * size_threshold && entry.size > before.size
+            {
+                regressions.push(Regression::SizeJump {
+                    path: path.clone(),
+                    before: before.size,
+                    after: entry.size,
+                });
+            }
+            if before.hashed && !entry.hashed {
+                regressions.push(Regression::NoLongerHashed { path: path.clone() });
+            }
+        }
+        for path in previous.assets.keys() {
+            if !self.assets.contains_key(path) {
+                regressions.push(Regression::Removed { path: path.clone() });
+            }
+        }
+        regressions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_works() {
+        let entry = |size, hashed| SnapshotEntry {
+            size,
+            mime: Some("text/css".into()),
+            hashed,
+        };
+        let mut previous = Snapshot::default();
+        previous.assets.insert("a.css".into(), entry(100, true));
+        previous.assets.insert("b.css".into(), entry(100, true));
+        previous.assets.insert("c.css".into(), entry(100, true));
+
+        let mut current = Snapshot::default();
+        current.assets.insert("a.css".into(), entry(120, true));
+        current.assets.insert("b.css".into(), entry(200, false));
+        current.assets.insert("d.css".into(), entry(100, true));
+
+        assert_eq!(
+            current.compare(&previous, 1.5),
+            vec![
+                Regression::SizeJump {
+                    path: "b.css".into(),
+                    before: 100,
+                    after: 200
+                },
+                Regression::NoLongerHashed {
+                    path: "b.css".into()
+                },
+                Regression::Added {
+                    path: "d.css".into()
+                },
+                Regression::Removed {
+                    path: "c.css".into()
+                },
+            ]
+        );
+        assert!(current.compare(&current, 1.5).is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Content-addressed blob store shared between builds
+//!
+//! Blobs are stored once per distinct content, under their SHA-256 hash, no
+//! matter how many builds reference them. Builds reference blobs through the
+//! hashes recorded in their filemaps, so [gc][Store::gc] can drop blobs that
+//! none of the retained filemaps refer to anymore.
+//!
+//! Processing keeps its result in a store with
+//! [BusterBuilder::store][crate::BusterBuilder::store], on Unix: files
+//! written to result are hard links to blobs, so files identical across the
+//! generations kept by
+//! [keep_generations][crate::BusterBuilder::keep_generations] take space
+//! once, and blobs that no kept generation links to anymore are removed
+//! after every run.
+//!
+//! Blobs can also be stored and collected by hand:
+//!
+//! ```rust
+//! use cache_buster::store::Store;
+//! use cache_buster::Files;
+//!
+//! let store = Store::new("/tmp/cache-buster-store-doc");
+//! let hash = store.put(b"body { color: red; }").unwrap();
+//! assert!(store.path(&hash).exists());
+//!
+//! // no filemap references the blob, so it's collected
+//! let retained: Vec<&Files> = Vec::new();
+//! assert_eq!(store.gc(&retained).unwrap(), 1);
+//! assert!(!store.path(&hash).exists());
+//! ```
+use std::collections::HashMap;
+use std::fs;
+#[cfg(unix)]
+use std::io::ErrorKind;
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+
+use crate::naming;
+use crate::CacheBusterError;
+use crate::Files;
+
+/// Content-addressed blob store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    /// Create store rooted at `dir`. The directory is created on first write
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Path of the blob with `hash`
+    pub fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(hash)
+    }
+
+    /// Store `contents` and return its hash. Contents that are already
+    /// stored aren't written again. Blobs are written to a temporary file
+    /// and renamed into place, so that builds sharing the store never see
+    /// partial blobs
+    pub fn put(&self, contents: &[u8]) -> Result<String, CacheBusterError> {
+        let hash = naming::hash(contents);
+        let path = self.path(&hash);
+        if !path.exists() {
+            fs::create_dir_all(&self.dir).map_err(CacheBusterError::file(&self.dir))?;
+            let tmp = self.dir.join(format!("{}.{}.tmp", hash, process::id()));
+            fs::write(&tmp, contents).map_err(CacheBusterError::file(&tmp))?;
+            fs::rename(&tmp, &path).map_err(CacheBusterError::file(&path))?;
+        }
+        Ok(hash)
+    }
+
+    /// Move the file at `path` into the store and hard link it back, so that
+    /// identical files share a blob. Files on another filesystem than the
+    /// store are left as they are
+    #[cfg(unix)]
+    pub(crate) fn link(&self, path: &Path) -> Result<(), CacheBusterError> {
+        let hash = naming::hash(&fs::read(path).map_err(CacheBusterError::file(path))?);
+        let blob = self.path(&hash);
+        fs::create_dir_all(&self.dir).map_err(CacheBusterError::file(&self.dir))?;
+        match fs::hard_link(path, &blob) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
+            Err(e) if e.kind() == ErrorKind::CrossesDevices => return Ok(()),
+            Err(e) => return Err(CacheBusterError::file(&blob)(e).into()),
+        }
+        // replace path with a link to the blob, never leaving it missing
+        let name = path.file_name().unwrap().to_string_lossy();
+        let tmp = path.with_file_name(format!(".{}.{}.tmp", name, process::id()));
+        fs::hard_link(&blob, &tmp).map_err(CacheBusterError::file(&tmp))?;
+        fs::rename(&tmp, path).map_err(CacheBusterError::file(path))?;
+        Ok(())
+    }
+
+    /// Remove blobs that no file outside the store links to anymore, like
+    /// those of files removed along with the generations that
+    /// [keep_generations][crate::BusterBuilder::keep_generations] dropped.
+    /// Returns the number of blobs removed
+    #[cfg(unix)]
+    pub(crate) fn gc_unlinked(&self) -> Result<usize, CacheBusterError> {
+        use std::os::unix::fs::MetadataExt;
+
+        let mut removed = 0;
+        if !self.dir.exists() {
+            return Ok(removed);
+        }
+        for entry in fs::read_dir(&self.dir).map_err(CacheBusterError::file(&self.dir))? {
+            let entry = entry?;
+            if !entry.file_name().to_str().is_some_and(is_hash) {
+                continue;
+            }
+            let path = entry.path();
+            let metadata = fs::metadata(&path).map_err(CacheBusterError::file(&path))?;
+            if metadata.nlink() == 1 {
+                fs::remove_file(&path).map_err(CacheBusterError::file(&path))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Number of references to each stored blob from `manifests`. Blobs
+    /// that aren't referenced are included with a count of zero. Files that
+    /// aren't named after a hash, like temporary files of
+    /// [put][Store::put], aren't blobs
+    pub fn references(
+        &self,
+        manifests: &[&Files],
+    ) -> Result<HashMap<String, usize>, CacheBusterError> {
+        let mut references = HashMap::new();
+        if self.dir.exists() {
+            for entry in fs::read_dir(&self.dir).map_err(CacheBusterError::file(&self.dir))? {
+                let name = entry?.file_name();
+                if let Some(name) = name.to_str().filter(|name| is_hash(name)) {
+                    references.insert(name.to_string(), 0);
+                }
+            }
+        }
+        for manifest in manifests.iter() {
+            for (_, entry) in manifest.entries() {
+                if let Some(count) = entry
+                    .hash
+                    .as_ref()
+                    .and_then(|hash| references.get_mut(hash))
+                {
+                    *count += 1;
+                }
+            }
+        }
+        Ok(references)
+    }
+
+    /// Remove blobs that aren't referenced by any of `manifests`. Returns
+    /// the number of blobs removed
+    pub fn gc(&self, manifests: &[&Files]) -> Result<usize, CacheBusterError> {
+        let mut removed = 0;
+        for (hash, count) in self.references(manifests)? {
+            if count == 0 {
+                let path = self.path(&hash);
+                fs::remove_file(&path).map_err(CacheBusterError::file(&path))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+// is name a hash, as returned by naming::hash?
+fn is_hash(name: &str) -> bool {
+    name.len() == 64
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'A'..=b'F').contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn store_works() {
+        let dir = "/tmp/cache-buster-store";
+        let _ = fs::remove_dir_all(dir);
+        let store = Store::new(dir);
+
+        let kept = store.put(b"kept").unwrap();
+        assert_eq!(store.put(b"kept").unwrap(), kept);
+        let dropped = store.put(b"dropped").unwrap();
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 2);
+        let tmp = Path::new(dir).join(format!("{}.1.tmp", kept));
+        fs::write(&tmp, "partial").unwrap();
+        fs::write(Path::new(dir).join(kept.to_lowercase()), "").unwrap();
+
+        let map = format!(
+            r#"{{
+                "map": {{
+                    "./dist/a.css": {{"path": "./prod/a.css", "hash": "{0}"}},
+                    "./dist/b.css": {{"path": "./prod/b.css", "hash": "{0}"}}
+                }},
+                "base_dir": "./prod"
+            }}"#,
+            kept
+        );
+        let files = Files::try_new(&map).unwrap();
+        let references = store.references(&[&files]).unwrap();
+        assert_eq!(references[&kept], 2);
+        assert_eq!(references[&dropped], 0);
+        assert_eq!(references.len(), 2);
+
+        assert_eq!(store.gc(&[&files]).unwrap(), 1);
+        assert!(store.path(&kept).exists());
+        assert!(!store.path(&dropped).exists());
+        assert!(tmp.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
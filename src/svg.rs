@@ -0,0 +1,166 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Ready-made [AssetTransform] minifying SVGs before they are hashed, so that
+//! hashes describe the bytes actually served:
+//!
+//! ```rust
+//! use cache_buster::svg::SvgMinifier;
+//! use cache_buster::BusterBuilder;
+//!
+//! let config = BusterBuilder::default()
+//!     .source("./dist")
+//!     .result("./prod")
+//!     .mime_types(vec![mime::IMAGE_SVG])
+//!     .follow_links(true)
+//!     .transform(mime::IMAGE_SVG, SvgMinifier)
+//!     .build()
+//!     .unwrap();
+//! ```
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::transform::AssetTransform;
+
+/// Removes comments, whitespace between elements and redundant whitespace
+/// within tags. Text inside `<text>` elements and CDATA sections is kept as is
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SvgMinifier;
+
+impl AssetTransform for SvgMinifier {
+    fn transform(&self, path: &Path, input: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let input = String::from_utf8(input).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{} isn't valid UTF-8", path.display()),
+            )
+        })?;
+        Ok(minify(&input).into_bytes())
+    }
+}
+
+/// Minify SVG source, see [SvgMinifier]
+pub fn minify(svg: &str) -> String {
+    let mut minified = String::with_capacity(svg.len());
+    let mut rest = svg;
+    // depth of open <text> elements, whose whitespace is significant
+    let mut text_depth = 0usize;
+    while !rest.is_empty() {
+        let len = if rest.starts_with("<!--") {
+            match rest.find("-->") {
+                Some(end) => end + "-->".len(),
+                None => rest.len(),
+            }
+        } else if rest.starts_with("<![CDATA[") {
+            let len = rest.find("]]>").map_or(rest.len(), |end| end + "]]>".len());
+            minified.push_str(&rest[..len]);
+            len
+        } else if rest.starts_with('<') {
+            let len = tag_len(rest);
+            let tag = &rest[..len];
+            if is_text_close(tag) {
+                text_depth = text_depth.saturating_sub(1);
+            } else if is_text_open(tag) {
+                text_depth += 1;
+            }
+            push_tag(&mut minified, tag);
+            len
+        } else {
+            let len = rest.find('<').unwrap_or(rest.len());
+            let text = &rest[..len];
+            if text_depth > 0 || !text.trim().is_empty() {
+                minified.push_str(text);
+            }
+            len
+        };
+        rest = &rest[len..];
+    }
+    minified
+}
+
+// length of the tag at the start of source, quoted attribute values included
+fn tag_len(source: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in source.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return i + 1,
+            _ => (),
+        }
+    }
+    source.len()
+}
+
+// is tag an opening <text> tag that isn't self-closing?
+fn is_text_open(tag: &str) -> bool {
+    is_tag(tag, "<text") && !tag.ends_with("/>")
+}
+
+// is tag a closing </text> tag, not one of </textPath>?
+fn is_text_close(tag: &str) -> bool {
+    is_tag(tag, "</text")
+}
+
+// does tag start with open, followed by whitespace or the end of the tag?
+fn is_tag(tag: &str, open: &str) -> bool {
+    tag.strip_prefix(open)
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(|c| c.is_whitespace() || c == '>')
+}
+
+// push tag, collapsing whitespace outside of quoted attribute values
+fn push_tag(minified: &mut String, tag: &str) {
+    let mut quote = None;
+    let mut space = false;
+    for c in tag.chars() {
+        if quote.is_none() && c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        if space && !(c == '>' || c == '/' || c == '?') {
+            minified.push(' ');
+        }
+        space = false;
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => (),
+        }
+        minified.push(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minify_works() {
+        let svg = r#"<?xml version="1.0" encoding="UTF-8" ?>
+<!-- generated by an editor -->
+<svg xmlns="http://www.w3.org/2000/svg"
+     viewBox="0 0 24 24">
+  <path d="M0 0  L24 24" />
+  <text x="1"> Hello <tspan>world</tspan> </text>
+  <text><textPath href="p">on</textPath> <tspan>path</tspan></text>
+  <style><![CDATA[ a > b { fill: red; } ]]></style>
+</svg>
+"#;
+        assert_eq!(
+            minify(svg),
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 24 24">"#,
+                r#"<path d="M0 0  L24 24"/>"#,
+                r#"<text x="1"> Hello <tspan>world</tspan> </text>"#,
+                r#"<text><textPath href="p">on</textPath> <tspan>path</tspan></text>"#,
+                r#"<style><![CDATA[ a > b { fill: red; } ]]></style>"#,
+                "</svg>"
+            )
+        );
+    }
+}
@@ -0,0 +1,70 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Rewrite file contents before they are hashed
+//!
+//! Minifiers, optimizers and the like plug in by implementing [AssetTransform]
+//! and registering it for a MIME type or file extension with
+//! [BusterBuilder::transform][crate::BusterBuilder::transform]. Hashes,
+//! integrity values and sizes in the filemap describe the transformed
+//! contents, which are written to result in place of the source file:
+//!
+//! ```rust
+//! use std::io::Error;
+//! use std::path::Path;
+//!
+//! use cache_buster::{AssetTransform, BusterBuilder, TransformTarget};
+//!
+//! /// drops blank lines
+//! struct StripBlankLines;
+//!
+//! impl AssetTransform for StripBlankLines {
+//!     fn transform(&self, _path: &Path, input: Vec<u8>) -> Result<Vec<u8>, Error> {
+//!         let input = String::from_utf8_lossy(&input);
+//!         let lines: Vec<&str> = input.lines().filter(|l| !l.trim().is_empty()).collect();
+//!         Ok(lines.join("\n").into_bytes())
+//!     }
+//! }
+//!
+//! let config = BusterBuilder::default()
+//!     .source("./dist")
+//!     .result("./prod")
+//!     .follow_links(true)
+//!     .transform(mime::TEXT_CSS, StripBlankLines)
+//!     .transform(TransformTarget::extension("js"), StripBlankLines)
+//!     .build()
+//!     .unwrap();
+//! ```
+use std::io::Error;
+use std::path::Path;
+
+/// Rewrites file contents before they are hashed, see [transform][self]
+pub trait AssetTransform: Send + Sync {
+    /// Transformed contents of the file at `path`, in source
+    fn transform(&self, path: &Path, input: Vec<u8>) -> Result<Vec<u8>, Error>;
+}
+
+/// Files an [AssetTransform] applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransformTarget {
+    /// files of this MIME type. `text/*` matches every text file
+    Mime(mime::Mime),
+    /// files with this extension, without the leading dot
+    Extension(String),
+}
+
+impl TransformTarget {
+    /// Target files with `extension`, without the leading dot
+    pub fn extension(extension: impl Into<String>) -> Self {
+        Self::Extension(extension.into())
+    }
+}
+
+impl From<mime::Mime> for TransformTarget {
+    fn from(mime: mime::Mime) -> Self {
+        Self::Mime(mime)
+    }
+}
@@ -0,0 +1,186 @@
+/*
+* Copyright (C) 2022  Aravinth Manivannan <realaravinth@batsense.net>
+*
+* Use of this source code is governed by the Apache 2.0 and/or the MIT
+* License.
+*/
+//! Watch mode: reprocess files whenever the source directory changes
+//!
+//! Prints one summary line per rebuild to stderr and keeps going when a
+//! rebuild fails, so it pairs well with `cargo watch -x run`:
+//!
+//! ```no_run
+//! use std::process::Command;
+//!
+//! use cache_buster::watch::WatchOptionsBuilder;
+//! use cache_buster::BusterBuilder;
+//!
+//! let config = BusterBuilder::default()
+//!     .source("./dist")
+//!     .result("./prod")
+//!     .follow_links(true)
+//!     .build()
+//!     .unwrap();
+//!
+//! let options = WatchOptionsBuilder::default()
+//!     .on_rebuild(|summary| {
+//!         let _ = Command::new("notify-send")
+//!             .arg("cache-buster")
+//!             .arg(summary.to_string())
+//!             .status();
+//!     })
+//!     .build()
+//!     .unwrap();
+//!
+//! // runs until stopped
+//! config.watch(&options).unwrap();
+//! ```
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use derive_builder::Builder;
+
+use crate::processor::Buster;
+use crate::CacheBusterError;
+
+/// Hook called after every rebuild
+#[derive(Clone)]
+pub struct RebuildHook(Arc<dyn Fn(&RebuildSummary) + Send + Sync>);
+
+impl fmt::Debug for RebuildHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RebuildHook")
+    }
+}
+
+/// Configuration for [Buster::watch]
+#[derive(Debug, Clone, Builder)]
+pub struct WatchOptions {
+    /// how often source is checked for changes. Defaults to 500ms
+    #[builder(default = "Duration::from_millis(500)")]
+    interval: Duration,
+    /// don't print rebuild summaries to stderr
+    #[builder(default)]
+    quiet: bool,
+    /// called after every rebuild, successful or not. Useful for desktop
+    /// notifications
+    #[builder(setter(custom), default)]
+    on_rebuild: Option<RebuildHook>,
+    /// stop watching once this flag is set
+    #[builder(setter(strip_option), default)]
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl WatchOptionsBuilder {
+    /// called after every rebuild, successful or not. Useful for desktop
+    /// notifications
+    pub fn on_rebuild<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&RebuildSummary) + Send + Sync + 'static,
+    {
+        self.on_rebuild = Some(Some(RebuildHook(Arc::new(hook))));
+        self
+    }
+}
+
+/// Outcome of a single rebuild in watch mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebuildSummary {
+    /// rebuild count, starting at 1 for the initial build
+    pub rebuild: usize,
+    /// number of files written
+    pub files: usize,
+    /// time taken
+    pub duration: Duration,
+    /// set when the rebuild failed
+    pub error: Option<String>,
+}
+
+impl fmt::Display for RebuildSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            Some(error) => write!(
+                f,
+                "[cache-buster] rebuild #{} failed: {}",
+                self.rebuild, error
+            ),
+            None => write!(
+                f,
+                "[cache-buster] rebuild #{}: wrote {} files in {}ms",
+                self.rebuild,
+                self.files,
+                self.duration.as_millis()
+            ),
+        }
+    }
+}
+
+impl Buster {
+    /// Process files, then keep reprocessing them whenever source changes.
+    ///
+    /// Failed rebuilds are reported and watching continues. So are errors
+    /// scanning source, say when it's removed altogether, which is scanned
+    /// again on the next tick. Returns only when
+    /// [stop][WatchOptionsBuilder::stop] is set.
+    pub fn watch(&self, options: &WatchOptions) -> Result<(), CacheBusterError> {
+        let mut rebuild = 0;
+        let mut snapshot = None;
+        loop {
+            if let Some(stop) = &options.stop {
+                if stop.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+            }
+
+            let current = self.snapshot().map_err(|e| e.to_string());
+            if snapshot.as_ref() != Some(&current) {
+                rebuild += 1;
+                let summary = match &current {
+                    Ok(_) => self.rebuild(rebuild),
+                    Err(e) => RebuildSummary {
+                        rebuild,
+                        files: 0,
+                        duration: Duration::ZERO,
+                        error: Some(format!("can't scan source: {}", e)),
+                    },
+                };
+                if !options.quiet {
+                    eprintln!("{}", summary);
+                }
+                if let Some(hook) = &options.on_rebuild {
+                    (hook.0)(&summary);
+                }
+                snapshot = Some(current);
+            }
+            thread::sleep(options.interval);
+        }
+    }
+
+    // run a single rebuild, turning errors and panics into a summary
+    fn rebuild(&self, rebuild: usize) -> RebuildSummary {
+        let start = Instant::now();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| self.run()));
+        let (files, error) = match res {
+            Ok(Ok(report)) => (report.hashed + report.copied, None),
+            Ok(Err(e)) => (0, Some(e.to_string())),
+            Err(panic) => {
+                let msg = panic
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| panic.downcast_ref::<&str>().map(|msg| msg.to_string()))
+                    .unwrap_or_else(|| "panicked".into());
+                (0, Some(msg))
+            }
+        };
+        RebuildSummary {
+            rebuild,
+            files,
+            duration: start.elapsed(),
+            error,
+        }
+    }
+}